@@ -6461,6 +6461,7 @@ impl AccountsDb {
                 epoch_schedule,
                 epoch,
                 store_detailed_debug_info_on_failure: false,
+                progress: None,
             },
             expected_capitalization,
         );
@@ -6725,6 +6726,7 @@ impl AccountsDb {
                 zero_lamport_accounts: kind.zero_lamport_accounts(),
                 dir_for_temp_cache_files: transient_accounts_hash_cache_path,
                 active_stats: &self.active_stats,
+                progress: config.progress,
             };
 
             // get raw data by scanning
@@ -6805,6 +6807,7 @@ impl AccountsDb {
             epoch_schedule: config.epoch_schedule,
             epoch: config.epoch,
             store_detailed_debug_info_on_failure: config.store_detailed_debug_info,
+            progress: None,
         };
         let hash_mismatch_is_error = !config.ignore_mismatch;
 
@@ -3,7 +3,8 @@ use {
     crate::{
         accounts_file::AccountsFileError,
         accounts_hash::{
-            AccountHash, CalcAccountsHashConfig, CalculateHashIntermediate, HashStats,
+            AccountHash, CalcAccountsHashConfig, CalcAccountsHashPhase, CalculateHashIntermediate,
+            HashStats, ProgressReporter,
         },
         active_stats::ActiveStatItem,
         cache_hash_data::{CacheHashData, CacheHashDataFileReference},
@@ -318,10 +319,15 @@ impl AccountsDb {
         // deletes the old files that will not be used before creating new ones
         cache_hash_data.delete_old_cache_files();
 
+        let progress_reporter = ProgressReporter::new(
+            config.progress,
+            CalcAccountsHashPhase::Scan,
+            cache_files.len(),
+        );
         cache_files
             .into_par_iter()
             .map(|chunk| {
-                match chunk {
+                let cache_file = match chunk {
                     ScanAccountStorageResult::CacheFileAlreadyExists(file) => Some(file),
                     ScanAccountStorageResult::CacheFileNeedsToBeCreated((
                         file_name,
@@ -368,7 +374,11 @@ impl AccountsDb {
                             })
                             .flatten()
                     }
+                };
+                if let Some(progress_reporter) = &progress_reporter {
+                    progress_reporter.processed_one();
                 }
+                cache_file
             })
             .filter_map(|x| x)
             .collect()
@@ -4,7 +4,7 @@ use {
     crate::{
         account_info::StoredSize,
         accounts_file::AccountsFileProvider,
-        accounts_hash::MERKLE_FANOUT,
+        accounts_hash::{CalcAccountsHashPhase, MERKLE_FANOUT},
         accounts_index::{tests::*, AccountSecondaryIndexesIncludeExclude},
         ancient_append_vecs,
         append_vec::{
@@ -451,6 +451,51 @@ define_accounts_db_test!(
     }
 );
 
+define_accounts_db_test!(test_accountsdb_calculate_accounts_hash_progress, |db| {
+    sample_storages_and_account_in_slot(1, &db);
+    let (storages, _raw_expected) = sample_storages_and_account_in_slot(2, &db);
+    assert!(storages.len() > 1);
+
+    let reports = Mutex::new(Vec::new());
+    let progress = |phase: CalcAccountsHashPhase, processed: usize, total: usize| {
+        reports.lock().unwrap().push((phase, processed, total));
+    };
+    db.calculate_accounts_hash(
+        &CalcAccountsHashConfig {
+            progress: Some(&progress),
+            ..CalcAccountsHashConfig::default()
+        },
+        &get_storage_refs(&storages),
+        HashStats::default(),
+    );
+
+    let reports = reports.into_inner().unwrap();
+    assert!(reports
+        .iter()
+        .all(|(_phase, processed, total)| processed <= total));
+    // the storage scan reports every chunk of slots, before the de-dup reports every bin
+    let (scan_reports, dedup_reports): (Vec<_>, Vec<_>) = reports
+        .iter()
+        .partition(|(phase, ..)| *phase == CalcAccountsHashPhase::Scan);
+    let &(_, num_chunks, _) = scan_reports.last().unwrap();
+    assert!(num_chunks > 0);
+    assert!(scan_reports.contains(&&(CalcAccountsHashPhase::Scan, num_chunks, num_chunks)));
+    let total_bins = db.hash_calculation_pubkey_bins;
+    assert!(dedup_reports
+        .iter()
+        .all(|(_phase, _processed, total)| *total == total_bins));
+    assert!(dedup_reports.contains(&&(CalcAccountsHashPhase::DeDup, total_bins, total_bins)));
+    let last_scan_index = reports
+        .iter()
+        .rposition(|(phase, ..)| *phase == CalcAccountsHashPhase::Scan)
+        .unwrap();
+    let first_dedup_index = reports
+        .iter()
+        .position(|(phase, ..)| *phase == CalcAccountsHashPhase::DeDup)
+        .unwrap();
+    assert!(last_scan_index < first_dedup_index);
+});
+
 fn sample_storage() -> (Vec<Arc<AccountStorageEntry>>, usize, Slot) {
     let (_temp_dirs, paths) = get_temp_accounts_paths(1).unwrap();
     let slot_expected: Slot = 0;
@@ -2136,6 +2181,7 @@ impl CalcAccountsHashConfig<'_> {
             epoch_schedule: &EPOCH_SCHEDULE,
             epoch: 0,
             store_detailed_debug_info_on_failure: false,
+            progress: None,
         }
     }
 }
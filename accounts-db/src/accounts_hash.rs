@@ -86,8 +86,60 @@ impl AccountHashesFile {
     }
 }
 
+/// The phases of an accounts hash calculation that report their progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcAccountsHashPhase {
+    /// Scanning the storages, in chunks of slots
+    Scan,
+    /// De-duplicating the scanned accounts, in pubkey bins
+    DeDup,
+}
+
+/// Called with the phase, how many of its chunks or bins have been processed so far, and their
+/// total, as an accounts hash calculation progresses
+pub type CalcAccountsHashProgress =
+    dyn Fn(CalcAccountsHashPhase, /*processed*/ usize, /*total*/ usize) + Sync;
+
+/// How many times, at most, progress is reported during each phase of an accounts hash
+/// calculation
+const NUM_PROGRESS_REPORTS: usize = 10;
+
+/// Counts the items processed in a phase of an accounts hash calculation, and reports progress
+/// about every tenth of the way, and once all are processed
+pub(crate) struct ProgressReporter<'a> {
+    progress: &'a CalcAccountsHashProgress,
+    phase: CalcAccountsHashPhase,
+    total: usize,
+    interval: usize,
+    processed: AtomicUsize,
+}
+
+impl<'a> ProgressReporter<'a> {
+    /// Returns None if there is no `progress` to report to
+    pub(crate) fn new(
+        progress: Option<&'a CalcAccountsHashProgress>,
+        phase: CalcAccountsHashPhase,
+        total: usize,
+    ) -> Option<Self> {
+        progress.map(|progress| Self {
+            progress,
+            phase,
+            total,
+            interval: (total / NUM_PROGRESS_REPORTS).max(1),
+            processed: AtomicUsize::new(0),
+        })
+    }
+
+    /// Counts one more item as processed
+    pub(crate) fn processed_one(&self) {
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+        if processed % self.interval == 0 || processed == self.total {
+            (self.progress)(self.phase, processed, self.total);
+        }
+    }
+}
+
 /// parameters to calculate accounts hash
-#[derive(Debug)]
 pub struct CalcAccountsHashConfig<'a> {
     /// true to use a thread pool dedicated to bg operations
     pub use_bg_thread_pool: bool,
@@ -100,6 +152,24 @@ pub struct CalcAccountsHashConfig<'a> {
     pub store_detailed_debug_info_on_failure: bool,
     /// used to calculate the number of slots in the given epoch
     pub epoch: Epoch,
+    /// called periodically with the calculation's progress
+    pub progress: Option<&'a CalcAccountsHashProgress>,
+}
+
+impl std::fmt::Debug for CalcAccountsHashConfig<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CalcAccountsHashConfig")
+            .field("use_bg_thread_pool", &self.use_bg_thread_pool)
+            .field("ancestors", &self.ancestors)
+            .field("epoch_schedule", &self.epoch_schedule)
+            .field(
+                "store_detailed_debug_info_on_failure",
+                &self.store_detailed_debug_info_on_failure,
+            )
+            .field("epoch", &self.epoch)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
 }
 
 // smallest, 3 quartiles, largest, average
@@ -467,6 +537,8 @@ pub struct AccountsHasher<'a> {
     /// The directory where temporary cache files are put
     pub dir_for_temp_cache_files: PathBuf,
     pub(crate) active_stats: &'a ActiveStats,
+    /// Called periodically with how many bins have been de-duplicated
+    pub progress: Option<&'a CalcAccountsHashProgress>,
 }
 
 /// Pointer to a specific item in chunked accounts hash slices.
@@ -812,6 +884,8 @@ impl AccountsHasher<'_> {
             lamports_sum: u64,
         }
 
+        let progress_reporter =
+            ProgressReporter::new(self.progress, CalcAccountsHashPhase::DeDup, max_bin);
         let mut zeros = Measure::start("eliminate zeros");
         let DedupResult {
             hashes_files: hashes,
@@ -829,6 +903,10 @@ impl AccountsHasher<'_> {
                     .expect("summing capitalization cannot overflow");
                 accum.hashes_count += hashes_file.count();
                 accum.hashes_files.push(hashes_file);
+
+                if let Some(progress_reporter) = &progress_reporter {
+                    progress_reporter.processed_one();
+                }
                 accum
             })
             .reduce(
@@ -1356,6 +1434,7 @@ mod tests {
                 zero_lamport_accounts: ZeroLamportAccounts::Excluded,
                 dir_for_temp_cache_files,
                 active_stats: &ACTIVE_STATS,
+                progress: None,
             }
         }
     }
@@ -3,114 +3,479 @@
 use {
     crate::snapshot_packager_service::PendingSnapshotPackages,
     crossbeam_channel::{Receiver, Sender},
+    rand::{rngs::StdRng, Rng, SeedableRng},
+    rayon::prelude::*,
     solana_accounts_db::{
-        accounts_db::CalcAccountsHashKind,
+        accounts::Accounts,
+        accounts_db::{AccountStorageEntry, AccountsDb, CalcAccountsHashKind, DuplicatesLtHash},
         accounts_hash::{
-            AccountsHash, CalcAccountsHashConfig, HashStats, IncrementalAccountsHash,
-            MerkleOrLatticeAccountsHash,
+            AccountsHash, AccountsLtHash, CalcAccountsHashConfig, CalcAccountsHashPhase, HashStats,
+            IncrementalAccountsHash, MerkleOrLatticeAccountsHash,
         },
+        ancestors::Ancestors,
+        pubkey_bins::PubkeyBinCalculator24,
         sorted_storages::SortedStorages,
     },
     solana_clock::{Slot, DEFAULT_MS_PER_SLOT},
+    solana_epoch_schedule::EpochSchedule,
+    solana_hash::Hash,
     solana_measure::measure_us,
+    solana_perf::thread::{is_renice_allowed, renice_this_thread},
+    solana_pubkey::Pubkey,
     solana_runtime::{
         serde_snapshot::BankIncrementalSnapshotPersistence,
-        snapshot_config::SnapshotConfig,
+        snapshot_archive_info::SnapshotArchiveInfoGetter,
+        snapshot_config::{OnMissingIncrementalBase, SnapshotConfig},
         snapshot_controller::SnapshotController,
+        snapshot_hash::SnapshotHash,
         snapshot_package::{
             self, AccountsHashAlgorithm, AccountsPackage, AccountsPackageKind, SnapshotKind,
             SnapshotPackage,
         },
         snapshot_utils,
     },
+    solana_time_utils::timestamp,
     std::{
-        io,
+        collections::{hash_map::Entry, HashMap, HashSet},
+        fmt,
+        fs::{self, File},
+        io::{self, BufWriter, Write},
+        mem,
+        path::{Path, PathBuf},
         sync::{
-            atomic::{AtomicBool, Ordering},
+            atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
             Arc, Mutex,
         },
         thread::{self, Builder, JoinHandle},
-        time::Duration,
+        time::{Duration, Instant},
     },
+    thiserror::Error,
 };
 
+/// Observer invoked with each accounts package, and its accounts hash, once it has been handled
+pub type AccountsPackageObserver =
+    Arc<dyn Fn(&AccountsPackage, &MerkleOrLatticeAccountsHash) + Send + Sync>;
+
+/// Selects the accounts hash algorithm for each accounts package, overriding the one it was
+/// created with
+pub type AccountsHashAlgorithmSelector =
+    Arc<dyn Fn(&AccountsPackage) -> AccountsHashAlgorithm + Send + Sync>;
+
+/// Configuration for the accounts hash verifier's thread
+#[derive(Debug, Default, Clone)]
+pub struct AccountsHashVerifierThreadConfig {
+    /// Appended to the thread's name, to tell multiple verifiers apart
+    ///
+    /// Linux truncates thread names to 15 bytes, so a suffixed thread is named `solAcctHash`, and
+    /// only the first [`MAX_THREAD_NAME_SUFFIX_LEN`] bytes of the suffix are used.
+    pub name_suffix: Option<String>,
+    /// Added to the thread's nice value; negative values increase its priority
+    ///
+    /// An adjustment that is not allowed (see [`is_renice_allowed`]) is logged and ignored.
+    pub niceness_adj: i8,
+}
+
+/// An incremental accounts hash's capitalization, plus its base's less the base lamports of the
+/// accounts modified since the base, does not equal the accounts package's expected
+/// capitalization
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error(
+    "incremental capitalization mismatch at slot {slot}: base capitalization \
+     {base_capitalization}, less base lamports of modified accounts \
+     {modified_accounts_base_lamports}, plus incremental capitalization \
+     {incremental_capitalization} does not equal expected capitalization \
+     {expected_capitalization}"
+)]
+pub struct IncrementalCapitalizationMismatch {
+    pub slot: Slot,
+    pub base_capitalization: u64,
+    pub modified_accounts_base_lamports: u64,
+    pub incremental_capitalization: u64,
+    pub expected_capitalization: u64,
+}
+
+/// The lamports summed across an accounts package's storages do not equal the accounts package's
+/// expected capitalization
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error(
+    "capitalization mismatch at slot {slot}: calculated capitalization {capitalization} does not \
+     equal expected capitalization {expected_capitalization}"
+)]
+pub struct CapitalizationMismatch {
+    pub slot: Slot,
+    pub capitalization: u64,
+    pub expected_capitalization: u64,
+}
+
+/// A full accounts hash does not equal the hash of the full snapshot archive for the same slot
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error(
+    "full snapshot archive hash mismatch at slot {slot}: archive {archive_path:?} has hash \
+     {archive_hash:?}, but the calculated accounts hash is {accounts_hash:?}"
+)]
+pub struct ArchiveAccountsHashMismatch {
+    pub slot: Slot,
+    pub archive_path: PathBuf,
+    pub archive_hash: SnapshotHash,
+    pub accounts_hash: AccountsHash,
+}
+
+/// Errors from verifying a slot on demand, see [`AccountsHashVerifier::verify_slot`]
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AccountsHashVerifierError {
+    #[error("no account storages for slot {0} or older")]
+    NoStorages(Slot),
+
+    #[error("no accounts hash for incremental snapshot base slot {0}")]
+    MissingIncrementalBase(Slot),
+}
+
+/// What becomes of an accounts package when the next one to handle is selected
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum AccountsPackageDisposition {
+    Handled,
+    ReEnqueued,
+    Dropped,
+}
+
+/// What became of an accounts package once it was processed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProcessOutcome {
+    /// The merkle-based accounts hash was calculated, and a snapshot package was submitted
+    Packaged,
+    /// The accounts package was not for a snapshot, so it was skipped
+    SkippedNonSnapshot,
+    /// The merkle-based accounts hash calculation was skipped, since the lattice-based accounts
+    /// hash is used instead, and a snapshot package was submitted
+    SkippedLattice,
+    /// The accounts package was dropped, per `SnapshotConfig::on_missing_incremental_base`
+    DroppedMissingIncrementalBase,
+    /// Only the capitalization was verified, per
+    /// `SnapshotConfig::capitalization_only_verification`, so no snapshot package was submitted
+    CapitalizationVerified,
+}
+
+/// How long the steps of processing an accounts package took
+///
+/// Reported with the sampled datapoint, so steps that were not taken are left out.
+#[derive(Debug, Default)]
+struct ProcessTimings {
+    calculate_capitalization_us: Option<u64>,
+    calculate_hash_us: Option<u64>,
+    calculate_incremental_accounts_hash_us: Option<u64>,
+    submit_lock_us: Option<u64>,
+}
+
+impl ProcessOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Packaged => "packaged",
+            Self::SkippedNonSnapshot => "skipped_non_snapshot",
+            Self::SkippedLattice => "skipped_lattice",
+            Self::DroppedMissingIncrementalBase => "dropped_missing_incremental_base",
+            Self::CapitalizationVerified => "capitalization_verified",
+        }
+    }
+}
+
+/// A version of an account, from scanning storages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AccountVersion {
+    /// The slot of the storage the version is in
+    slot: Slot,
+    /// The offset of the version in its storage
+    offset: usize,
+    lamports: u64,
+}
+
+/// The latest version of each account scanned, and the older versions they replaced
+#[derive(Debug, Default)]
+struct LatestAccounts {
+    latest: HashMap<Pubkey, AccountVersion>,
+    older: Vec<AccountVersion>,
+}
+
+impl LatestAccounts {
+    /// Adds `version` of the account with `pubkey`
+    ///
+    /// Versions from newer slots replace older ones.  Within a slot, versions are added in storage
+    /// order, so the one added last replaces the others.
+    fn add(&mut self, pubkey: Pubkey, version: AccountVersion) {
+        match self.latest.entry(pubkey) {
+            Entry::Vacant(entry) => {
+                entry.insert(version);
+            }
+            Entry::Occupied(mut entry) => {
+                if version.slot >= entry.get().slot {
+                    self.older.push(entry.insert(version));
+                } else {
+                    self.older.push(version);
+                }
+            }
+        }
+    }
+
+    /// Merges the latest accounts scanned from storages of different slots
+    fn merge(mut self, mut other: Self) -> Self {
+        if self.latest.len() < other.latest.len() {
+            mem::swap(&mut self, &mut other);
+        }
+        self.older.append(&mut other.older);
+        for (pubkey, version) in other.latest {
+            self.add(pubkey, version);
+        }
+        self
+    }
+}
+
+/// Maximum number of bytes of [`AccountsHashVerifierThreadConfig::name_suffix`] used in the
+/// verifier thread's name
+pub const MAX_THREAD_NAME_SUFFIX_LEN: usize = 4;
+
+/// Sentinel for when no slot has been prioritized with [`AccountsHashVerifier::prioritize_slot`]
+const NO_PREFERRED_SLOT: Slot = Slot::MAX;
+
+/// Number of pubkey bins to split storage scans for the latest version of each account into
+const LATEST_ACCOUNTS_SCAN_BINS: usize = 256;
+
+/// Maximum number of accounts to track at once when scanning storages for the latest version of
+/// each account; more accounts are scanned in multiple passes, each over a range of pubkey bins
+const MAX_LATEST_ACCOUNTS_PER_SCAN_PASS: usize = 4_000_000;
+
+/// Number of pubkey bins to sum lamports over when dumping a capitalization mismatch
+const CAPITALIZATION_MISMATCH_DUMP_BINS: usize = 256;
+
 pub struct AccountsHashVerifier {
     t_accounts_hash_verifier: JoinHandle<()>,
+    queue_depth: Arc<AtomicUsize>,
+    preferred_slot: Arc<AtomicU64>,
+    /// Timestamp, in milliseconds, of when the thread last made progress
+    last_progress: Arc<AtomicU64>,
 }
 
 impl AccountsHashVerifier {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         accounts_package_sender: Sender<AccountsPackage>,
         accounts_package_receiver: Receiver<AccountsPackage>,
         pending_snapshot_packages: Arc<Mutex<PendingSnapshotPackages>>,
         exit: Arc<AtomicBool>,
         snapshot_controller: Arc<SnapshotController>,
+        accounts_package_observer: Option<AccountsPackageObserver>,
+        accounts_hash_sender: Option<Sender<(Slot, AccountsHash, /*capitalization*/ u64)>>,
+        accounts_hash_algorithm_selector: Option<AccountsHashAlgorithmSelector>,
+        thread_config: AccountsHashVerifierThreadConfig,
     ) -> Self {
-        // If there are no accounts packages to process, limit how often we re-check
-        const LOOP_LIMITER: Duration = Duration::from_millis(DEFAULT_MS_PER_SLOT);
+        // If there are no accounts packages to process, limit how often we re-check.  Re-check
+        // quickly right after handling a package, and back off the longer the channel stays empty.
+        const MIN_LOOP_LIMITER: Duration = Duration::from_millis(10);
+        const MAX_LOOP_LIMITER: Duration = Duration::from_millis(DEFAULT_MS_PER_SLOT);
+        let queue_depth = Arc::new(AtomicUsize::new(0));
+        let queue_depth_clone = queue_depth.clone();
+        let preferred_slot = Arc::new(AtomicU64::new(NO_PREFERRED_SLOT));
+        let preferred_slot_clone = preferred_slot.clone();
+        let last_progress = Arc::new(AtomicU64::new(timestamp()));
+        let last_progress_clone = last_progress.clone();
+        let thread_name = Self::thread_name(thread_config.name_suffix.as_deref());
+        let niceness_adj = if is_renice_allowed(thread_config.niceness_adj) {
+            thread_config.niceness_adj
+        } else {
+            warn!(
+                "AccountsHashVerifier niceness adjustment {} is not allowed, ignoring it",
+                thread_config.niceness_adj,
+            );
+            0
+        };
         let t_accounts_hash_verifier = Builder::new()
-            .name("solAcctHashVer".to_string())
+            .name(thread_name)
             .spawn(move || {
                 info!("AccountsHashVerifier has started");
+                if let Err(err) = renice_this_thread(niceness_adj) {
+                    warn!("AccountsHashVerifier {err}");
+                }
+                let mut loop_limiter = LoopLimiter::new(MIN_LOOP_LIMITER, MAX_LOOP_LIMITER);
+                let round_robin_forks = snapshot_controller
+                    .snapshot_config()
+                    .round_robin_full_snapshots_across_forks;
+                let mut next_fork_index = 0;
+                let mut re_enqueue_jitter = snapshot_controller
+                    .snapshot_config()
+                    .max_re_enqueue_jitter
+                    .map(|max| ReEnqueueJitter::new(max, StdRng::from_entropy()));
+                let mut metrics_sampler =
+                    MetricsSampler::new(snapshot_controller.snapshot_config().metrics_sample_rate);
                 loop {
                     if exit.load(Ordering::Relaxed) {
                         break;
                     }
 
+                    last_progress_clone.store(timestamp(), Ordering::Relaxed);
+                    Self::update_queue_depth(&queue_depth_clone, &accounts_package_receiver);
+
                     let Some((
                         accounts_package,
                         num_outstanding_accounts_packages,
                         num_re_enqueued_accounts_packages,
+                        selection_us,
                     )) = Self::get_next_accounts_package(
                         &accounts_package_sender,
                         &accounts_package_receiver,
+                        Self::load_preferred_slot(&preferred_slot_clone),
+                        round_robin_forks.then_some(&mut next_fork_index),
+                        re_enqueue_jitter.as_mut(),
                     )
                     else {
-                        std::thread::sleep(LOOP_LIMITER);
+                        std::thread::sleep(loop_limiter.next_sleep());
                         continue;
                     };
+                    loop_limiter.reset();
+                    // The preferred slot has been handled, unless it was changed meanwhile
+                    let _ = preferred_slot_clone.compare_exchange(
+                        accounts_package.slot,
+                        NO_PREFERRED_SLOT,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    );
                     info!("handling accounts package: {accounts_package:?}");
                     let enqueued_time = accounts_package.enqueued.elapsed();
+                    let correlation_id = accounts_package.correlation_id;
 
-                    let snapshot_config = snapshot_controller.snapshot_config();
+                    let mut timings = ProcessTimings::default();
                     let (result, handling_time_us) = measure_us!(Self::process_accounts_package(
                         accounts_package,
                         &pending_snapshot_packages,
-                        snapshot_config,
+                        &snapshot_controller,
+                        accounts_package_observer.as_ref(),
+                        accounts_hash_sender.as_ref(),
+                        accounts_hash_algorithm_selector.as_ref(),
+                        &mut timings,
                     ));
+                    last_progress_clone.store(timestamp(), Ordering::Relaxed);
+
+                    if metrics_sampler.should_emit(result.is_err()) {
+                        datapoint_info!(
+                            "accounts_hash_verifier",
+                            (
+                                "num_outstanding_accounts_packages",
+                                num_outstanding_accounts_packages,
+                                i64
+                            ),
+                            (
+                                "num_re_enqueued_accounts_packages",
+                                num_re_enqueued_accounts_packages,
+                                i64
+                            ),
+                            ("selection_us", selection_us, i64),
+                            ("enqueued_time_us", enqueued_time.as_micros(), i64),
+                            ("handling_time_us", handling_time_us, i64),
+                            (
+                                "calculate_capitalization_us",
+                                timings.calculate_capitalization_us.map(|us| us as i64),
+                                Option<i64>
+                            ),
+                            (
+                                "calculate_hash",
+                                timings.calculate_hash_us.map(|us| us as i64),
+                                Option<i64>
+                            ),
+                            (
+                                "calculate_incremental_accounts_hash_us",
+                                timings
+                                    .calculate_incremental_accounts_hash_us
+                                    .map(|us| us as i64),
+                                Option<i64>
+                            ),
+                            (
+                                "submit_lock_us",
+                                timings.submit_lock_us.map(|us| us as i64),
+                                Option<i64>
+                            ),
+                            (
+                                "outcome",
+                                result.as_ref().map_or("error", ProcessOutcome::as_str),
+                                String
+                            ),
+                            (
+                                "correlation_id",
+                                correlation_id.map(|id| id as i64),
+                                Option<i64>
+                            ),
+                        );
+                    }
+
                     if let Err(err) = result {
                         error!(
-                            "Stopping AccountsHashVerifier! Fatal error while processing accounts \
-                             package: {err}"
+                            "Stopping AccountsHashVerifier! Fatal error while processing \
+                             accounts package: {err}"
                         );
                         exit.store(true, Ordering::Relaxed);
                         break;
                     }
-
-                    datapoint_info!(
-                        "accounts_hash_verifier",
-                        (
-                            "num_outstanding_accounts_packages",
-                            num_outstanding_accounts_packages,
-                            i64
-                        ),
-                        (
-                            "num_re_enqueued_accounts_packages",
-                            num_re_enqueued_accounts_packages,
-                            i64
-                        ),
-                        ("enqueued_time_us", enqueued_time.as_micros(), i64),
-                        ("handling_time_us", handling_time_us, i64),
-                    );
                 }
                 info!("AccountsHashVerifier has stopped");
             })
             .unwrap();
         Self {
             t_accounts_hash_verifier,
+            queue_depth,
+            preferred_slot,
+            last_progress,
         }
     }
 
+    /// Returns whether the thread has made progress within the last `max_stall`
+    ///
+    /// Progress is recorded every loop iteration, including idle ones, and after every accounts
+    /// package handled.  So a thread that stopped, or is stuck handling an accounts package, is
+    /// reported unhealthy once `max_stall` has passed.
+    pub fn is_healthy(&self, max_stall: Duration) -> bool {
+        let last_progress = self.last_progress.load(Ordering::Relaxed);
+        let stall = timestamp().saturating_sub(last_progress);
+        u128::from(stall) <= max_stall.as_millis()
+    }
+
+    /// Handles the accounts package for `slot` next, once it is in the channel
+    ///
+    /// Intended for debugging forks on demand.  The preference is cleared once that accounts
+    /// package has been handled.
+    pub fn prioritize_slot(&self, slot: Slot) {
+        self.preferred_slot.store(slot, Ordering::Relaxed);
+    }
+
+    fn load_preferred_slot(preferred_slot: &AtomicU64) -> Option<Slot> {
+        let preferred_slot = preferred_slot.load(Ordering::Relaxed);
+        (preferred_slot != NO_PREFERRED_SLOT).then_some(preferred_slot)
+    }
+
+    /// Returns the number of accounts packages waiting in the channel
+    ///
+    /// This is updated every loop iteration, so external exporters may poll it at any time.
+    pub fn queue_depth(&self) -> Arc<AtomicUsize> {
+        self.queue_depth.clone()
+    }
+
+    /// Returns the verifier thread's name, with at most [`MAX_THREAD_NAME_SUFFIX_LEN`] bytes of
+    /// `name_suffix`, so it fits in the 15 bytes Linux keeps
+    fn thread_name(name_suffix: Option<&str>) -> String {
+        let Some(name_suffix) = name_suffix else {
+            return "solAcctHashVer".to_string();
+        };
+        let name_suffix_len = name_suffix
+            .char_indices()
+            .map(|(index, char)| index + char.len_utf8())
+            .take_while(|&len| len <= MAX_THREAD_NAME_SUFFIX_LEN)
+            .last()
+            .unwrap_or(0);
+        format!("solAcctHash{}", &name_suffix[..name_suffix_len])
+    }
+
+    fn update_queue_depth(
+        queue_depth: &AtomicUsize,
+        accounts_package_receiver: &Receiver<AccountsPackage>,
+    ) {
+        queue_depth.store(accounts_package_receiver.len(), Ordering::Relaxed);
+    }
+
     /// Get the next accounts package to handle
     ///
     /// Look through the accounts package channel to find the highest priority one to handle next.
@@ -118,20 +483,69 @@ impl AccountsHashVerifier {
     /// highest priority one.  Unhandled accounts packages with slots GREATER-THAN the handled one
     /// will be re-enqueued.  The remaining will be dropped.
     ///
-    /// Also return the number of accounts packages initially in the channel, and the number of
-    /// ones re-enqueued.
+    /// Re-enqueued accounts packages are sent in priority order, lowest first.  This ensures the
+    /// next call sees them in a stable order, independent of the order they were received in.
+    ///
+    /// If there is an accounts package for `preferred_slot`, it is handled next instead,
+    /// regardless of priority.
+    ///
+    /// If `re_enqueue_jitter` is set, a random delay is slept before re-enqueuing each accounts
+    /// package, to spread out when they reappear.
+    ///
+    /// Also return the number of accounts packages initially in the channel, the number of ones
+    /// re-enqueued, and how long selecting the highest priority ones took, which is zero if there
+    /// was no need to select.
+    // Slots may be anywhere in their range, including zero, so selection must not over/underflow
+    #[deny(clippy::arithmetic_side_effects)]
     fn get_next_accounts_package(
         accounts_package_sender: &Sender<AccountsPackage>,
         accounts_package_receiver: &Receiver<AccountsPackage>,
+        preferred_slot: Option<Slot>,
+        next_fork_index: Option<&mut usize>,
+        re_enqueue_jitter: Option<&mut ReEnqueueJitter>,
     ) -> Option<(
         AccountsPackage,
         /*num outstanding accounts packages*/ usize,
         /*num re-enqueued accounts packages*/ usize,
+        /*selection_us*/ u64,
     )> {
         let mut accounts_packages: Vec<_> = accounts_package_receiver.try_iter().collect();
         let accounts_packages_len = accounts_packages.len();
         debug!("outstanding accounts packages ({accounts_packages_len}): {accounts_packages:?}");
 
+        let preferred_index = preferred_slot.and_then(|preferred_slot| {
+            accounts_packages
+                .iter()
+                .enumerate()
+                .filter(|(_, accounts_package)| accounts_package.slot == preferred_slot)
+                .max_by(|(_, a), (_, b)| snapshot_package::cmp_accounts_packages_by_priority(a, b))
+                .map(|(index, _)| index)
+        });
+        if let Some(preferred_index) = preferred_index {
+            let accounts_package = accounts_packages.swap_remove(preferred_index);
+            // Priority order already handles full snapshots before incrementals, but a preferred
+            // incremental could otherwise jump ahead of its base.
+            let accounts_package =
+                Self::base_full_snapshot_first(&mut accounts_packages, accounts_package);
+            info!(
+                "handling preferred accounts package for slot {}",
+                accounts_package.slot
+            );
+            Self::log_accounts_package_dispositions(&accounts_package, &accounts_packages);
+            let num_re_enqueued_accounts_packages = Self::re_enqueue_accounts_packages(
+                accounts_package_sender,
+                accounts_packages,
+                accounts_package.slot,
+                re_enqueue_jitter,
+            );
+            return Some((
+                accounts_package,
+                accounts_packages_len,
+                num_re_enqueued_accounts_packages,
+                0,
+            ));
+        }
+
         // NOTE: This code to select the next request is mirrored in AccountsBackgroundService.
         // Please ensure they stay in sync.
         match accounts_packages_len {
@@ -139,7 +553,8 @@ impl AccountsHashVerifier {
             1 => {
                 // SAFETY: We know the len is 1, so `pop` will return `Some`
                 let accounts_package = accounts_packages.pop().unwrap();
-                Some((accounts_package, 1, 0))
+                Self::log_accounts_package_dispositions(&accounts_package, &accounts_packages);
+                Some((accounts_package, 1, 0, 0))
             }
             _ => {
                 let num_eah_packages = accounts_packages
@@ -157,10 +572,11 @@ impl AccountsHashVerifier {
                 // Get the two highest priority requests, `y` and `z`.
                 // By asking for the second-to-last element to be in its final sorted position, we
                 // also ensure that the last element is also sorted.
-                let (_, y, z) = accounts_packages.select_nth_unstable_by(
-                    accounts_packages_len - 2,
-                    snapshot_package::cmp_accounts_packages_by_priority,
-                );
+                let ((_, y, z), selection_us) = measure_us!(accounts_packages
+                    .select_nth_unstable_by(
+                        accounts_packages_len.saturating_sub(2),
+                        snapshot_package::cmp_accounts_packages_by_priority,
+                    ));
                 assert_eq!(z.len(), 1);
                 let z = z.first().unwrap();
                 let y: &_ = y; // reborrow to remove `mut`
@@ -187,60 +603,291 @@ impl AccountsHashVerifier {
                     // SAFETY: We know the len is > 1, so `pop` will return `Some`
                     accounts_packages.pop().unwrap()
                 };
+                let accounts_package = match next_fork_index {
+                    Some(next_fork_index) => Self::select_fork_round_robin(
+                        &mut accounts_packages,
+                        accounts_package,
+                        next_fork_index,
+                    ),
+                    None => accounts_package,
+                };
 
-                let handled_accounts_package_slot = accounts_package.slot;
-                // re-enqueue any remaining accounts packages for slots GREATER-THAN the accounts package
-                // that will be handled
-                let num_re_enqueued_accounts_packages = accounts_packages
-                    .into_iter()
-                    .filter(|accounts_package| {
-                        accounts_package.slot > handled_accounts_package_slot
-                    })
-                    .map(|accounts_package| {
-                        accounts_package_sender
-                            .try_send(accounts_package)
-                            .expect("re-enqueue accounts package")
-                    })
-                    .count();
+                Self::log_accounts_package_dispositions(&accounts_package, &accounts_packages);
+                let num_re_enqueued_accounts_packages = Self::re_enqueue_accounts_packages(
+                    accounts_package_sender,
+                    accounts_packages,
+                    accounts_package.slot,
+                    re_enqueue_jitter,
+                );
 
                 Some((
                     accounts_package,
                     accounts_packages_len,
                     num_re_enqueued_accounts_packages,
+                    selection_us,
                 ))
             }
         }
     }
 
+    /// If `accounts_package` is an incremental snapshot whose base full snapshot is also pending,
+    /// return the full snapshot instead, so the incremental does not miss its base.  The package
+    /// that is not returned is put back into `accounts_packages`.
+    fn base_full_snapshot_first(
+        accounts_packages: &mut Vec<AccountsPackage>,
+        accounts_package: AccountsPackage,
+    ) -> AccountsPackage {
+        let AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(base_slot)) =
+            accounts_package.package_kind
+        else {
+            return accounts_package;
+        };
+        let base_index = accounts_packages.iter().position(|other| {
+            other.package_kind == AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot)
+                && other.slot == base_slot
+        });
+        let Some(base_index) = base_index else {
+            return accounts_package;
+        };
+        debug!(
+            "handling full snapshot accounts package for slot {base_slot} before its incremental \
+             snapshot accounts package for slot {}",
+            accounts_package.slot,
+        );
+        accounts_packages.push(accounts_package);
+        accounts_packages.swap_remove(base_index)
+    }
+
+    /// If `accounts_package` is a full snapshot, and other full snapshots for the same slot but
+    /// from different forks are pending, pick among them by rotating `next_fork_index`.
+    ///
+    /// Forks are ordered by bank hash so the rotation is stable across calls.  The package that
+    /// is not picked is put back into `accounts_packages`.
+    #[deny(clippy::arithmetic_side_effects)]
+    fn select_fork_round_robin(
+        accounts_packages: &mut Vec<AccountsPackage>,
+        accounts_package: AccountsPackage,
+        next_fork_index: &mut usize,
+    ) -> AccountsPackage {
+        if accounts_package.package_kind
+            != AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot)
+        {
+            return accounts_package;
+        }
+
+        let slot = accounts_package.slot;
+        accounts_packages.push(accounts_package);
+        let mut candidates: Vec<_> = accounts_packages
+            .iter()
+            .enumerate()
+            .filter(|(_, accounts_package)| {
+                accounts_package.package_kind
+                    == AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot)
+                    && accounts_package.slot == slot
+            })
+            .map(|(index, accounts_package)| (Self::bank_hash(accounts_package), index))
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup_by_key(|(bank_hash, _)| *bank_hash);
+
+        // SAFETY: `candidates` includes `accounts_package`, so it is not empty
+        let (_, index) = candidates[next_fork_index.checked_rem(candidates.len()).unwrap()];
+        if candidates.len() > 1 {
+            *next_fork_index = next_fork_index.wrapping_add(1);
+        }
+        accounts_packages.swap_remove(index)
+    }
+
+    /// Returns the bank hash of the accounts package, which identifies its fork
+    fn bank_hash(accounts_package: &AccountsPackage) -> Option<Hash> {
+        accounts_package
+            .snapshot_info
+            .as_ref()
+            .map(|snapshot_info| snapshot_info.bank_fields_to_serialize.hash)
+    }
+
+    /// Should the remaining `accounts_package` be re-enqueued, instead of dropped?
+    ///
+    /// Only accounts packages for slots GREATER-THAN the accounts package that will be handled are
+    /// re-enqueued.
+    #[deny(clippy::arithmetic_side_effects)]
+    fn should_re_enqueue(
+        accounts_package: &AccountsPackage,
+        handled_accounts_package_slot: Slot,
+    ) -> bool {
+        accounts_package.slot > handled_accounts_package_slot
+    }
+
+    /// Classifies the accounts package that will be handled, and the remaining ones, by what
+    /// becomes of them
+    fn classify_accounts_packages(
+        handled_accounts_package: &AccountsPackage,
+        remaining_accounts_packages: &[AccountsPackage],
+    ) -> Vec<(Slot, AccountsPackageKind, AccountsPackageDisposition)> {
+        let remaining_dispositions = remaining_accounts_packages.iter().map(|accounts_package| {
+            let disposition =
+                if Self::should_re_enqueue(accounts_package, handled_accounts_package.slot) {
+                    AccountsPackageDisposition::ReEnqueued
+                } else {
+                    AccountsPackageDisposition::Dropped
+                };
+            (
+                accounts_package.slot,
+                accounts_package.package_kind,
+                disposition,
+            )
+        });
+        std::iter::once((
+            handled_accounts_package.slot,
+            handled_accounts_package.package_kind,
+            AccountsPackageDisposition::Handled,
+        ))
+        .chain(remaining_dispositions)
+        .collect()
+    }
+
+    /// Logs what becomes of each accounts package, so the selection can be reconstructed
+    fn log_accounts_package_dispositions(
+        handled_accounts_package: &AccountsPackage,
+        remaining_accounts_packages: &[AccountsPackage],
+    ) {
+        if !log_enabled!(log::Level::Debug) {
+            return;
+        }
+        for (slot, kind, disposition) in
+            Self::classify_accounts_packages(handled_accounts_package, remaining_accounts_packages)
+        {
+            debug!("accounts package {disposition:?}, slot: {slot}, kind: {kind:?}");
+        }
+    }
+
+    /// Re-enqueue the remaining accounts packages for slots GREATER-THAN the accounts package that
+    /// will be handled, and drop the rest
+    ///
+    /// If `re_enqueue_jitter` is set, a random delay is slept before re-enqueuing each one.
+    ///
+    /// Returns the number of accounts packages successfully re-enqueued.
+    #[deny(clippy::arithmetic_side_effects)]
+    fn re_enqueue_accounts_packages(
+        accounts_package_sender: &Sender<AccountsPackage>,
+        mut accounts_packages: Vec<AccountsPackage>,
+        handled_accounts_package_slot: Slot,
+        mut re_enqueue_jitter: Option<&mut ReEnqueueJitter>,
+    ) -> usize {
+        accounts_packages.retain(|accounts_package| {
+            Self::should_re_enqueue(accounts_package, handled_accounts_package_slot)
+        });
+        // `select_nth_unstable_by` leaves the remaining accounts packages in an arbitrary
+        // order, so sort them to keep the re-enqueued order deterministic
+        accounts_packages.sort_by(snapshot_package::cmp_accounts_packages_by_priority);
+        accounts_packages
+            .into_iter()
+            .map(|accounts_package| {
+                if let Some(re_enqueue_jitter) = re_enqueue_jitter.as_deref_mut() {
+                    thread::sleep(re_enqueue_jitter.next_delay());
+                }
+                accounts_package_sender
+                    .try_send(accounts_package)
+                    .inspect_err(|err| {
+                        // The receiver may be gone during shutdown; drop the package
+                        error!("Failed to re-enqueue accounts package: {err}");
+                    })
+                    .is_ok()
+            })
+            .filter(|&is_re_enqueued| is_re_enqueued)
+            .count()
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn process_accounts_package(
-        accounts_package: AccountsPackage,
+        mut accounts_package: AccountsPackage,
         pending_snapshot_packages: &Mutex<PendingSnapshotPackages>,
-        snapshot_config: &SnapshotConfig,
-    ) -> io::Result<()> {
-        let (merkle_or_lattice_accounts_hash, bank_incremental_snapshot_persistence) =
-            Self::calculate_and_verify_accounts_hash(&accounts_package, snapshot_config)?;
+        snapshot_controller: &SnapshotController,
+        accounts_package_observer: Option<&AccountsPackageObserver>,
+        accounts_hash_sender: Option<&Sender<(Slot, AccountsHash, /*capitalization*/ u64)>>,
+        accounts_hash_algorithm_selector: Option<&AccountsHashAlgorithmSelector>,
+        timings: &mut ProcessTimings,
+    ) -> io::Result<ProcessOutcome> {
+        if accounts_package.package_kind == AccountsPackageKind::EpochAccountsHash {
+            // EAH is removed, so these accounts packages should never be created
+            error!("Skipping unexpected epoch accounts hash package: {accounts_package:?}");
+            return Ok(ProcessOutcome::SkippedNonSnapshot);
+        }
+
+        if let Some(accounts_hash_algorithm_selector) = accounts_hash_algorithm_selector {
+            let accounts_hash_algorithm = accounts_hash_algorithm_selector(&accounts_package);
+            if accounts_hash_algorithm != accounts_package.accounts_hash_algorithm {
+                info!(
+                    "overriding accounts hash algorithm for slot {} from {:?} to {:?}",
+                    accounts_package.slot,
+                    accounts_package.accounts_hash_algorithm,
+                    accounts_hash_algorithm,
+                );
+                accounts_package.accounts_hash_algorithm = accounts_hash_algorithm;
+            }
+        }
+
+        let snapshot_config = snapshot_controller.snapshot_config();
+        if snapshot_config.capitalization_only_verification {
+            // Only the capitalization is checked, so there is no accounts hash to package
+            let (result, calculate_capitalization_us) =
+                measure_us!(Self::verify_capitalization(&accounts_package));
+            timings.calculate_capitalization_us = Some(calculate_capitalization_us);
+            result?;
+            return Ok(ProcessOutcome::CapitalizationVerified);
+        }
+
+        let Some((merkle_or_lattice_accounts_hash, bank_incremental_snapshot_persistence)) =
+            Self::calculate_and_verify_accounts_hash(
+                &accounts_package,
+                snapshot_controller,
+                accounts_hash_sender,
+                timings,
+            )?
+        else {
+            // The accounts package was dropped, so there is nothing to purge or submit
+            return Ok(ProcessOutcome::DroppedMissingIncrementalBase);
+        };
+        let outcome = match merkle_or_lattice_accounts_hash {
+            MerkleOrLatticeAccountsHash::Merkle(_) => ProcessOutcome::Packaged,
+            MerkleOrLatticeAccountsHash::Lattice => ProcessOutcome::SkippedLattice,
+        };
+
+        if let Some(accounts_package_observer) = accounts_package_observer {
+            accounts_package_observer(&accounts_package, &merkle_or_lattice_accounts_hash);
+        }
 
         Self::purge_old_accounts_hashes(&accounts_package, snapshot_config);
 
-        Self::submit_for_packaging(
+        timings.submit_lock_us = Self::submit_for_packaging(
             accounts_package,
             pending_snapshot_packages,
             merkle_or_lattice_accounts_hash,
             bank_incremental_snapshot_persistence,
+            snapshot_config,
         );
 
-        Ok(())
+        Ok(outcome)
     }
 
     /// returns calculated accounts hash
+    ///
+    /// Returns None if the accounts package was dropped instead, per
+    /// `SnapshotConfig::on_missing_incremental_base`.
+    ///
+    /// Full accounts hashes, and their capitalization, are also sent on `accounts_hash_sender`.
     fn calculate_and_verify_accounts_hash(
         accounts_package: &AccountsPackage,
-        snapshot_config: &SnapshotConfig,
-    ) -> io::Result<(
-        MerkleOrLatticeAccountsHash,
-        Option<BankIncrementalSnapshotPersistence>,
-    )> {
+        snapshot_controller: &SnapshotController,
+        accounts_hash_sender: Option<&Sender<(Slot, AccountsHash, /*capitalization*/ u64)>>,
+        timings: &mut ProcessTimings,
+    ) -> io::Result<
+        Option<(
+            MerkleOrLatticeAccountsHash,
+            Option<BankIncrementalSnapshotPersistence>,
+        )>,
+    > {
+        let snapshot_config = snapshot_controller.snapshot_config();
         match accounts_package.accounts_hash_algorithm {
             AccountsHashAlgorithm::Merkle => {
                 debug!(
@@ -253,7 +900,7 @@ impl AccountsHashVerifier {
                     "calculate_and_verify_accounts_hash(): snapshots lt hash is enabled, SKIP \
                      merkle-based accounts hash calculation",
                 );
-                return Ok((MerkleOrLatticeAccountsHash::Lattice, None));
+                return Ok(Some((MerkleOrLatticeAccountsHash::Lattice, None)));
             }
         }
 
@@ -268,8 +915,36 @@ impl AccountsHashVerifier {
         let (accounts_hash_kind, bank_incremental_snapshot_persistence) =
             match accounts_hash_calculation_kind {
                 CalcAccountsHashKind::Full => {
-                    let (accounts_hash, _capitalization) =
-                        Self::_calculate_full_accounts_hash(accounts_package);
+                    let ((accounts_hash, capitalization), calculate_hash_us) = measure_us!(
+                        Self::_calculate_full_accounts_hash(accounts_package, snapshot_config)
+                    );
+                    timings.calculate_hash_us = Some(calculate_hash_us);
+                    if snapshot_config.verify_against_archives {
+                        Self::verify_against_archives(
+                            accounts_package.slot,
+                            accounts_hash,
+                            snapshot_config,
+                        )?;
+                    }
+                    Self::audit_accounts_hash(
+                        snapshot_config,
+                        accounts_package,
+                        accounts_package.accounts_hash_algorithm,
+                        &accounts_hash.0,
+                        capitalization,
+                    );
+                    if let Some(accounts_hash_sender) = accounts_hash_sender {
+                        if let Err(err) = accounts_hash_sender.send((
+                            accounts_package.slot,
+                            accounts_hash,
+                            capitalization,
+                        )) {
+                            warn!(
+                                "Failed to send accounts hash for slot {}: {err}",
+                                accounts_package.slot
+                            );
+                        }
+                    }
                     (accounts_hash.into(), None)
                 }
                 CalcAccountsHashKind::Incremental => {
@@ -279,36 +954,80 @@ impl AccountsHashVerifier {
                         panic!("Calculating incremental accounts hash requires a base slot");
                     };
                     let accounts_db = &accounts_package.accounts.accounts_db;
-                    let Some((base_accounts_hash, base_capitalization)) =
+                    let Some((_base_accounts_hash, base_capitalization)) =
                         accounts_db.get_accounts_hash(base_slot)
                     else {
-                        #[rustfmt::skip]
-                        panic!(
-                            "incremental snapshot requires accounts hash and capitalization from \
-                             the full snapshot it is based on\n\
-                             package: {accounts_package:?}\n\
-                             accounts hashes: {:?}\n\
-                             incremental accounts hashes: {:?}\n\
-                             full snapshot archives: {:?}\n\
-                             bank snapshots: {:?}",
-                            accounts_db.get_accounts_hashes(),
-                            accounts_db.get_incremental_accounts_hashes(),
-                            snapshot_utils::get_full_snapshot_archives(
-                                &snapshot_config.full_snapshot_archives_dir,
-                            ),
-                            snapshot_utils::get_bank_snapshots(&snapshot_config.bank_snapshots_dir),
-                        );
+                        match snapshot_config.on_missing_incremental_base {
+                            OnMissingIncrementalBase::Panic => {
+                                #[rustfmt::skip]
+                                panic!(
+                                    "incremental snapshot requires accounts hash and capitalization from \
+                                     the full snapshot it is based on\n\
+                                     package: {accounts_package:?}\n\
+                                     accounts hashes: {:?}\n\
+                                     incremental accounts hashes: {:?}\n\
+                                     full snapshot archives: {:?}\n\
+                                     bank snapshots: {:?}",
+                                    accounts_db.get_accounts_hashes(),
+                                    accounts_db.get_incremental_accounts_hashes(),
+                                    snapshot_utils::get_full_snapshot_archives(
+                                        &snapshot_config.full_snapshot_archives_dir,
+                                    ),
+                                    snapshot_utils::get_bank_snapshots(&snapshot_config.bank_snapshots_dir),
+                                );
+                            }
+                            OnMissingIncrementalBase::Drop => {
+                                warn!(
+                                    "Dropping incremental snapshot package, the accounts hash \
+                                     for its base slot {base_slot} is missing: \
+                                     {accounts_package:?}",
+                                );
+                                return Ok(None);
+                            }
+                            OnMissingIncrementalBase::RequestFull => {
+                                warn!(
+                                    "Dropping incremental snapshot package and requesting a new \
+                                     full snapshot, the accounts hash for its base slot \
+                                     {base_slot} is missing: {accounts_package:?}",
+                                );
+                                snapshot_controller.request_full_snapshot();
+                                return Ok(None);
+                            }
+                        }
                     };
-                    let (incremental_accounts_hash, incremental_capitalization) =
-                        Self::_calculate_incremental_accounts_hash(accounts_package, base_slot);
+                    let (
+                        (incremental_accounts_hash, incremental_capitalization),
+                        calculate_incremental_accounts_hash_us,
+                    ) = measure_us!(Self::_calculate_incremental_accounts_hash(
+                        accounts_package,
+                        base_slot
+                    ));
+                    timings.calculate_incremental_accounts_hash_us =
+                        Some(calculate_incremental_accounts_hash_us);
+                    if snapshot_config.verify_incremental_capitalization {
+                        Self::check_incremental_capitalization(
+                            accounts_package,
+                            base_slot,
+                            base_capitalization,
+                            incremental_capitalization,
+                        )
+                        .map_err(io::Error::other)?;
+                    }
+                    Self::audit_accounts_hash(
+                        snapshot_config,
+                        accounts_package,
+                        accounts_package.accounts_hash_algorithm,
+                        &incremental_accounts_hash.0,
+                        incremental_capitalization,
+                    );
                     let bank_incremental_snapshot_persistence =
-                        BankIncrementalSnapshotPersistence {
-                            full_slot: base_slot,
-                            full_hash: base_accounts_hash.into(),
-                            full_capitalization: base_capitalization,
-                            incremental_hash: incremental_accounts_hash.into(),
+                        Self::build_incremental_persistence(
+                            accounts_db,
+                            base_slot,
+                            incremental_accounts_hash,
                             incremental_capitalization,
-                        };
+                        )
+                        .map_err(io::Error::other)?;
                     (
                         incremental_accounts_hash.into(),
                         Some(bank_incremental_snapshot_persistence),
@@ -316,86 +1035,728 @@ impl AccountsHashVerifier {
                 }
             };
 
-        Ok((
+        if snapshot_config.compute_shadow_lattice_hash {
+            Self::shadow_lattice_hash(accounts_package, snapshot_config);
+        }
+
+        Ok(Some((
             MerkleOrLatticeAccountsHash::Merkle(accounts_hash_kind),
             bank_incremental_snapshot_persistence,
-        ))
+        )))
     }
 
-    fn _calculate_full_accounts_hash(
-        accounts_package: &AccountsPackage,
-    ) -> (AccountsHash, /*capitalization*/ u64) {
-        let (sorted_storages, storage_sort_us) =
-            measure_us!(SortedStorages::new(&accounts_package.snapshot_storages));
-
-        let mut timings = HashStats {
-            storage_sort_us,
-            ..HashStats::default()
-        };
-        timings.calc_storage_size_quartiles(&accounts_package.snapshot_storages);
-
-        let epoch = accounts_package
-            .epoch_schedule
-            .get_epoch(accounts_package.slot);
-        let calculate_accounts_hash_config = CalcAccountsHashConfig {
-            use_bg_thread_pool: true,
-            ancestors: None,
-            epoch_schedule: &accounts_package.epoch_schedule,
-            epoch,
-            store_detailed_debug_info_on_failure: false,
-        };
-
-        let slot = accounts_package.slot;
-        let ((accounts_hash, lamports), measure_hash_us) =
-            measure_us!(accounts_package.accounts.accounts_db.update_accounts_hash(
-                &calculate_accounts_hash_config,
-                &sorted_storages,
-                slot,
-                timings,
-            ));
-
-        if accounts_package.expected_capitalization != lamports {
-            // before we assert, run the hash calc again. This helps track down whether it could have been a failure in a race condition possibly with shrink.
-            // We could add diagnostics to the hash calc here to produce a per bin cap or something to help narrow down how many pubkeys are different.
-            let calculate_accounts_hash_config = CalcAccountsHashConfig {
-                // since we're going to assert, use the fg thread pool to go faster
-                use_bg_thread_pool: false,
-                // now that we've failed, store off the failing contents that produced a bad capitalization
-                store_detailed_debug_info_on_failure: true,
-                ..calculate_accounts_hash_config
-            };
-            let second_accounts_hash = accounts_package
-                .accounts
-                .accounts_db
-                .calculate_accounts_hash(
-                    &calculate_accounts_hash_config,
-                    &sorted_storages,
-                    HashStats::default(),
-                );
-            panic!(
-                "accounts hash capitalization mismatch: expected {}, but calculated {} (then \
-                 recalculated {})",
-                accounts_package.expected_capitalization, lamports, second_accounts_hash.1,
+    /// Calculates the lattice-based accounts hash of `accounts_package` alongside its
+    /// merkle-based one, and logs it, so the two can be compared before switching algorithms
+    ///
+    /// The lattice-based accounts hash is checked against the bank's, if the accounts package has
+    /// it, and is added to the hash audit log, but it is never packaged.
+    fn shadow_lattice_hash(accounts_package: &AccountsPackage, snapshot_config: &SnapshotConfig) {
+        let (accounts_lt_hash, calculate_us) =
+            measure_us!(Self::calculate_accounts_lt_hash(accounts_package));
+        let checksum = accounts_lt_hash.0.checksum();
+        let bank_accounts_lt_hash =
+            accounts_package
+                .snapshot_info
+                .as_ref()
+                .and_then(|snapshot_info| {
+                    snapshot_info
+                        .bank_fields_to_serialize
+                        .accounts_lt_hash
+                        .as_ref()
+                });
+        let is_mismatch = bank_accounts_lt_hash
+            .is_some_and(|bank_accounts_lt_hash| *bank_accounts_lt_hash != accounts_lt_hash);
+        if is_mismatch {
+            warn!(
+                "shadow lattice-based accounts hash for slot {} does not match the bank's: \
+                 calculated {checksum}, bank has {}",
+                accounts_package.slot,
+                // SAFETY: Only a mismatch if the bank has an accounts lt hash
+                bank_accounts_lt_hash.unwrap().0.checksum(),
+            );
+        } else {
+            info!(
+                "shadow lattice-based accounts hash for slot {}: {checksum}",
+                accounts_package.slot,
             );
         }
-
-        if let Some(expected_hash) = accounts_package.accounts_hash_for_testing {
-            assert_eq!(expected_hash, accounts_hash);
-        };
-
         datapoint_info!(
-            "accounts_hash_verifier",
-            ("calculate_hash", measure_hash_us, i64),
+            "accounts_hash_verifier-shadow_lattice_hash",
+            ("slot", accounts_package.slot, i64),
+            ("checksum", checksum.to_string(), String),
+            ("is_mismatch", is_mismatch, bool),
+            ("calculate_us", calculate_us, i64),
+        );
+        // The lattice-based calculation does not sum the lamports, so record the expected ones
+        Self::audit_accounts_hash(
+            snapshot_config,
+            accounts_package,
+            AccountsHashAlgorithm::Lattice,
+            &checksum,
+            accounts_package.expected_capitalization,
         );
-
-        (accounts_hash, lamports)
     }
 
-    fn _calculate_incremental_accounts_hash(
-        accounts_package: &AccountsPackage,
-        base_slot: Slot,
-    ) -> (IncrementalAccountsHash, /*capitalization*/ u64) {
-        let incremental_storages =
+    /// Calculates the lattice-based accounts hash of the latest version of each account in the
+    /// accounts package's storages
+    ///
+    /// The storages are scanned in parallel, a range of pubkey bins per pass, to find the older
+    /// versions of accounts.  These are the duplicates accounts-db mixes out at startup.
+    fn calculate_accounts_lt_hash(accounts_package: &AccountsPackage) -> AccountsLtHash {
+        let storages = Self::storages_up_to_slot(accounts_package);
+        let storages_by_slot: HashMap<_, _> = storages
+            .iter()
+            .map(|storage| (storage.slot(), storage))
+            .collect();
+
+        // Every version in the storages is mixed in, so the older versions of each account are
+        // mixed out, just like duplicates are at startup
+        let mut duplicates_lt_hash = DuplicatesLtHash::default();
+        Self::scan_latest_accounts(
+            &storages,
+            LATEST_ACCOUNTS_SCAN_BINS,
+            MAX_LATEST_ACCOUNTS_PER_SCAN_PASS,
+            |latest_accounts| {
+                let pass_duplicates_lt_hash = latest_accounts
+                    .older
+                    .par_iter()
+                    .fold(DuplicatesLtHash::default, |mut accum, older_version| {
+                        storages_by_slot[&older_version.slot]
+                            .accounts
+                            .get_stored_account_callback(older_version.offset, |account| {
+                                let account_lt_hash =
+                                    AccountsDb::lt_hash_account(&account, account.pubkey());
+                                accum.0.mix_in(&account_lt_hash.0);
+                            })
+                            .expect("older version must be in its storage");
+                        accum
+                    })
+                    .reduce(DuplicatesLtHash::default, |mut accum, elem| {
+                        accum.0.mix_in(&elem.0);
+                        accum
+                    });
+                duplicates_lt_hash.0.mix_in(&pass_duplicates_lt_hash.0);
+            },
+        );
+        accounts_package
+            .accounts
+            .accounts_db
+            .calculate_accounts_lt_hash_at_startup_from_storages(&storages, &duplicates_lt_hash)
+    }
+
+    /// Checks that the accounts package's expected capitalization is the incremental
+    /// capitalization, plus the base capitalization less the base lamports of the accounts
+    /// modified since the base
+    ///
+    /// Accounts modified since the base are counted in both capitalizations, so their lamports as
+    /// of the base must be taken out of the base capitalization.
+    fn check_incremental_capitalization(
+        accounts_package: &AccountsPackage,
+        base_slot: Slot,
+        base_capitalization: u64,
+        incremental_capitalization: u64,
+    ) -> Result<(), IncrementalCapitalizationMismatch> {
+        let modified_accounts_base_lamports =
+            Self::modified_accounts_base_lamports(accounts_package, base_slot);
+        let capitalization = base_capitalization
+            .checked_sub(modified_accounts_base_lamports)
+            .and_then(|capitalization| capitalization.checked_add(incremental_capitalization));
+        if capitalization == Some(accounts_package.expected_capitalization) {
+            return Ok(());
+        }
+        Err(IncrementalCapitalizationMismatch {
+            slot: accounts_package.slot,
+            base_capitalization,
+            modified_accounts_base_lamports,
+            incremental_capitalization,
+            expected_capitalization: accounts_package.expected_capitalization,
+        })
+    }
+
+    /// Returns the sum of the lamports, as of `base_slot`, of the accounts modified after it
+    ///
+    /// Only the modified accounts are tracked, so memory is bounded by the size of the incremental
+    /// snapshot, not the whole state.
+    fn modified_accounts_base_lamports(accounts_package: &AccountsPackage, base_slot: Slot) -> u64 {
+        let (base_storages, incremental_storages): (Vec<_>, Vec<_>) = accounts_package
+            .snapshot_storages
+            .iter()
+            .filter(|storage| storage.slot() <= accounts_package.slot)
+            .partition(|storage| storage.slot() <= base_slot);
+
+        let modified_pubkeys: HashSet<Pubkey> = incremental_storages
+            .par_iter()
+            .map(|storage| {
+                let mut pubkeys = Vec::new();
+                storage
+                    .accounts
+                    .scan_pubkeys(|pubkey| pubkeys.push(*pubkey))
+                    .expect("must scan accounts storage");
+                pubkeys
+            })
+            .flatten_iter()
+            .collect();
+
+        // Keep the version of each modified account from the newest base storage it is in
+        let base_accounts = base_storages
+            .par_iter()
+            .map(|storage| {
+                let storage_slot = storage.slot();
+                let mut base_accounts = LatestAccounts::default();
+                storage
+                    .accounts
+                    .scan_accounts_without_data(|offset, account| {
+                        if modified_pubkeys.contains(account.pubkey()) {
+                            base_accounts.add(
+                                *account.pubkey(),
+                                AccountVersion {
+                                    slot: storage_slot,
+                                    offset,
+                                    lamports: account.lamports,
+                                },
+                            );
+                        }
+                    })
+                    .expect("must scan accounts storage");
+                base_accounts
+            })
+            .reduce(LatestAccounts::default, LatestAccounts::merge);
+        base_accounts
+            .latest
+            .into_values()
+            .map(|base_account| base_account.lamports)
+            .try_fold(0u64, u64::checked_add)
+            .expect("capitalization cannot overflow")
+    }
+
+    /// Appends a record of the accounts hash calculated for `accounts_package` to the hash audit
+    /// log, if the snapshot config has one
+    ///
+    /// Failing to write the record is logged, but does not stop the accounts package from being
+    /// handled.
+    fn audit_accounts_hash(
+        snapshot_config: &SnapshotConfig,
+        accounts_package: &AccountsPackage,
+        accounts_hash_algorithm: AccountsHashAlgorithm,
+        hash: &impl fmt::Display,
+        capitalization: u64,
+    ) {
+        let Some(hash_audit_log) = &snapshot_config.hash_audit_log else {
+            return;
+        };
+        let record = HashAuditRecord {
+            slot: accounts_package.slot,
+            kind: match accounts_package.package_kind {
+                AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot) => "full",
+                AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(_)) => {
+                    "incremental"
+                }
+                AccountsPackageKind::EpochAccountsHash => "epoch_accounts_hash",
+            }
+            .to_string(),
+            algorithm: match accounts_hash_algorithm {
+                AccountsHashAlgorithm::Merkle => "merkle",
+                AccountsHashAlgorithm::Lattice => "lattice",
+            }
+            .to_string(),
+            hash: hash.to_string(),
+            capitalization,
+            timestamp: timestamp(),
+        };
+        if let Err(err) = Self::append_hash_audit_record(hash_audit_log, &record) {
+            warn!(
+                "failed to append accounts hash for slot {} to the hash audit log at {}: {err}",
+                accounts_package.slot,
+                hash_audit_log.display(),
+            );
+        }
+    }
+
+    fn append_hash_audit_record(hash_audit_log: &Path, record: &HashAuditRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        // Write the whole line at once, so concurrent appenders cannot interleave within it
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(hash_audit_log)?
+            .write_all(line.as_bytes())
+    }
+
+    /// Checks that the lamports of the latest version of each account in the accounts package's
+    /// storages sum to its expected capitalization, without calculating an accounts hash
+    fn verify_capitalization(accounts_package: &AccountsPackage) -> io::Result<()> {
+        let capitalization = Self::calculate_capitalization(accounts_package);
+
+        if capitalization != accounts_package.expected_capitalization {
+            return Err(io::Error::other(CapitalizationMismatch {
+                slot: accounts_package.slot,
+                capitalization,
+                expected_capitalization: accounts_package.expected_capitalization,
+            }));
+        }
+        Ok(())
+    }
+
+    fn calculate_capitalization(accounts_package: &AccountsPackage) -> u64 {
+        let mut capitalization = 0u64;
+        Self::scan_latest_accounts(
+            &Self::storages_up_to_slot(accounts_package),
+            LATEST_ACCOUNTS_SCAN_BINS,
+            MAX_LATEST_ACCOUNTS_PER_SCAN_PASS,
+            |latest_accounts| {
+                capitalization = latest_accounts
+                    .latest
+                    .into_values()
+                    .map(|latest_account| latest_account.lamports)
+                    .try_fold(capitalization, u64::checked_add)
+                    .expect("capitalization cannot overflow");
+            },
+        );
+        capitalization
+    }
+
+    /// Returns the accounts package's storages, up to its slot
+    fn storages_up_to_slot(accounts_package: &AccountsPackage) -> Vec<Arc<AccountStorageEntry>> {
+        accounts_package
+            .snapshot_storages
+            .iter()
+            .filter(|storage| storage.slot() <= accounts_package.slot)
+            .cloned()
+            .collect()
+    }
+
+    /// Scans `storages` for the latest version of each account, and calls `scan_pass` with them
+    ///
+    /// The storages are scanned in parallel, in passes over ranges of pubkey bins.  Each pass only
+    /// tracks the accounts in its bins, so memory is bounded by `max_accounts_per_pass` instead of
+    /// the whole state, and larger states take more passes.  The storages must all be from
+    /// different slots.
+    fn scan_latest_accounts(
+        storages: &[Arc<AccountStorageEntry>],
+        num_bins: usize,
+        max_accounts_per_pass: usize,
+        mut scan_pass: impl FnMut(LatestAccounts),
+    ) {
+        let bin_calculator = PubkeyBinCalculator24::new(num_bins);
+        // Accounts with older versions not cleaned yet are counted more than once, so this may
+        // take more passes than needed
+        let num_accounts: usize = storages.iter().map(|storage| storage.count()).sum();
+        let num_passes = num_accounts
+            .div_ceil(max_accounts_per_pass)
+            .clamp(1, num_bins);
+        let bins_per_pass = num_bins.div_ceil(num_passes);
+        for start_bin in (0..num_bins).step_by(bins_per_pass) {
+            let pass_bins = start_bin..(start_bin + bins_per_pass).min(num_bins);
+            let latest_accounts = storages
+                .par_iter()
+                .map(|storage| {
+                    let storage_slot = storage.slot();
+                    let mut latest_accounts = LatestAccounts::default();
+                    storage
+                        .accounts
+                        .scan_accounts_without_data(|offset, account| {
+                            if pass_bins.contains(&bin_calculator.bin_from_pubkey(account.pubkey()))
+                            {
+                                latest_accounts.add(
+                                    *account.pubkey(),
+                                    AccountVersion {
+                                        slot: storage_slot,
+                                        offset,
+                                        lamports: account.lamports,
+                                    },
+                                );
+                            }
+                        })
+                        .expect("must scan accounts storage");
+                    latest_accounts
+                })
+                .reduce(LatestAccounts::default, LatestAccounts::merge);
+            scan_pass(latest_accounts);
+        }
+    }
+
+    /// Assembles the incremental snapshot persistence for an incremental accounts hash on top of
+    /// the full accounts hash of `base_slot`
+    ///
+    /// This is what incremental snapshots hold, so tools validating existing incremental snapshots
+    /// can reconstruct it to compare against.  Fails if there is no accounts hash for `base_slot`.
+    pub fn build_incremental_persistence(
+        accounts_db: &AccountsDb,
+        base_slot: Slot,
+        incremental_accounts_hash: IncrementalAccountsHash,
+        incremental_capitalization: u64,
+    ) -> Result<BankIncrementalSnapshotPersistence, AccountsHashVerifierError> {
+        let (base_accounts_hash, base_capitalization) = accounts_db
+            .get_accounts_hash(base_slot)
+            .ok_or(AccountsHashVerifierError::MissingIncrementalBase(base_slot))?;
+        Ok(BankIncrementalSnapshotPersistence {
+            full_slot: base_slot,
+            full_hash: base_accounts_hash.into(),
+            full_capitalization: base_capitalization,
+            incremental_hash: incremental_accounts_hash.into(),
+            incremental_capitalization,
+        })
+    }
+
+    fn _calculate_full_accounts_hash(
+        accounts_package: &AccountsPackage,
+        snapshot_config: &SnapshotConfig,
+    ) -> (AccountsHash, /*capitalization*/ u64) {
+        Self::calculate_full_accounts_hash_with_ancestors(accounts_package, snapshot_config, None)
+    }
+
+    /// Like [`Self::_calculate_full_accounts_hash`], with `ancestors` in the calculation's config
+    fn calculate_full_accounts_hash_with_ancestors(
+        accounts_package: &AccountsPackage,
+        snapshot_config: &SnapshotConfig,
+        ancestors: Option<&Ancestors>,
+    ) -> (AccountsHash, /*capitalization*/ u64) {
+        let mut timings = HashStats::default();
+        let sorted_storages = if accounts_package.snapshot_storages.is_empty() {
+            // Without any storages there are no accounts, so skip sorting them and their size
+            // quartiles.  The hash of the empty accounts set is still stored and checked below.
+            warn!(
+                "accounts package for slot {} has no storages, so its accounts hash is empty",
+                accounts_package.slot,
+            );
+            datapoint_warn!(
+                "accounts_hash_verifier-empty_storages",
+                ("slot", accounts_package.slot, i64),
+                (
+                    "expected_capitalization",
+                    accounts_package.expected_capitalization,
+                    i64
+                ),
+            );
+            SortedStorages::empty()
+        } else {
+            let (sorted_storages, storage_sort_us) =
+                measure_us!(SortedStorages::new(&accounts_package.snapshot_storages));
+            timings.storage_sort_us = storage_sort_us;
+            timings.calc_storage_size_quartiles(&accounts_package.snapshot_storages);
+            sorted_storages
+        };
+
+        let calculate_accounts_hash_config = CalcAccountsHashConfig {
+            ancestors,
+            ..Self::new_full_calculate_accounts_hash_config(accounts_package, snapshot_config)
+        };
+
+        let slot = accounts_package.slot;
+        let (accounts_hash, lamports) = accounts_package.accounts.accounts_db.update_accounts_hash(
+            &calculate_accounts_hash_config,
+            &sorted_storages,
+            slot,
+            timings,
+        );
+
+        if accounts_package.expected_capitalization != lamports {
+            // before we assert, run the hash calc again. This helps track down whether it could have been a failure in a race condition possibly with shrink.
+            // We could add diagnostics to the hash calc here to produce a per bin cap or something to help narrow down how many pubkeys are different.
+            let calculate_accounts_hash_config = CalcAccountsHashConfig {
+                // since we're going to assert, use the fg thread pool to go faster
+                use_bg_thread_pool: false,
+                // now that we've failed, store off the failing contents that produced a bad capitalization
+                store_detailed_debug_info_on_failure: true,
+                ..calculate_accounts_hash_config
+            };
+            let second_accounts_hash = accounts_package
+                .accounts
+                .accounts_db
+                .calculate_accounts_hash(
+                    &calculate_accounts_hash_config,
+                    &sorted_storages,
+                    HashStats::default(),
+                );
+            if let Some(dump_dir) = &snapshot_config.accounts_hash_mismatch_dump_dir {
+                match Self::dump_capitalization_mismatch(
+                    dump_dir,
+                    accounts_package,
+                    (accounts_hash, lamports),
+                    second_accounts_hash,
+                ) {
+                    Ok(dump_path) => error!(
+                        "wrote accounts hash capitalization mismatch diagnostics to {}",
+                        dump_path.display(),
+                    ),
+                    Err(err) => error!(
+                        "failed to write accounts hash capitalization mismatch diagnostics to {}: \
+                         {err}",
+                        dump_dir.display(),
+                    ),
+                }
+            }
+            panic!(
+                "accounts hash capitalization mismatch: expected {}, but calculated {} (then \
+                 recalculated {})",
+                accounts_package.expected_capitalization, lamports, second_accounts_hash.1,
+            );
+        }
+
+        if let Some(expected_hash) = accounts_package.accounts_hash_for_testing {
+            assert_eq!(expected_hash, accounts_hash);
+        };
+
+        (accounts_hash, lamports)
+    }
+
+    /// Recalculates the full accounts hash of `slot` from the existing storages, on demand
+    ///
+    /// Meant for debugging a suspected bad slot.  A transient full snapshot accounts package is
+    /// built from the storages of `slot` and older, and its full accounts hash is calculated and
+    /// stored for `slot`, but nothing is packaged and no old accounts hashes are purged.
+    ///
+    /// Storages are cleaned and shrunk as newer slots are rooted, so this only reproduces the
+    /// original accounts hash of `slot` while its state is still intact in the storages.
+    ///
+    /// To verify a slot that is not rooted, pass its `ancestors`.  Then only the storages of
+    /// rooted slots and of `ancestors` are used, so storages of other forks are left out.  If
+    /// None, the storages of every slot up to `slot` are used.
+    pub fn verify_slot(
+        accounts: &Arc<Accounts>,
+        slot: Slot,
+        snapshot_config: &SnapshotConfig,
+        ancestors: Option<Ancestors>,
+    ) -> Result<AccountsHash, AccountsHashVerifierError> {
+        let (mut snapshot_storages, _slots) = accounts.accounts_db.get_storages(..=slot);
+        if let Some(ancestors) = &ancestors {
+            let accounts_index = &accounts.accounts_db.accounts_index;
+            snapshot_storages.retain(|storage| {
+                let storage_slot = storage.slot();
+                storage_slot == slot
+                    || ancestors.contains_key(&storage_slot)
+                    || accounts_index.is_alive_root(storage_slot)
+            });
+        }
+        if snapshot_storages.is_empty() {
+            return Err(AccountsHashVerifierError::NoStorages(slot));
+        }
+        let mut accounts_package = AccountsPackage {
+            package_kind: AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot),
+            slot,
+            block_height: Slot::default(),
+            snapshot_storages,
+            expected_capitalization: u64::default(),
+            accounts_hash_for_testing: None,
+            accounts: Arc::clone(accounts),
+            // Only used to tell which storages are ancient, which does not change the accounts hash
+            epoch_schedule: EpochSchedule::default(),
+            rent_collector: Default::default(),
+            accounts_hash_algorithm: AccountsHashAlgorithm::Merkle,
+            snapshot_info: None,
+            enqueued: Instant::now(),
+            correlation_id: None,
+        };
+        // There is no bank to take the expected capitalization from, so take it from the
+        // storages, which the accounts hash calculation must agree with
+        accounts_package.expected_capitalization =
+            Self::calculate_capitalization(&accounts_package);
+
+        let (accounts_hash, _capitalization) = Self::calculate_full_accounts_hash_with_ancestors(
+            &accounts_package,
+            snapshot_config,
+            ancestors.as_ref(),
+        );
+        info!("verified accounts hash for slot {slot}: {accounts_hash:?}");
+        Ok(accounts_hash)
+    }
+
+    /// Calculates the full accounts hash of `accounts_package`, for benchmarking
+    ///
+    /// Unlike the production calculation, the capitalization is not checked against the expected
+    /// capitalization, and the accounts hash is not stored in the accounts db, so this may be run
+    /// repeatedly on the same accounts package.
+    #[cfg(feature = "dev-context-only-utils")]
+    pub fn calculate_full_hash_for_bench(
+        accounts_package: &AccountsPackage,
+        snapshot_config: &SnapshotConfig,
+    ) -> (AccountsHash, /*capitalization*/ u64) {
+        Self::calculate_full_accounts_hash_unstored(accounts_package, snapshot_config)
+    }
+
+    /// Calculates the full accounts hashes of `a` and `b`, and whether they are equal
+    ///
+    /// Meant for debugging forks, e.g. with banks for the same slot on different forks.  Neither
+    /// accounts package is packaged, and neither accounts hash is stored in the accounts db.  Like
+    /// [`Self::calculate_full_hash_for_bench`], the capitalizations are not checked.
+    pub fn compare_packages(
+        a: &AccountsPackage,
+        b: &AccountsPackage,
+        snapshot_config: &SnapshotConfig,
+    ) -> (AccountsHash, AccountsHash, /*equal*/ bool) {
+        let (accounts_hash_a, _capitalization) =
+            Self::calculate_full_accounts_hash_unstored(a, snapshot_config);
+        let (accounts_hash_b, _capitalization) =
+            Self::calculate_full_accounts_hash_unstored(b, snapshot_config);
+        let equal = accounts_hash_a == accounts_hash_b;
+        if !equal {
+            info!(
+                "accounts hashes differ: {accounts_hash_a:?} for slot {}, {accounts_hash_b:?} for \
+                 slot {}",
+                a.slot, b.slot,
+            );
+        }
+        (accounts_hash_a, accounts_hash_b, equal)
+    }
+
+    /// Calculates the full accounts hash of `accounts_package`, without storing it
+    fn calculate_full_accounts_hash_unstored(
+        accounts_package: &AccountsPackage,
+        snapshot_config: &SnapshotConfig,
+    ) -> (AccountsHash, /*capitalization*/ u64) {
+        let sorted_storages = SortedStorages::new(&accounts_package.snapshot_storages);
+        let calculate_accounts_hash_config =
+            Self::new_full_calculate_accounts_hash_config(accounts_package, snapshot_config);
+        accounts_package
+            .accounts
+            .accounts_db
+            .calculate_accounts_hash(
+                &calculate_accounts_hash_config,
+                &sorted_storages,
+                HashStats::default(),
+            )
+    }
+
+    /// Checks `accounts_hash` against the hash of the full snapshot archive for `slot`, if any
+    fn verify_against_archives(
+        slot: Slot,
+        accounts_hash: AccountsHash,
+        snapshot_config: &SnapshotConfig,
+    ) -> io::Result<()> {
+        let archive =
+            snapshot_utils::get_full_snapshot_archives(&snapshot_config.full_snapshot_archives_dir)
+                .into_iter()
+                .find(|archive| archive.slot() == slot);
+        let Some(archive) = archive else {
+            debug!("no full snapshot archive for slot {slot} to verify the accounts hash against");
+            return Ok(());
+        };
+        if archive.hash().0 != accounts_hash.0 {
+            return Err(io::Error::other(ArchiveAccountsHashMismatch {
+                slot,
+                archive_path: archive.path().clone(),
+                archive_hash: *archive.hash(),
+                accounts_hash,
+            }));
+        }
+        info!(
+            "verified accounts hash for slot {slot} against full snapshot archive {}",
+            archive.path().display(),
+        );
+        Ok(())
+    }
+
+    /// Returns the config for the initial full accounts hash calculation of `accounts_package`
+    fn new_full_calculate_accounts_hash_config<'a>(
+        accounts_package: &'a AccountsPackage,
+        snapshot_config: &SnapshotConfig,
+    ) -> CalcAccountsHashConfig<'a> {
+        let epoch = accounts_package
+            .epoch_schedule
+            .get_epoch(accounts_package.slot);
+        CalcAccountsHashConfig {
+            use_bg_thread_pool: true,
+            ancestors: None,
+            epoch_schedule: &accounts_package.epoch_schedule,
+            epoch,
+            store_detailed_debug_info_on_failure: snapshot_config.always_store_hash_debug_info,
+            progress: snapshot_config
+                .log_full_accounts_hash_progress
+                .then_some(&Self::log_full_accounts_hash_progress),
+        }
+    }
+
+    /// Full accounts hash calculations on large state take a while, so log how far along they are
+    fn log_full_accounts_hash_progress(
+        phase: CalcAccountsHashPhase,
+        processed: usize,
+        total: usize,
+    ) {
+        info!("full accounts hash calculation progress: {phase:?} {processed}/{total}");
+    }
+
+    /// Writes the diagnostics for a capitalization mismatch to a new file in `dump_dir`
+    ///
+    /// The file name includes the slot and both the expected and calculated capitalizations.
+    /// The contents include both calculations, every storage that went into them, and the
+    /// lamports of the latest accounts in the storages summed per pubkey bin, to narrow down which
+    /// accounts are off.  The bins are summed by [`Self::scan_latest_accounts`], so the dump does
+    /// not hold every account in memory at once.
+    ///
+    /// Returns the path of the written file.
+    fn dump_capitalization_mismatch(
+        dump_dir: &Path,
+        accounts_package: &AccountsPackage,
+        (accounts_hash, capitalization): (AccountsHash, /*capitalization*/ u64),
+        (second_accounts_hash, second_capitalization): (AccountsHash, /*capitalization*/ u64),
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(dump_dir)?;
+        let dump_path = dump_dir.join(format!(
+            "accounts_hash_capitalization_mismatch-{}-{}-{}.txt",
+            accounts_package.slot, accounts_package.expected_capitalization, capitalization,
+        ));
+        let mut writer = BufWriter::new(File::create(&dump_path)?);
+        writeln!(writer, "slot: {}", accounts_package.slot)?;
+        writeln!(
+            writer,
+            "expected capitalization: {}",
+            accounts_package.expected_capitalization,
+        )?;
+        writeln!(
+            writer,
+            "calculated: {accounts_hash:?}, capitalization: {capitalization}",
+        )?;
+        writeln!(
+            writer,
+            "recalculated: {second_accounts_hash:?}, capitalization: {second_capitalization}",
+        )?;
+        writeln!(
+            writer,
+            "storages ({}):",
+            accounts_package.snapshot_storages.len(),
+        )?;
+        for storage in &accounts_package.snapshot_storages {
+            writeln!(
+                writer,
+                "slot: {}, id: {}, count: {}, alive bytes: {}, capacity: {}",
+                storage.slot(),
+                storage.id(),
+                storage.count(),
+                storage.alive_bytes(),
+                storage.capacity(),
+            )?;
+        }
+        let bin_calculator = PubkeyBinCalculator24::new(CAPITALIZATION_MISMATCH_DUMP_BINS);
+        let mut bins = vec![(/*num accounts*/ 0u64, /*lamports*/ 0u128); bin_calculator.bins()];
+        Self::scan_latest_accounts(
+            &Self::storages_up_to_slot(accounts_package),
+            CAPITALIZATION_MISMATCH_DUMP_BINS,
+            MAX_LATEST_ACCOUNTS_PER_SCAN_PASS,
+            |latest_accounts| {
+                for (pubkey, latest_account) in latest_accounts.latest {
+                    let bin = &mut bins[bin_calculator.bin_from_pubkey(&pubkey)];
+                    bin.0 += 1;
+                    bin.1 += u128::from(latest_account.lamports);
+                }
+            },
+        );
+        writeln!(writer, "pubkey bins ({}):", bins.len())?;
+        for (index, (num_accounts, lamports)) in bins.into_iter().enumerate() {
+            writeln!(
+                writer,
+                "bin: {index}, accounts: {num_accounts}, lamports: {lamports}",
+            )?;
+        }
+        writer.flush()?;
+        Ok(dump_path)
+    }
+
+    fn _calculate_incremental_accounts_hash(
+        accounts_package: &AccountsPackage,
+        base_slot: Slot,
+    ) -> (IncrementalAccountsHash, /*capitalization*/ u64) {
+        let incremental_storages =
             accounts_package
                 .snapshot_storages
                 .iter()
@@ -414,9 +1775,10 @@ impl AccountsHashVerifier {
             epoch_schedule: &accounts_package.epoch_schedule,
             epoch,
             store_detailed_debug_info_on_failure: false,
+            progress: None,
         };
 
-        let (incremental_accounts_hash, measure_hash_us) = measure_us!(accounts_package
+        accounts_package
             .accounts
             .accounts_db
             .update_incremental_accounts_hash(
@@ -424,24 +1786,19 @@ impl AccountsHashVerifier {
                 &sorted_storages,
                 accounts_package.slot,
                 HashStats::default(),
-            ));
-
-        datapoint_info!(
-            "accounts_hash_verifier",
-            (
-                "calculate_incremental_accounts_hash_us",
-                measure_hash_us,
-                i64
-            ),
-        );
-
-        incremental_accounts_hash
+            )
     }
 
     fn purge_old_accounts_hashes(
         accounts_package: &AccountsPackage,
         snapshot_config: &SnapshotConfig,
     ) {
+        if accounts_package.accounts_hash_algorithm == AccountsHashAlgorithm::Lattice {
+            // Merkle-based accounts hashes are not calculated for lattice packages, so there is
+            // nothing to purge.  Skip it to avoid needlessly taking the accounts hashes locks.
+            return;
+        }
+
         let should_purge = match (
             snapshot_config.should_generate_snapshots(),
             accounts_package.package_kind,
@@ -462,35 +1819,87 @@ impl AccountsHashVerifier {
         };
 
         if should_purge {
+            let purge_floor = accounts_package
+                .slot
+                .saturating_sub(snapshot_config.retained_accounts_hash_slots);
             accounts_package
                 .accounts
                 .accounts_db
-                .purge_old_accounts_hashes(accounts_package.slot);
+                .purge_old_accounts_hashes(purge_floor);
         }
     }
 
+    /// Submits the snapshot package for `accounts_package` to SnapshotPackagerService
+    ///
+    /// Returns how long submitting took, including acquiring the lock, or None if nothing was
+    /// submitted.
+    ///
+    /// An incremental snapshot package is only useful once its base full snapshot exists, so if
+    /// its base was never handed to SnapshotPackagerService, a warning is logged.  If the snapshot
+    /// config says to skip such packages, it is dropped without building its snapshot package; a
+    /// later incremental snapshot is packaged instead, once its base is in place.
     fn submit_for_packaging(
         accounts_package: AccountsPackage,
         pending_snapshot_packages: &Mutex<PendingSnapshotPackages>,
         merkle_or_lattice_accounts_hash: MerkleOrLatticeAccountsHash,
         bank_incremental_snapshot_persistence: Option<BankIncrementalSnapshotPersistence>,
-    ) {
-        if !matches!(
-            accounts_package.package_kind,
-            AccountsPackageKind::Snapshot(_)
-        ) {
-            return;
-        }
+        snapshot_config: &SnapshotConfig,
+    ) -> Option</*submit_lock_us:*/ u64> {
+        let AccountsPackageKind::Snapshot(snapshot_kind) = accounts_package.package_kind else {
+            return None;
+        };
 
+        let slot = accounts_package.slot;
+        // The latest full snapshot slot is set when the full snapshot is requested, or loaded at
+        // startup, so it covers full snapshots that are pending or still being archived too
+        let latest_full_snapshot_slot = accounts_package
+            .accounts
+            .accounts_db
+            .latest_full_snapshot_slot();
+        let missing_base_slot = match snapshot_kind {
+            SnapshotKind::IncrementalSnapshot(base_slot)
+                if latest_full_snapshot_slot.is_none_or(|latest| latest < base_slot) =>
+            {
+                Some(base_slot)
+            }
+            _ => None,
+        };
+        let is_skipped =
+            missing_base_slot.is_some() && snapshot_config.skip_incremental_snapshots_without_base;
+        if let Some(base_slot) = missing_base_slot {
+            warn!(
+                "incremental snapshot package for slot {slot} has base slot {base_slot}, but the \
+                 latest full snapshot slot is {latest_full_snapshot_slot:?} (skipped: \
+                 {is_skipped})",
+            );
+            datapoint_warn!(
+                "accounts_hash_verifier-incremental_without_base",
+                ("slot", slot, i64),
+                ("base_slot", base_slot, i64),
+                ("skipped", is_skipped, bool),
+            );
+        }
+        if is_skipped {
+            return None;
+        }
         let snapshot_package = SnapshotPackage::new(
             accounts_package,
             merkle_or_lattice_accounts_hash,
             bank_incremental_snapshot_persistence,
         );
-        pending_snapshot_packages
+        // Measure the lock acquisition too, since it is contended with SnapshotPackagerService
+        let (evicted_snapshot_packages, submit_lock_us) = measure_us!(pending_snapshot_packages
             .lock()
             .unwrap()
-            .push(snapshot_package);
+            .push(snapshot_package));
+        for evicted_snapshot_package in evicted_snapshot_packages {
+            info!(
+                "evicted pending snapshot package, kind: {:?}, slot: {}",
+                evicted_snapshot_package.snapshot_kind, evicted_snapshot_package.slot,
+            );
+        }
+
+        Some(submit_lock_us)
     }
 
     pub fn join(self) -> thread::Result<()> {
@@ -498,9 +1907,118 @@ impl AccountsHashVerifier {
     }
 }
 
+/// A line of the hash audit log, see [`SnapshotConfig::hash_audit_log`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct HashAuditRecord {
+    slot: Slot,
+    /// "full" or "incremental"
+    kind: String,
+    /// "merkle" or "lattice"
+    algorithm: String,
+    /// The accounts hash, in base58
+    hash: String,
+    capitalization: u64,
+    /// Milliseconds since the unix epoch
+    timestamp: u64,
+}
+
+/// Adaptive sleep for when there are no accounts packages to handle
+///
+/// Each successive idle sleep doubles, from `min` up to `max`, until reset.
+#[derive(Debug)]
+struct LoopLimiter {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl LoopLimiter {
+    fn new(min: Duration, max: Duration) -> Self {
+        debug_assert!(min <= max);
+        Self {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    /// Returns how long to sleep for, and lengthens the following sleep
+    fn next_sleep(&mut self) -> Duration {
+        let sleep = self.next;
+        self.next = self.next.saturating_mul(2).min(self.max);
+        sleep
+    }
+
+    /// Shortens the next sleep back to the minimum, such as after handling an accounts package
+    fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+/// Decides which handled accounts packages emit the `accounts_hash_verifier` datapoint
+///
+/// Only every `sample_rate`th accounts package emits, starting with the first, but errors
+/// always do.
+#[derive(Debug)]
+struct MetricsSampler {
+    sample_rate: u32,
+    num_skipped: u32,
+}
+
+impl MetricsSampler {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            num_skipped: 0,
+        }
+    }
+
+    /// Should the accounts package just handled emit the datapoint?
+    fn should_emit(&mut self, is_err: bool) -> bool {
+        if is_err || self.num_skipped.saturating_add(1) >= self.sample_rate {
+            self.num_skipped = 0;
+            return true;
+        }
+        self.num_skipped += 1;
+        false
+    }
+}
+
+/// Random delays before re-enqueuing accounts packages
+///
+/// When many accounts packages are re-enqueued at once, they would otherwise all reappear
+/// together and be handed to the packager in a burst.
+#[derive(Debug)]
+struct ReEnqueueJitter {
+    max: Duration,
+    rng: StdRng,
+}
+
+impl ReEnqueueJitter {
+    fn new(max: Duration, rng: StdRng) -> Self {
+        Self { max, rng }
+    }
+
+    /// Returns how long to sleep for before re-enqueuing the next accounts package
+    fn next_delay(&mut self) -> Duration {
+        self.rng.gen_range(Duration::ZERO..=self.max)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use {super::*, rand::seq::SliceRandom, solana_runtime::snapshot_package::SnapshotKind};
+    use {
+        super::*,
+        rand::seq::SliceRandom,
+        solana_accounts_db::accounts_hash::{AccountsHasher, MERKLE_FANOUT},
+        solana_pubkey::Pubkey,
+        solana_runtime::{
+            bank::Bank, genesis_utils::create_genesis_config, snapshot_package::SnapshotKind,
+            snapshot_utils::ArchiveFormat,
+        },
+        solana_signer::Signer,
+        std::time::Instant,
+    };
 
     fn new(package_kind: AccountsPackageKind, slot: Slot) -> AccountsPackage {
         AccountsPackage {
@@ -526,6 +2044,26 @@ mod tests {
         )
     }
 
+    /// Create a full snapshot accounts package, with real storages, from a rooted bank
+    fn new_fss_with_storages() -> AccountsPackage {
+        let genesis_config_info = create_genesis_config(1_000_000_000);
+        let bank = Bank::new_for_tests(&genesis_config_info.genesis_config);
+        bank.fill_bank_with_ticks_for_tests();
+        bank.squash();
+        bank.force_flush_accounts_cache();
+        let snapshot_storages = bank.get_snapshot_storages(None);
+        AccountsPackage {
+            accounts_hash_algorithm: AccountsHashAlgorithm::Merkle,
+            ..AccountsPackage::new_for_snapshot(
+                AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot),
+                &bank,
+                snapshot_storages,
+                Vec::default(),
+                None,
+            )
+        }
+    }
+
     /// Ensure that unhandled accounts packages are properly re-enqueued or dropped
     ///
     /// The accounts package handler should re-enqueue unhandled accounts packages, if those
@@ -559,9 +2097,13 @@ mod tests {
             account_package,
             _num_outstanding_accounts_packages,
             num_re_enqueued_accounts_packages,
+            _selection_us,
         ) = AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
             &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .unwrap();
         assert_eq!(
@@ -577,9 +2119,13 @@ mod tests {
             account_package,
             _num_outstanding_accounts_packages,
             num_re_enqueued_accounts_packages,
+            _selection_us,
         ) = AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
             &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .unwrap();
         assert_eq!(
@@ -595,9 +2141,13 @@ mod tests {
             account_package,
             _num_outstanding_accounts_packages,
             num_re_enqueued_accounts_packages,
+            _selection_us,
         ) = AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
             &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .unwrap();
         assert_eq!(
@@ -610,7 +2160,10 @@ mod tests {
         // And now the accounts package channel is empty!
         assert!(AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
-            &accounts_package_receiver
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .is_none());
     }
@@ -643,9 +2196,13 @@ mod tests {
             account_package,
             _num_outstanding_accounts_packages,
             num_re_enqueued_accounts_packages,
+            _selection_us,
         ) = AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
             &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .unwrap();
         assert_eq!(
@@ -660,9 +2217,13 @@ mod tests {
             account_package,
             _num_outstanding_accounts_packages,
             num_re_enqueued_accounts_packages,
+            _selection_us,
         ) = AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
             &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .unwrap();
         assert_eq!(
@@ -678,9 +2239,13 @@ mod tests {
             account_package,
             _num_outstanding_accounts_packages,
             num_re_enqueued_accounts_packages,
+            _selection_us,
         ) = AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
             &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .unwrap();
         assert_eq!(
@@ -693,8 +2258,1899 @@ mod tests {
         // And now the accounts package channel is empty!
         assert!(AccountsHashVerifier::get_next_accounts_package(
             &accounts_package_sender,
-            &accounts_package_receiver
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
         )
         .is_none());
     }
+
+    /// Ensure the queue depth reflects the number of accounts packages in the channel
+    #[test]
+    fn test_update_queue_depth() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let queue_depth = AtomicUsize::new(0);
+
+        AccountsHashVerifier::update_queue_depth(&queue_depth, &accounts_package_receiver);
+        assert_eq!(queue_depth.load(Ordering::Relaxed), 0);
+
+        const NUM_ACCOUNTS_PACKAGES: usize = 5;
+        for i in 0..NUM_ACCOUNTS_PACKAGES as Slot {
+            accounts_package_sender.send(new_fss(i * 100)).unwrap();
+        }
+        AccountsHashVerifier::update_queue_depth(&queue_depth, &accounts_package_receiver);
+        assert_eq!(queue_depth.load(Ordering::Relaxed), NUM_ACCOUNTS_PACKAGES);
+
+        // The highest full snapshot is handled; everything else is dropped
+        let (accounts_package, ..) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(accounts_package.slot, 400);
+        AccountsHashVerifier::update_queue_depth(&queue_depth, &accounts_package_receiver);
+        assert_eq!(queue_depth.load(Ordering::Relaxed), 0);
+    }
+
+    /// Ensure capitalization mismatch diagnostics are written to a file before panicking
+    #[test]
+    fn test_calculate_full_accounts_hash_dumps_capitalization_mismatch() {
+        let accounts_package = new_fss_with_storages();
+        let calculated_capitalization = accounts_package.expected_capitalization;
+        let accounts_package = AccountsPackage {
+            expected_capitalization: calculated_capitalization + 1,
+            ..accounts_package
+        };
+        let dump_dir = tempfile::TempDir::new().unwrap();
+        let snapshot_config = SnapshotConfig {
+            accounts_hash_mismatch_dump_dir: Some(dump_dir.path().to_path_buf()),
+            ..SnapshotConfig::default()
+        };
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            AccountsHashVerifier::_calculate_full_accounts_hash(&accounts_package, &snapshot_config)
+        }));
+        assert!(result.is_err());
+
+        let dump_path = dump_dir.path().join(format!(
+            "accounts_hash_capitalization_mismatch-{}-{}-{}.txt",
+            accounts_package.slot,
+            accounts_package.expected_capitalization,
+            calculated_capitalization,
+        ));
+        let contents = fs::read_to_string(dump_path).unwrap();
+        assert!(contents.contains(&format!(
+            "expected capitalization: {}",
+            accounts_package.expected_capitalization,
+        )));
+        assert!(contents.contains(&format!(
+            "storages ({}):",
+            accounts_package.snapshot_storages.len(),
+        )));
+        // the per-bin lamports add up to the calculated capitalization
+        assert!(contents.contains(&format!(
+            "pubkey bins ({CAPITALIZATION_MISMATCH_DUMP_BINS}):"
+        )));
+        let bin_lamports: Vec<u128> = contents
+            .lines()
+            .filter(|line| line.starts_with("bin: "))
+            .map(|line| line.rsplit_once("lamports: ").unwrap().1.parse().unwrap())
+            .collect();
+        assert_eq!(bin_lamports.len(), CAPITALIZATION_MISMATCH_DUMP_BINS);
+        assert_eq!(
+            bin_lamports.iter().sum::<u128>(),
+            u128::from(calculated_capitalization),
+        );
+    }
+
+    /// Ensure re-enqueued accounts packages are in a deterministic order
+    ///
+    /// Regardless of the order the accounts packages are received in, two selection cycles must
+    /// handle the same accounts packages, and re-enqueue the rest in the same order.
+    #[test]
+    fn test_get_next_accounts_package_re_enqueue_order() {
+        let select_twice = |seed: u64| {
+            let (accounts_package_sender, accounts_package_receiver) =
+                crossbeam_channel::unbounded();
+            let mut accounts_packages = [
+                new_fss(100),
+                new_iss(110, 100),
+                new_iss(120, 100),
+                new_fss(200),
+                new_iss(230, 200),
+                new_iss(210, 200),
+                new_iss(220, 200),
+            ];
+            accounts_packages.shuffle(&mut StdRng::seed_from_u64(seed));
+            accounts_packages.into_iter().for_each(|accounts_package| {
+                accounts_package_sender.send(accounts_package).unwrap()
+            });
+
+            let mut handled = Vec::new();
+            let mut re_enqueued = Vec::new();
+            for _ in 0..2 {
+                let (accounts_package, ..) = AccountsHashVerifier::get_next_accounts_package(
+                    &accounts_package_sender,
+                    &accounts_package_receiver,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+                handled.push((accounts_package.package_kind, accounts_package.slot));
+
+                // Peek at the re-enqueued accounts packages, then put them back as-is
+                let accounts_packages: Vec<_> = accounts_package_receiver.try_iter().collect();
+                re_enqueued.push(
+                    accounts_packages
+                        .iter()
+                        .map(|accounts_package| accounts_package.slot)
+                        .collect::<Vec<_>>(),
+                );
+                accounts_packages.into_iter().for_each(|accounts_package| {
+                    accounts_package_sender.send(accounts_package).unwrap()
+                });
+            }
+            (handled, re_enqueued)
+        };
+
+        let expected = select_twice(0);
+        assert_eq!(
+            expected,
+            (
+                vec![
+                    (
+                        AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot),
+                        200
+                    ),
+                    (
+                        AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(200)),
+                        230
+                    ),
+                ],
+                vec![vec![210, 220, 230], vec![]],
+            ),
+        );
+        for seed in 1..20 {
+            assert_eq!(select_twice(seed), expected, "seed: {seed}");
+        }
+    }
+
+    /// Ensure re-enqueue jitter does not change which accounts packages are re-enqueued
+    #[test]
+    fn test_re_enqueue_accounts_packages_jitter_disabled() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let accounts_packages = vec![new_iss(110, 100), new_iss(120, 100), new_iss(130, 100)];
+
+        let num_re_enqueued = AccountsHashVerifier::re_enqueue_accounts_packages(
+            &accounts_package_sender,
+            accounts_packages,
+            115,
+            None,
+        );
+        assert_eq!(num_re_enqueued, 2);
+        assert_eq!(
+            accounts_package_receiver
+                .try_iter()
+                .map(|accounts_package| accounts_package.slot)
+                .collect::<Vec<_>>(),
+            vec![120, 130],
+        );
+    }
+
+    /// Ensure re-enqueue jitter sleeps before re-enqueuing each accounts package
+    #[test]
+    fn test_re_enqueue_accounts_packages_jitter() {
+        const SEED: u64 = 42;
+        let max = Duration::from_millis(20);
+        let mut expected_jitter = ReEnqueueJitter::new(max, StdRng::seed_from_u64(SEED));
+        let expected_delays: Vec<_> = (0..2).map(|_| expected_jitter.next_delay()).collect();
+        assert!(expected_delays.iter().all(|delay| *delay <= max));
+
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let accounts_packages = vec![new_iss(110, 100), new_iss(120, 100), new_iss(130, 100)];
+        let mut re_enqueue_jitter = ReEnqueueJitter::new(max, StdRng::seed_from_u64(SEED));
+        let start = Instant::now();
+        let num_re_enqueued = AccountsHashVerifier::re_enqueue_accounts_packages(
+            &accounts_package_sender,
+            accounts_packages,
+            115,
+            Some(&mut re_enqueue_jitter),
+        );
+        assert!(start.elapsed() >= expected_delays.iter().sum());
+        assert_eq!(num_re_enqueued, 2);
+        assert_eq!(accounts_package_receiver.len(), 2);
+
+        // The same seed produced the same delays, so both jitters are at the same point
+        assert_eq!(re_enqueue_jitter.next_delay(), expected_jitter.next_delay());
+    }
+
+    /// Ensure old accounts hashes are only purged when handling merkle-based accounts packages
+    #[test]
+    fn test_purge_old_accounts_hashes_skipped_for_lattice() {
+        let snapshot_config = SnapshotConfig::default();
+        let accounts_package = AccountsPackage {
+            accounts_hash_algorithm: AccountsHashAlgorithm::Lattice,
+            ..new_fss(100)
+        };
+        let old_slot = accounts_package.slot - 1;
+        accounts_package
+            .accounts
+            .accounts_db
+            .set_incremental_accounts_hash(
+                old_slot,
+                (IncrementalAccountsHash(Hash::new_unique()), 1),
+            );
+
+        AccountsHashVerifier::purge_old_accounts_hashes(&accounts_package, &snapshot_config);
+        assert!(accounts_package
+            .accounts
+            .accounts_db
+            .get_incremental_accounts_hash(old_slot)
+            .is_some());
+
+        let accounts_package = AccountsPackage {
+            accounts_hash_algorithm: AccountsHashAlgorithm::Merkle,
+            ..accounts_package
+        };
+        AccountsHashVerifier::purge_old_accounts_hashes(&accounts_package, &snapshot_config);
+        assert!(accounts_package
+            .accounts
+            .accounts_db
+            .get_incremental_accounts_hash(old_slot)
+            .is_none());
+    }
+
+    /// Ensure accounts hashes within the retention window survive purging
+    #[test]
+    fn test_purge_old_accounts_hashes_retained_slots() {
+        let snapshot_config = SnapshotConfig {
+            retained_accounts_hash_slots: 10,
+            ..SnapshotConfig::default()
+        };
+        let accounts_package = new_fss(100);
+        let accounts_db = &accounts_package.accounts.accounts_db;
+        for slot in [85, 89, 90, 95] {
+            accounts_db.set_incremental_accounts_hash(
+                slot,
+                (IncrementalAccountsHash(Hash::new_unique()), 1),
+            );
+        }
+
+        AccountsHashVerifier::purge_old_accounts_hashes(&accounts_package, &snapshot_config);
+        for (slot, is_retained) in [(85, false), (89, false), (90, true), (95, true)] {
+            assert_eq!(
+                accounts_db.get_incremental_accounts_hash(slot).is_some(),
+                is_retained,
+                "slot: {slot}",
+            );
+        }
+    }
+
+    /// Ensure only snapshot accounts packages are submitted for packaging
+    #[test]
+    fn test_submit_for_packaging() {
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        AccountsHashVerifier::submit_for_packaging(
+            new_eah(100),
+            &pending_snapshot_packages,
+            MerkleOrLatticeAccountsHash::Lattice,
+            None,
+            &SnapshotConfig::default(),
+        );
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+
+        AccountsHashVerifier::submit_for_packaging(
+            new_fss(200),
+            &pending_snapshot_packages,
+            MerkleOrLatticeAccountsHash::Lattice,
+            None,
+            &SnapshotConfig::default(),
+        );
+        let mut pending_snapshot_packages = pending_snapshot_packages.lock().unwrap();
+        let snapshot_package = pending_snapshot_packages.pop().unwrap();
+        assert_eq!(snapshot_package.snapshot_kind, SnapshotKind::FullSnapshot);
+        assert_eq!(snapshot_package.slot, 200);
+        assert!(pending_snapshot_packages.pop().is_none());
+    }
+
+    /// Ensure incremental snapshot packages whose base full snapshot was never handed to the
+    /// snapshot packager are only submitted if they are not skipped
+    #[test]
+    fn test_submit_for_packaging_incremental_without_base() {
+        let snapshot_config = SnapshotConfig {
+            skip_incremental_snapshots_without_base: true,
+            ..SnapshotConfig::default()
+        };
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+        let new_iss_with_latest_full_snapshot_slot = |slot, base, latest_full_snapshot_slot| {
+            let accounts_package = new_iss(slot, base);
+            if let Some(latest_full_snapshot_slot) = latest_full_snapshot_slot {
+                accounts_package
+                    .accounts
+                    .accounts_db
+                    .set_latest_full_snapshot_slot(latest_full_snapshot_slot);
+            }
+            accounts_package
+        };
+
+        // Without a full snapshot for slot 100, the incremental is skipped
+        AccountsHashVerifier::submit_for_packaging(
+            new_iss_with_latest_full_snapshot_slot(110, 100, None),
+            &pending_snapshot_packages,
+            MerkleOrLatticeAccountsHash::Lattice,
+            None,
+            &snapshot_config,
+        );
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+
+        // As it is if the latest full snapshot is older than its base
+        AccountsHashVerifier::submit_for_packaging(
+            new_iss_with_latest_full_snapshot_slot(110, 100, Some(50)),
+            &pending_snapshot_packages,
+            MerkleOrLatticeAccountsHash::Lattice,
+            None,
+            &snapshot_config,
+        );
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+
+        // Unless skipping is disabled, in which case it is submitted with just a warning
+        AccountsHashVerifier::submit_for_packaging(
+            new_iss_with_latest_full_snapshot_slot(110, 100, None),
+            &pending_snapshot_packages,
+            MerkleOrLatticeAccountsHash::Lattice,
+            None,
+            &SnapshotConfig {
+                skip_incremental_snapshots_without_base: false,
+                ..snapshot_config.clone()
+            },
+        );
+        let snapshot_package = pending_snapshot_packages.lock().unwrap().pop().unwrap();
+        assert_eq!(
+            snapshot_package.snapshot_kind,
+            SnapshotKind::IncrementalSnapshot(100)
+        );
+
+        // A full snapshot handed to the snapshot packager is a valid base, whether or not it has
+        // been archived yet
+        for latest_full_snapshot_slot in [100, 105] {
+            AccountsHashVerifier::submit_for_packaging(
+                new_iss_with_latest_full_snapshot_slot(120, 100, Some(latest_full_snapshot_slot)),
+                &pending_snapshot_packages,
+                MerkleOrLatticeAccountsHash::Lattice,
+                None,
+                &snapshot_config,
+            );
+            let snapshot_package = pending_snapshot_packages.lock().unwrap().pop().unwrap();
+            assert_eq!(snapshot_package.slot, 120);
+        }
+    }
+
+    /// Ensure the correlation id is carried from the accounts package into the snapshot package
+    #[test]
+    fn test_submit_for_packaging_correlation_id() {
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        AccountsHashVerifier::submit_for_packaging(
+            AccountsPackage {
+                correlation_id: Some(42),
+                ..new_fss(100)
+            },
+            &pending_snapshot_packages,
+            MerkleOrLatticeAccountsHash::Lattice,
+            None,
+            &SnapshotConfig::default(),
+        );
+        let snapshot_package = pending_snapshot_packages.lock().unwrap().pop().unwrap();
+        assert_eq!(snapshot_package.slot, 100);
+        assert_eq!(snapshot_package.correlation_id, Some(42));
+    }
+
+    /// Ensure incremental accounts packages are dropped, without panicking, when their base is
+    /// missing and the snapshot config says to drop them
+    #[test]
+    fn test_process_accounts_package_drop_missing_incremental_base() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller = SnapshotController::new(
+            abs_request_sender,
+            SnapshotConfig {
+                on_missing_incremental_base: OnMissingIncrementalBase::Drop,
+                ..SnapshotConfig::default()
+            },
+            0,
+        );
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        // There is no accounts hash for the base slot
+        let accounts_package = new_iss(110, 100);
+        assert!(accounts_package
+            .accounts
+            .accounts_db
+            .get_accounts_hash(100)
+            .is_none());
+
+        let outcome = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, ProcessOutcome::DroppedMissingIncrementalBase);
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+    }
+
+    /// Ensure the accounts package observer is invoked once per handled accounts package
+    #[test]
+    fn test_process_accounts_package_observer() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller = SnapshotController::new(
+            abs_request_sender,
+            SnapshotConfig {
+                on_missing_incremental_base: OnMissingIncrementalBase::Drop,
+                ..SnapshotConfig::default()
+            },
+            0,
+        );
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        let accounts_package_observer: AccountsPackageObserver = {
+            let observed = observed.clone();
+            Arc::new(move |accounts_package, _accounts_hash| {
+                observed
+                    .lock()
+                    .unwrap()
+                    .push((accounts_package.slot, accounts_package.package_kind));
+            })
+        };
+
+        let accounts_packages = [
+            AccountsPackage {
+                accounts_hash_algorithm: AccountsHashAlgorithm::Lattice,
+                ..new_fss(100)
+            },
+            AccountsPackage {
+                accounts_hash_algorithm: AccountsHashAlgorithm::Lattice,
+                ..new_iss(110, 100)
+            },
+            // dropped, since there is no accounts hash for its base slot
+            new_iss(120, 50),
+        ];
+        for accounts_package in accounts_packages {
+            AccountsHashVerifier::process_accounts_package(
+                accounts_package,
+                &pending_snapshot_packages,
+                &snapshot_controller,
+                Some(&accounts_package_observer),
+                None,
+                None,
+                &mut ProcessTimings::default(),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            *observed.lock().unwrap(),
+            vec![
+                (
+                    100,
+                    AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot)
+                ),
+                (
+                    110,
+                    AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(100))
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_metrics_sampler() {
+        let mut num_emitted = 0;
+        let mut emit = |metrics_sampler: &mut MetricsSampler, is_err| {
+            let should_emit = metrics_sampler.should_emit(is_err);
+            if should_emit {
+                num_emitted += 1;
+            }
+            should_emit
+        };
+
+        let mut metrics_sampler = MetricsSampler::new(3);
+        let emitted: Vec<_> = (0..9).map(|_| emit(&mut metrics_sampler, false)).collect();
+        assert_eq!(
+            emitted,
+            [true, false, false, true, false, false, true, false, false],
+        );
+
+        // Errors always emit, and restart the sampling
+        assert!(emit(&mut metrics_sampler, true));
+        assert!(!emit(&mut metrics_sampler, false));
+        assert!(!emit(&mut metrics_sampler, false));
+        assert!(emit(&mut metrics_sampler, false));
+        assert_eq!(num_emitted, 5);
+
+        // Sample rates of 0 and 1 both emit for every accounts package
+        for sample_rate in [0, 1] {
+            let mut metrics_sampler = MetricsSampler::new(sample_rate);
+            assert!((0..3).all(|_| metrics_sampler.should_emit(false)));
+        }
+    }
+
+    #[test]
+    fn test_loop_limiter() {
+        let min = Duration::from_millis(10);
+        let max = Duration::from_millis(50);
+        let mut loop_limiter = LoopLimiter::new(min, max);
+        assert_eq!(loop_limiter.next_sleep(), Duration::from_millis(10));
+        assert_eq!(loop_limiter.next_sleep(), Duration::from_millis(20));
+        assert_eq!(loop_limiter.next_sleep(), Duration::from_millis(40));
+        assert_eq!(loop_limiter.next_sleep(), max);
+        assert_eq!(loop_limiter.next_sleep(), max);
+
+        loop_limiter.reset();
+        assert_eq!(loop_limiter.next_sleep(), min);
+    }
+
+    /// Ensure an accounts package enqueued shortly after going idle is picked up quickly, instead
+    /// of after the maximum sleep
+    #[test]
+    fn test_loop_limiter_picks_up_package_after_short_idle() {
+        let min = Duration::from_millis(1);
+        let max = Duration::from_secs(10);
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+
+        let t_handler = {
+            let accounts_package_sender = accounts_package_sender.clone();
+            thread::spawn(move || {
+                let mut loop_limiter = LoopLimiter::new(min, max);
+                loop {
+                    if let Some((accounts_package, ..)) =
+                        AccountsHashVerifier::get_next_accounts_package(
+                            &accounts_package_sender,
+                            &accounts_package_receiver,
+                            None,
+                            None,
+                            None,
+                        )
+                    {
+                        return (accounts_package.slot, Instant::now());
+                    }
+                    thread::sleep(loop_limiter.next_sleep());
+                }
+            })
+        };
+
+        // let the handler go idle for a few iterations before enqueuing
+        thread::sleep(Duration::from_millis(10));
+        let enqueued = Instant::now();
+        accounts_package_sender.send(new_fss(100)).unwrap();
+
+        let (slot, handled) = t_handler.join().unwrap();
+        assert_eq!(slot, 100);
+        assert!(handled.duration_since(enqueued) < max / 2);
+    }
+
+    /// Ensure full accounts hashes, and their capitalization, are sent on the result channel
+    #[test]
+    fn test_process_accounts_package_sends_accounts_hash() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller =
+            SnapshotController::new(abs_request_sender, SnapshotConfig::default(), 0);
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+        let (accounts_hash_sender, accounts_hash_receiver) = crossbeam_channel::unbounded();
+
+        let accounts_package = new_fss_with_storages();
+        let slot = accounts_package.slot;
+        let expected_capitalization = accounts_package.expected_capitalization;
+        let outcome = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            Some(&accounts_hash_sender),
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, ProcessOutcome::Packaged);
+
+        let (accounts_hash_slot, _accounts_hash, capitalization) =
+            accounts_hash_receiver.try_recv().unwrap();
+        assert_eq!(accounts_hash_slot, slot);
+        assert_ne!(capitalization, 0);
+        assert_eq!(capitalization, expected_capitalization);
+        assert!(accounts_hash_receiver.try_recv().is_err());
+    }
+
+    /// Ensure the accounts hash algorithm selector overrides the accounts package's algorithm
+    #[test]
+    fn test_process_accounts_package_algorithm_selector() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller =
+            SnapshotController::new(abs_request_sender, SnapshotConfig::default(), 0);
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+        let (accounts_hash_sender, accounts_hash_receiver) = crossbeam_channel::unbounded();
+        let accounts_hash_algorithm_selector: AccountsHashAlgorithmSelector =
+            Arc::new(|_accounts_package| AccountsHashAlgorithm::Lattice);
+
+        let accounts_package = new_fss_with_storages();
+        assert_eq!(
+            accounts_package.accounts_hash_algorithm,
+            AccountsHashAlgorithm::Merkle
+        );
+        let slot = accounts_package.slot;
+        let outcome = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            Some(&accounts_hash_sender),
+            Some(&accounts_hash_algorithm_selector),
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, ProcessOutcome::SkippedLattice);
+
+        // the merkle-based calculation was skipped, so no accounts hash was sent
+        assert!(accounts_hash_receiver.try_recv().is_err());
+        let snapshot_package = pending_snapshot_packages.lock().unwrap().pop().unwrap();
+        assert_eq!(snapshot_package.slot, slot);
+    }
+
+    /// Ensure a mismatched incremental capitalization is reported as an error, when verifying it
+    #[test]
+    fn test_process_accounts_package_verify_incremental_capitalization() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller = SnapshotController::new(
+            abs_request_sender,
+            SnapshotConfig {
+                verify_incremental_capitalization: true,
+                ..SnapshotConfig::default()
+            },
+            0,
+        );
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        // All storages are at the base slot, so the incremental capitalization is zero, and the
+        // base capitalization alone must match the expected capitalization
+        fn new_iss_with_base_capitalization(
+            base_capitalization: impl FnOnce(/*expected capitalization*/ u64) -> u64,
+        ) -> AccountsPackage {
+            let accounts_package = new_fss_with_storages();
+            let base_slot = accounts_package.slot;
+            accounts_package.accounts.accounts_db.set_accounts_hash(
+                base_slot,
+                (
+                    AccountsHash(Hash::new_unique()),
+                    base_capitalization(accounts_package.expected_capitalization),
+                ),
+            );
+            AccountsPackage {
+                package_kind: AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(
+                    base_slot,
+                )),
+                ..accounts_package
+            }
+        }
+
+        let accounts_package = new_iss_with_base_capitalization(|expected| expected);
+        AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_some());
+
+        let accounts_package = new_iss_with_base_capitalization(|expected| expected - 1);
+        let slot = accounts_package.slot;
+        let expected_capitalization = accounts_package.expected_capitalization;
+        let err = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.get_ref()
+                .unwrap()
+                .downcast_ref::<IncrementalCapitalizationMismatch>(),
+            Some(&IncrementalCapitalizationMismatch {
+                slot,
+                base_capitalization: expected_capitalization - 1,
+                modified_accounts_base_lamports: 0,
+                incremental_capitalization: 0,
+                expected_capitalization,
+            }),
+        );
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+    }
+
+    /// Ensure the incremental capitalization check holds when accounts that existed in the base
+    /// are modified after it, since they are in both the base and incremental capitalizations
+    #[test]
+    fn test_check_incremental_capitalization_modified_base_accounts() {
+        let genesis_config_info = create_genesis_config(1_000_000_000);
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config_info.genesis_config));
+        bank0.fill_bank_with_ticks_for_tests();
+        bank0.squash();
+        bank0.force_flush_accounts_cache();
+        let base_capitalization = bank0.capitalization();
+
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::new_unique(), 1);
+        bank1
+            .transfer(
+                1_000_000,
+                &genesis_config_info.mint_keypair,
+                &Pubkey::new_unique(),
+            )
+            .unwrap();
+        bank1.fill_bank_with_ticks_for_tests();
+        bank1.squash();
+        bank1.force_flush_accounts_cache();
+        let accounts_package = AccountsPackage::new_for_snapshot(
+            AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(0)),
+            &bank1,
+            bank1.get_snapshot_storages(None),
+            Vec::default(),
+            None,
+        );
+        let (_incremental_accounts_hash, incremental_capitalization) =
+            AccountsHashVerifier::_calculate_incremental_accounts_hash(&accounts_package, 0);
+
+        // the mint existed in the base, and is modified after it
+        let modified_accounts_base_lamports =
+            AccountsHashVerifier::modified_accounts_base_lamports(&accounts_package, 0);
+        assert!(
+            modified_accounts_base_lamports
+                >= genesis_config_info.genesis_config.accounts
+                    [&genesis_config_info.mint_keypair.pubkey()]
+                    .lamports
+        );
+        assert_ne!(
+            base_capitalization + incremental_capitalization,
+            accounts_package.expected_capitalization,
+        );
+        assert_eq!(
+            AccountsHashVerifier::check_incremental_capitalization(
+                &accounts_package,
+                0,
+                base_capitalization,
+                incremental_capitalization,
+            ),
+            Ok(()),
+        );
+        assert!(AccountsHashVerifier::check_incremental_capitalization(
+            &accounts_package,
+            0,
+            base_capitalization - 1,
+            incremental_capitalization,
+        )
+        .is_err());
+    }
+
+    /// Ensure every accounts hash calculated is appended to the hash audit log
+    #[test]
+    fn test_process_accounts_package_hash_audit_log() {
+        let audit_dir = tempfile::TempDir::new().unwrap();
+        let hash_audit_log = audit_dir.path().join("hash_audit.jsonl");
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_config = SnapshotConfig {
+            hash_audit_log: Some(hash_audit_log.clone()),
+            ..SnapshotConfig::default()
+        };
+        let snapshot_controller =
+            SnapshotController::new(abs_request_sender, snapshot_config.clone(), 0);
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        let mut expected = Vec::new();
+        for _ in 0..2 {
+            let accounts_package = new_fss_with_storages();
+            let (accounts_hash, capitalization) =
+                AccountsHashVerifier::calculate_full_hash_for_bench(
+                    &accounts_package,
+                    &snapshot_config,
+                );
+            expected.push((accounts_package.slot, accounts_hash, capitalization));
+            AccountsHashVerifier::process_accounts_package(
+                accounts_package,
+                &pending_snapshot_packages,
+                &snapshot_controller,
+                None,
+                None,
+                None,
+                &mut ProcessTimings::default(),
+            )
+            .unwrap();
+            pending_snapshot_packages.lock().unwrap().pop().unwrap();
+        }
+
+        let audit_log = fs::read_to_string(&hash_audit_log).unwrap();
+        let records: Vec<HashAuditRecord> = audit_log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        for (record, (slot, accounts_hash, capitalization)) in records.iter().zip(expected) {
+            assert_eq!(record.slot, slot);
+            assert_eq!(record.kind, "full");
+            assert_eq!(record.algorithm, "merkle");
+            assert_eq!(record.hash, accounts_hash.0.to_string());
+            assert_eq!(record.capitalization, capitalization);
+            assert!(record.timestamp > 0);
+        }
+    }
+
+    /// Ensure the shadow lattice-based accounts hash is calculated for merkle-based accounts
+    /// packages, while the merkle-based accounts hash is still the one packaged
+    #[test]
+    fn test_process_accounts_package_compute_shadow_lattice_hash() {
+        let audit_dir = tempfile::TempDir::new().unwrap();
+        let hash_audit_log = audit_dir.path().join("hash_audit.jsonl");
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_config = SnapshotConfig {
+            hash_audit_log: Some(hash_audit_log.clone()),
+            compute_shadow_lattice_hash: true,
+            ..SnapshotConfig::default()
+        };
+        let snapshot_controller =
+            SnapshotController::new(abs_request_sender, snapshot_config.clone(), 0);
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        let accounts_package = new_fss_with_storages();
+        let slot = accounts_package.slot;
+        let (accounts_hash, capitalization) = AccountsHashVerifier::calculate_full_hash_for_bench(
+            &accounts_package,
+            &snapshot_config,
+        );
+        let accounts_lt_hash = AccountsHashVerifier::calculate_accounts_lt_hash(&accounts_package);
+        // the bank kept its accounts lt hash up to date, and the storages agree with it
+        let bank_accounts_lt_hash = accounts_package
+            .snapshot_info
+            .as_ref()
+            .unwrap()
+            .bank_fields_to_serialize
+            .accounts_lt_hash
+            .clone();
+        if let Some(bank_accounts_lt_hash) = bank_accounts_lt_hash {
+            assert_eq!(accounts_lt_hash, bank_accounts_lt_hash);
+        }
+
+        let outcome = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, ProcessOutcome::Packaged);
+        let snapshot_package = pending_snapshot_packages.lock().unwrap().pop().unwrap();
+        assert_eq!(snapshot_package.slot, slot);
+        assert_eq!(snapshot_package.accounts_hash, accounts_hash);
+
+        let audit_log = fs::read_to_string(&hash_audit_log).unwrap();
+        let records: Vec<HashAuditRecord> = audit_log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].algorithm, "merkle");
+        assert_eq!(records[0].hash, accounts_hash.0.to_string());
+        assert_eq!(records[0].capitalization, capitalization);
+        assert_eq!(records[1].slot, slot);
+        assert_eq!(records[1].kind, "full");
+        assert_eq!(records[1].algorithm, "lattice");
+        assert_eq!(records[1].hash, accounts_lt_hash.0.checksum().to_string());
+        assert_eq!(records[1].capitalization, capitalization);
+    }
+
+    /// Ensure a full accounts hash that does not match its full snapshot archive is reported as an
+    /// error, when verifying against archives
+    #[test]
+    fn test_process_accounts_package_verify_against_archives() {
+        let full_snapshot_archives_dir = tempfile::TempDir::new().unwrap();
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_config = SnapshotConfig {
+            full_snapshot_archives_dir: full_snapshot_archives_dir.path().to_path_buf(),
+            verify_against_archives: true,
+            ..SnapshotConfig::default()
+        };
+        let snapshot_controller =
+            SnapshotController::new(abs_request_sender, snapshot_config.clone(), 0);
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+        let write_archive = |slot, hash| {
+            let archive_path = snapshot_utils::build_full_snapshot_archive_path(
+                full_snapshot_archives_dir.path(),
+                slot,
+                &SnapshotHash(hash),
+                ArchiveFormat::TarLz4,
+            );
+            fs::write(&archive_path, []).unwrap();
+            archive_path
+        };
+
+        // matching archive
+        let accounts_package = new_fss_with_storages();
+        let (accounts_hash, _capitalization) = AccountsHashVerifier::calculate_full_hash_for_bench(
+            &accounts_package,
+            &snapshot_config,
+        );
+        let archive_path = write_archive(accounts_package.slot, accounts_hash.0);
+        AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_some());
+        fs::remove_file(archive_path).unwrap();
+
+        // mismatching archive
+        let accounts_package = new_fss_with_storages();
+        let slot = accounts_package.slot;
+        let archive_hash = Hash::new_unique();
+        let archive_path = write_archive(slot, archive_hash);
+        let err = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.get_ref()
+                .unwrap()
+                .downcast_ref::<ArchiveAccountsHashMismatch>(),
+            Some(&ArchiveAccountsHashMismatch {
+                slot,
+                archive_path,
+                archive_hash: SnapshotHash(archive_hash),
+                accounts_hash,
+            }),
+        );
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+    }
+
+    /// Ensure capitalization-only verification checks the capitalization, reports a mismatch as an
+    /// error, and never produces a snapshot package
+    #[test]
+    fn test_process_accounts_package_capitalization_only_verification() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller = SnapshotController::new(
+            abs_request_sender,
+            SnapshotConfig {
+                capitalization_only_verification: true,
+                ..SnapshotConfig::default()
+            },
+            0,
+        );
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+        let (accounts_hash_sender, accounts_hash_receiver) = crossbeam_channel::unbounded();
+
+        let accounts_package = new_fss_with_storages();
+        let outcome = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            Some(&accounts_hash_sender),
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, ProcessOutcome::CapitalizationVerified);
+        assert!(accounts_hash_receiver.try_recv().is_err());
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+
+        let accounts_package = new_fss_with_storages();
+        let slot = accounts_package.slot;
+        let capitalization = accounts_package.expected_capitalization;
+        let accounts_package = AccountsPackage {
+            expected_capitalization: capitalization + 1,
+            ..accounts_package
+        };
+        let err = AccountsHashVerifier::process_accounts_package(
+            accounts_package,
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            Some(&accounts_hash_sender),
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.get_ref()
+                .unwrap()
+                .downcast_ref::<CapitalizationMismatch>(),
+            Some(&CapitalizationMismatch {
+                slot,
+                capitalization,
+                expected_capitalization: capitalization + 1,
+            }),
+        );
+        assert!(accounts_hash_receiver.try_recv().is_err());
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+    }
+
+    /// Ensure scanning for the latest accounts finds the same ones, and only the latest version of
+    /// each, however many passes the scan is split into
+    #[test]
+    fn test_scan_latest_accounts() {
+        let genesis_config_info = create_genesis_config(1_000_000_000);
+        let mint_pubkey = genesis_config_info.mint_keypair.pubkey();
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config_info.genesis_config));
+        bank0.fill_bank_with_ticks_for_tests();
+        bank0.squash();
+        bank0.force_flush_accounts_cache();
+        let bank0_mint_balance = bank0.get_balance(&mint_pubkey);
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::new_unique(), 1);
+        bank1
+            .transfer(
+                1_000_000,
+                &genesis_config_info.mint_keypair,
+                &Pubkey::new_unique(),
+            )
+            .unwrap();
+        bank1.fill_bank_with_ticks_for_tests();
+        bank1.squash();
+        bank1.force_flush_accounts_cache();
+        let storages = bank1.get_snapshot_storages(None);
+        assert_eq!(storages.len(), 2);
+
+        let scan_latest_accounts = |max_accounts_per_pass| {
+            let mut num_passes = 0;
+            let mut latest_accounts = HashMap::new();
+            let mut older_versions = Vec::new();
+            AccountsHashVerifier::scan_latest_accounts(
+                &storages,
+                LATEST_ACCOUNTS_SCAN_BINS,
+                max_accounts_per_pass,
+                |mut pass_latest_accounts| {
+                    num_passes += 1;
+                    for (pubkey, latest_account) in pass_latest_accounts.latest {
+                        // every account is only in a single pass
+                        assert!(latest_accounts.insert(pubkey, latest_account).is_none());
+                    }
+                    older_versions.append(&mut pass_latest_accounts.older);
+                },
+            );
+            (num_passes, latest_accounts, older_versions)
+        };
+        let (num_passes, latest_accounts, older_versions) = scan_latest_accounts(usize::MAX);
+        assert_eq!(num_passes, 1);
+        // the mint was modified in slot 1, so its version from slot 0 is an older one
+        let mint_account = latest_accounts[&mint_pubkey];
+        assert_eq!(mint_account.slot, 1);
+        assert_eq!(mint_account.lamports, bank1.get_balance(&mint_pubkey));
+        assert!(older_versions.iter().any(|older_version| {
+            older_version.slot == 0 && older_version.lamports == bank0_mint_balance
+        }));
+        assert_eq!(
+            latest_accounts
+                .values()
+                .map(|latest_account| latest_account.lamports)
+                .sum::<u64>(),
+            bank1.capitalization(),
+        );
+
+        let (num_passes, multi_pass_latest_accounts, multi_pass_older_versions) =
+            scan_latest_accounts(1);
+        assert_eq!(num_passes, LATEST_ACCOUNTS_SCAN_BINS);
+        assert_eq!(multi_pass_latest_accounts, latest_accounts);
+        assert_eq!(multi_pass_older_versions.len(), older_versions.len());
+    }
+
+    /// Ensure verifying a slot on demand calculates the same accounts hash every time
+    #[test]
+    fn test_verify_slot() {
+        let snapshot_config = SnapshotConfig::default();
+        let accounts_package = new_fss_with_storages();
+        let slot = accounts_package.slot;
+        let accounts = Arc::clone(&accounts_package.accounts);
+        let (expected_accounts_hash, _capitalization) =
+            AccountsHashVerifier::calculate_full_hash_for_bench(
+                &accounts_package,
+                &snapshot_config,
+            );
+
+        let accounts_hash =
+            AccountsHashVerifier::verify_slot(&accounts, slot, &snapshot_config, None).unwrap();
+        assert_eq!(accounts_hash, expected_accounts_hash);
+        assert_eq!(
+            AccountsHashVerifier::verify_slot(&accounts, slot, &snapshot_config, None),
+            Ok(accounts_hash),
+        );
+        // the slot is rooted, so its ancestors do not change which storages are used
+        assert_eq!(
+            AccountsHashVerifier::verify_slot(
+                &accounts,
+                slot,
+                &snapshot_config,
+                Some(Ancestors::from(vec![slot])),
+            ),
+            Ok(accounts_hash),
+        );
+
+        let accounts = AccountsPackage::default_for_tests().accounts;
+        assert_eq!(
+            AccountsHashVerifier::verify_slot(&accounts, slot, &snapshot_config, None),
+            Err(AccountsHashVerifierError::NoStorages(slot)),
+        );
+    }
+
+    /// Ensure verifying a slot that is not rooted with its ancestors leaves out other forks
+    #[test]
+    fn test_verify_slot_with_ancestors() {
+        let snapshot_config = SnapshotConfig::default();
+        let genesis_config_info = create_genesis_config(1_000_000_000);
+        let mint_keypair = &genesis_config_info.mint_keypair;
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config_info.genesis_config));
+        bank0.fill_bank_with_ticks_for_tests();
+        bank0.squash();
+        bank0.force_flush_accounts_cache();
+
+        // Fork off of the root, with different transfers on each fork, and flush both forks
+        // without rooting them.  Slot 1 is the other fork, and slot 2 is the one verified.
+        let new_fork = |slot| {
+            let bank = Bank::new_from_parent(Arc::clone(&bank0), &Pubkey::default(), slot);
+            bank.transfer(1_000, mint_keypair, &Pubkey::new_unique())
+                .unwrap();
+            bank.fill_bank_with_ticks_for_tests();
+            bank.freeze();
+            bank.rc
+                .accounts
+                .accounts_db
+                .flush_accounts_cache_slot_for_tests(slot);
+            bank
+        };
+        let _other_bank = new_fork(1);
+        let bank = new_fork(2);
+        let accounts = &bank.rc.accounts;
+
+        let accounts_hash = AccountsHashVerifier::verify_slot(
+            accounts,
+            bank.slot(),
+            &snapshot_config,
+            Some(bank.ancestors.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            AccountsHashVerifier::verify_slot(
+                accounts,
+                bank.slot(),
+                &snapshot_config,
+                Some(Ancestors::from(vec![0, 2])),
+            ),
+            Ok(accounts_hash),
+        );
+
+        // without ancestors, the other fork's storage is used too
+        let all_forks_accounts_hash =
+            AccountsHashVerifier::verify_slot(accounts, bank.slot(), &snapshot_config, None)
+                .unwrap();
+        assert_ne!(all_forks_accounts_hash, accounts_hash);
+    }
+
+    /// Ensure the incremental snapshot persistence is assembled from the base's accounts hash, and
+    /// that a missing base is reported as an error
+    #[test]
+    fn test_build_incremental_persistence() {
+        let accounts_db = AccountsDb::default_for_tests();
+        let base_slot = 100;
+        let incremental_accounts_hash = IncrementalAccountsHash(Hash::new_unique());
+        let incremental_capitalization = 123;
+
+        assert_eq!(
+            AccountsHashVerifier::build_incremental_persistence(
+                &accounts_db,
+                base_slot,
+                incremental_accounts_hash,
+                incremental_capitalization,
+            ),
+            Err(AccountsHashVerifierError::MissingIncrementalBase(base_slot)),
+        );
+
+        let base_accounts_hash = AccountsHash(Hash::new_unique());
+        let base_capitalization = 456;
+        accounts_db.set_accounts_hash(base_slot, (base_accounts_hash, base_capitalization));
+        let persistence = AccountsHashVerifier::build_incremental_persistence(
+            &accounts_db,
+            base_slot,
+            incremental_accounts_hash,
+            incremental_capitalization,
+        )
+        .unwrap();
+        assert_eq!(
+            persistence,
+            BankIncrementalSnapshotPersistence {
+                full_slot: base_slot,
+                full_hash: base_accounts_hash.into(),
+                full_capitalization: base_capitalization,
+                incremental_hash: incremental_accounts_hash.into(),
+                incremental_capitalization,
+            },
+        );
+    }
+
+    /// Ensure an unexpected EAH accounts package is skipped, without panicking
+    #[test]
+    fn test_process_accounts_package_skips_eah() {
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller =
+            SnapshotController::new(abs_request_sender, SnapshotConfig::default(), 0);
+        let pending_snapshot_packages = Mutex::new(PendingSnapshotPackages::default());
+
+        let outcome = AccountsHashVerifier::process_accounts_package(
+            new_eah(100),
+            &pending_snapshot_packages,
+            &snapshot_controller,
+            None,
+            None,
+            None,
+            &mut ProcessTimings::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, ProcessOutcome::SkippedNonSnapshot);
+        assert!(pending_snapshot_packages.lock().unwrap().pop().is_none());
+    }
+
+    /// Ensure the highest priority accounts package is still selected from a large backlog
+    #[test]
+    fn test_get_next_accounts_package_large_backlog() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+
+        const NUM_FULL_SNAPSHOTS: Slot = 1_000;
+        let mut accounts_packages: Vec<_> = (1..=NUM_FULL_SNAPSHOTS)
+            .flat_map(|i| {
+                let base_slot = i * 100;
+                [
+                    new_fss(base_slot),
+                    new_iss(base_slot + 10, base_slot),
+                    new_iss(base_slot + 20, base_slot),
+                ]
+            })
+            .collect();
+        let num_accounts_packages = accounts_packages.len();
+        accounts_packages.shuffle(&mut rand::thread_rng());
+        accounts_packages
+            .into_iter()
+            .for_each(|accounts_package| accounts_package_sender.send(accounts_package).unwrap());
+
+        // The full snapshot with the highest slot is handled, and only the incremental snapshots
+        // on top of it are re-enqueued
+        let (
+            accounts_package,
+            num_outstanding_accounts_packages,
+            num_re_enqueued_accounts_packages,
+            _selection_us,
+        ) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            accounts_package.package_kind,
+            AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot)
+        );
+        assert_eq!(accounts_package.slot, NUM_FULL_SNAPSHOTS * 100);
+        assert_eq!(num_outstanding_accounts_packages, num_accounts_packages);
+        assert_eq!(num_re_enqueued_accounts_packages, 2);
+
+        // Then the latest incremental snapshot on top of it
+        let (accounts_package, ..) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            accounts_package.package_kind,
+            AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(
+                NUM_FULL_SNAPSHOTS * 100
+            ))
+        );
+        assert_eq!(accounts_package.slot, NUM_FULL_SNAPSHOTS * 100 + 20);
+    }
+
+    /// Ensure accounts packages for slot zero are selected, re-enqueued, and purged without
+    /// over/underflowing
+    #[test]
+    fn test_get_next_accounts_package_slot_zero() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        for accounts_package in [new_fss(0), new_iss(0, 0), new_fss(0), new_iss(1, 0)] {
+            accounts_package_sender.send(accounts_package).unwrap();
+        }
+
+        let mut next_fork_index = usize::MAX;
+        let (accounts_package, num_outstanding, num_re_enqueued, _selection_us) =
+            AccountsHashVerifier::get_next_accounts_package(
+                &accounts_package_sender,
+                &accounts_package_receiver,
+                None,
+                Some(&mut next_fork_index),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            accounts_package.package_kind,
+            AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot),
+        );
+        assert_eq!(accounts_package.slot, 0);
+        assert_eq!(num_outstanding, 4);
+        // only the incremental snapshot for slot 1 is newer than slot 0
+        assert_eq!(num_re_enqueued, 1);
+
+        let (accounts_package, num_outstanding, num_re_enqueued, _selection_us) =
+            AccountsHashVerifier::get_next_accounts_package(
+                &accounts_package_sender,
+                &accounts_package_receiver,
+                Some(0),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(accounts_package.slot, 1);
+        assert_eq!(num_outstanding, 1);
+        assert_eq!(num_re_enqueued, 0);
+        assert!(!AccountsHashVerifier::should_re_enqueue(&new_fss(0), 0));
+
+        let snapshot_config = SnapshotConfig {
+            retained_accounts_hash_slots: u64::MAX,
+            ..SnapshotConfig::default()
+        };
+        AccountsHashVerifier::purge_old_accounts_hashes(
+            &AccountsPackage {
+                accounts_hash_algorithm: AccountsHashAlgorithm::Merkle,
+                ..new_fss(0)
+            },
+            &snapshot_config,
+        );
+    }
+
+    /// Ensure the verifier thread may be named with a suffix, and still exits cleanly, even with
+    /// a niceness adjustment that may not be allowed
+    #[test]
+    fn test_thread_config_name_suffix() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller = Arc::new(SnapshotController::new(
+            abs_request_sender,
+            SnapshotConfig::default(),
+            0,
+        ));
+        let exit = Arc::new(AtomicBool::new(false));
+        let accounts_hash_verifier = AccountsHashVerifier::new(
+            accounts_package_sender,
+            accounts_package_receiver,
+            Arc::new(Mutex::new(PendingSnapshotPackages::default())),
+            exit.clone(),
+            snapshot_controller,
+            None,
+            None,
+            None,
+            AccountsHashVerifierThreadConfig {
+                name_suffix: Some("1".to_string()),
+                niceness_adj: -1,
+            },
+        );
+        assert_eq!(
+            accounts_hash_verifier
+                .t_accounts_hash_verifier
+                .thread()
+                .name(),
+            Some("solAcctHash1"),
+        );
+
+        exit.store(true, Ordering::Relaxed);
+        accounts_hash_verifier.join().unwrap();
+    }
+
+    /// Ensure the verifier thread's name always fits in the 15 bytes Linux keeps
+    #[test]
+    fn test_thread_name() {
+        assert_eq!(AccountsHashVerifier::thread_name(None), "solAcctHashVer");
+        assert_eq!(AccountsHashVerifier::thread_name(Some("")), "solAcctHash");
+        assert_eq!(
+            AccountsHashVerifier::thread_name(Some("12")),
+            "solAcctHash12"
+        );
+        assert_eq!(
+            AccountsHashVerifier::thread_name(Some("12345678")),
+            "solAcctHash1234",
+        );
+        // the suffix is only cut at char boundaries
+        assert_eq!(
+            AccountsHashVerifier::thread_name(Some("1ñ23")),
+            "solAcctHash1ñ2"
+        );
+        assert_eq!(
+            AccountsHashVerifier::thread_name(Some("12ñ")),
+            "solAcctHash12ñ"
+        );
+        assert_eq!(
+            AccountsHashVerifier::thread_name(Some("123ñ")),
+            "solAcctHash123"
+        );
+        assert!(AccountsHashVerifier::thread_name(Some("12345678")).len() <= 15);
+    }
+
+    /// Ensure the verifier reports healthy while running, and unhealthy once it stalls
+    #[test]
+    fn test_is_healthy() {
+        const MAX_STALL: Duration = Duration::from_millis(100);
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let (abs_request_sender, _abs_request_receiver) = crossbeam_channel::unbounded();
+        let snapshot_controller = Arc::new(SnapshotController::new(
+            abs_request_sender,
+            SnapshotConfig::default(),
+            0,
+        ));
+        let exit = Arc::new(AtomicBool::new(false));
+        let accounts_hash_verifier = AccountsHashVerifier::new(
+            accounts_package_sender,
+            accounts_package_receiver,
+            Arc::new(Mutex::new(PendingSnapshotPackages::default())),
+            exit.clone(),
+            snapshot_controller,
+            None,
+            None,
+            None,
+            AccountsHashVerifierThreadConfig::default(),
+        );
+        assert!(accounts_hash_verifier.is_healthy(Duration::from_secs(60)));
+
+        // Stop the thread, so it no longer makes progress, and wait out the max stall
+        exit.store(true, Ordering::Relaxed);
+        while !accounts_hash_verifier
+            .t_accounts_hash_verifier
+            .is_finished()
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        thread::sleep(MAX_STALL * 2);
+        assert!(!accounts_hash_verifier.is_healthy(MAX_STALL));
+
+        accounts_hash_verifier.join().unwrap();
+    }
+
+    /// Ensure a full accounts hash calculation without any storages yields, and stores, the empty
+    /// hash
+    #[test]
+    fn test_calculate_full_accounts_hash_empty_storages() {
+        let accounts_package = new_fss(100);
+        assert!(accounts_package.snapshot_storages.is_empty());
+        let snapshot_config = SnapshotConfig::default();
+
+        let (accounts_hash, capitalization) = AccountsHashVerifier::_calculate_full_accounts_hash(
+            &accounts_package,
+            &snapshot_config,
+        );
+        assert_eq!(capitalization, 0);
+        assert_eq!(
+            accounts_hash,
+            AccountsHash(AccountsHasher::compute_merkle_root(
+                Vec::new(),
+                MERKLE_FANOUT
+            )),
+        );
+        // it is stored, so a later incremental accounts hash finds its base
+        assert_eq!(
+            accounts_package
+                .accounts
+                .accounts_db
+                .get_accounts_hash(accounts_package.slot),
+            Some((accounts_hash, capitalization)),
+        );
+    }
+
+    /// Ensure a full accounts hash calculation without any storages still checks capitalization
+    #[test]
+    #[should_panic(expected = "accounts hash capitalization mismatch")]
+    fn test_calculate_full_accounts_hash_empty_storages_capitalization_mismatch() {
+        let accounts_package = AccountsPackage {
+            expected_capitalization: 1,
+            ..new_fss(100)
+        };
+        assert!(accounts_package.snapshot_storages.is_empty());
+
+        AccountsHashVerifier::_calculate_full_accounts_hash(
+            &accounts_package,
+            &SnapshotConfig::default(),
+        );
+    }
+
+    /// Ensure the benchmark entry point calculates the same full accounts hash as production
+    #[test]
+    fn test_calculate_full_hash_for_bench() {
+        let accounts_package = new_fss_with_storages();
+        let snapshot_config = SnapshotConfig::default();
+
+        let (bench_accounts_hash, bench_capitalization) =
+            AccountsHashVerifier::calculate_full_hash_for_bench(
+                &accounts_package,
+                &snapshot_config,
+            );
+        assert_eq!(
+            bench_capitalization,
+            accounts_package.expected_capitalization
+        );
+        // the bench entry point does not store the accounts hash
+        let accounts_db = &accounts_package.accounts.accounts_db;
+        assert!(accounts_db
+            .get_accounts_hash(accounts_package.slot)
+            .is_none());
+
+        let (accounts_hash, capitalization) = AccountsHashVerifier::_calculate_full_accounts_hash(
+            &accounts_package,
+            &snapshot_config,
+        );
+        assert_eq!(bench_accounts_hash, accounts_hash);
+        assert_eq!(bench_capitalization, capitalization);
+    }
+
+    /// Ensure comparing accounts packages built from the same storages finds equal accounts hashes,
+    /// and that packages with different accounts do not
+    #[test]
+    fn test_compare_packages() {
+        let snapshot_config = SnapshotConfig::default();
+        let accounts_package = new_fss_with_storages();
+        let same_accounts_package = AccountsPackage {
+            slot: accounts_package.slot,
+            snapshot_storages: accounts_package.snapshot_storages.clone(),
+            expected_capitalization: accounts_package.expected_capitalization,
+            accounts: Arc::clone(&accounts_package.accounts),
+            epoch_schedule: accounts_package.epoch_schedule.clone(),
+            ..AccountsPackage::default_for_tests()
+        };
+        let (accounts_hash, same_accounts_hash, equal) = AccountsHashVerifier::compare_packages(
+            &accounts_package,
+            &same_accounts_package,
+            &snapshot_config,
+        );
+        assert!(equal);
+        assert_eq!(accounts_hash, same_accounts_hash);
+        // nothing is stored in the accounts db
+        assert!(accounts_package
+            .accounts
+            .accounts_db
+            .get_accounts_hash(accounts_package.slot)
+            .is_none());
+
+        // a different genesis, so a different mint account
+        let other_accounts_package = new_fss_with_storages();
+        let (compared_accounts_hash, other_accounts_hash, equal) =
+            AccountsHashVerifier::compare_packages(
+                &accounts_package,
+                &other_accounts_package,
+                &snapshot_config,
+            );
+        assert!(!equal);
+        assert_eq!(compared_accounts_hash, accounts_hash);
+        assert_ne!(other_accounts_hash, accounts_hash);
+    }
+
+    /// Ensure detailed debug info may be stored on the initial full accounts hash calculation,
+    /// without changing the calculated accounts hash
+    #[test]
+    fn test_always_store_hash_debug_info() {
+        let accounts_package = new_fss_with_storages();
+        let snapshot_config = SnapshotConfig::default();
+        let debug_snapshot_config = SnapshotConfig {
+            always_store_hash_debug_info: true,
+            ..SnapshotConfig::default()
+        };
+
+        let calculate_accounts_hash_config =
+            AccountsHashVerifier::new_full_calculate_accounts_hash_config(
+                &accounts_package,
+                &snapshot_config,
+            );
+        assert!(!calculate_accounts_hash_config.store_detailed_debug_info_on_failure);
+        let calculate_accounts_hash_config =
+            AccountsHashVerifier::new_full_calculate_accounts_hash_config(
+                &accounts_package,
+                &debug_snapshot_config,
+            );
+        assert!(calculate_accounts_hash_config.store_detailed_debug_info_on_failure);
+
+        let calculate_accounts_hash_config =
+            AccountsHashVerifier::new_full_calculate_accounts_hash_config(
+                &accounts_package,
+                &snapshot_config,
+            );
+        assert!(calculate_accounts_hash_config.progress.is_none());
+        let calculate_accounts_hash_config =
+            AccountsHashVerifier::new_full_calculate_accounts_hash_config(
+                &accounts_package,
+                &SnapshotConfig {
+                    log_full_accounts_hash_progress: true,
+                    ..SnapshotConfig::default()
+                },
+            );
+        assert!(calculate_accounts_hash_config.progress.is_some());
+
+        let accounts_hash = AccountsHashVerifier::_calculate_full_accounts_hash(
+            &accounts_package,
+            &snapshot_config,
+        );
+        let debug_accounts_hash = AccountsHashVerifier::_calculate_full_accounts_hash(
+            &accounts_package,
+            &debug_snapshot_config,
+        );
+        assert_eq!(debug_accounts_hash, accounts_hash);
+    }
+
+    /// Ensure re-enqueuing does not panic if the channel is disconnected, and only successfully
+    /// re-enqueued accounts packages are counted
+    #[test]
+    fn test_get_next_accounts_package_re_enqueue_disconnected() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let (re_enqueue_sender, re_enqueue_receiver) = crossbeam_channel::unbounded();
+        drop(re_enqueue_receiver);
+
+        accounts_package_sender.send(new_fss(100)).unwrap();
+        accounts_package_sender.send(new_iss(110, 100)).unwrap();
+        accounts_package_sender.send(new_iss(120, 100)).unwrap();
+
+        // The full snapshot is handled, and the incrementals fail to be re-enqueued
+        let (
+            accounts_package,
+            num_outstanding_accounts_packages,
+            num_re_enqueued_accounts_packages,
+            _selection_us,
+        ) = AccountsHashVerifier::get_next_accounts_package(
+            &re_enqueue_sender,
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(accounts_package.slot, 100);
+        assert_eq!(num_outstanding_accounts_packages, 3);
+        assert_eq!(num_re_enqueued_accounts_packages, 0);
+    }
+
+    /// Ensure the accounts package for the preferred slot is handled first
+    #[test]
+    fn test_get_next_accounts_package_preferred_slot() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        for accounts_package in [
+            new_fss(100),
+            new_iss(110, 100),
+            new_iss(120, 100),
+            new_iss(130, 100),
+            new_fss(200),
+        ] {
+            accounts_package_sender.send(accounts_package).unwrap();
+        }
+
+        // the preferred slot is handled first, even though the full snapshot at 200 is higher
+        // priority, and greater slots are re-enqueued
+        let (
+            accounts_package,
+            num_outstanding_accounts_packages,
+            num_re_enqueued_accounts_packages,
+            _selection_us,
+        ) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            Some(120),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(accounts_package.slot, 120);
+        assert_eq!(num_outstanding_accounts_packages, 5);
+        assert_eq!(num_re_enqueued_accounts_packages, 2);
+
+        // without a preferred slot, the highest priority is handled
+        let (accounts_package, ..) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(accounts_package.slot, 200);
+
+        // a preferred slot without an accounts package has no effect
+        accounts_package_sender.send(new_fss(300)).unwrap();
+        let (accounts_package, ..) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            Some(250),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(accounts_package.slot, 300);
+    }
+
+    /// Ensure each accounts package is classified by what becomes of it when the next one to
+    /// handle is selected
+    #[test]
+    fn test_classify_accounts_packages() {
+        use AccountsPackageDisposition::{Dropped, Handled, ReEnqueued};
+        let mut remaining_accounts_packages = [
+            new_fss(100),
+            new_iss(110, 100),
+            new_eah(200),
+            new_iss(210, 100),
+            new_fss(300),
+            new_iss(310, 300),
+            new_iss(410, 400),
+            new_iss(420, 400),
+        ];
+        remaining_accounts_packages.shuffle(&mut rand::thread_rng());
+
+        let mut dispositions = AccountsHashVerifier::classify_accounts_packages(
+            &new_fss(400),
+            &remaining_accounts_packages,
+        );
+        assert_eq!(
+            dispositions[0],
+            (
+                400,
+                AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot),
+                Handled
+            )
+        );
+        dispositions.sort_by_key(|(slot, ..)| *slot);
+        let dispositions: Vec<_> = dispositions
+            .into_iter()
+            .map(|(slot, _kind, disposition)| (slot, disposition))
+            .collect();
+        assert_eq!(
+            dispositions,
+            vec![
+                (100, Dropped),
+                (110, Dropped),
+                (200, Dropped),
+                (210, Dropped),
+                (300, Dropped),
+                (310, Dropped),
+                (400, Handled),
+                (410, ReEnqueued),
+                (420, ReEnqueued),
+            ],
+        );
+    }
+
+    /// Ensure an incremental snapshot's base full snapshot is handled first, even when the
+    /// incremental snapshot is preferred
+    #[test]
+    fn test_get_next_accounts_package_base_full_snapshot_first() {
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        accounts_package_sender.send(new_iss(110, 100)).unwrap();
+        accounts_package_sender.send(new_fss(100)).unwrap();
+
+        let (
+            accounts_package,
+            num_outstanding_accounts_packages,
+            num_re_enqueued_accounts_packages,
+            _selection_us,
+        ) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            Some(110),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            accounts_package.package_kind,
+            AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot)
+        );
+        assert_eq!(accounts_package.slot, 100);
+        assert_eq!(num_outstanding_accounts_packages, 2);
+        assert_eq!(num_re_enqueued_accounts_packages, 1);
+
+        let (accounts_package, ..) = AccountsHashVerifier::get_next_accounts_package(
+            &accounts_package_sender,
+            &accounts_package_receiver,
+            Some(110),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            accounts_package.package_kind,
+            AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(100))
+        );
+        assert_eq!(accounts_package.slot, 110);
+        assert!(accounts_package_receiver.is_empty());
+    }
+
+    /// Ensure full snapshots from competing forks are handled in turn when round-robin is enabled
+    #[test]
+    fn test_get_next_accounts_package_round_robin_forks() {
+        let new_fss_for_fork = |slot, bank_hash| {
+            let mut accounts_package = new_fss(slot);
+            accounts_package
+                .snapshot_info
+                .as_mut()
+                .unwrap()
+                .bank_fields_to_serialize
+                .hash = bank_hash;
+            accounts_package
+        };
+        let fork_a = Hash::new_from_array([1; 32]);
+        let fork_b = Hash::new_from_array([2; 32]);
+        let (accounts_package_sender, accounts_package_receiver) = crossbeam_channel::unbounded();
+        let mut next_fork_index = 0;
+
+        for (slot, expected_fork) in [(100, fork_a), (200, fork_b), (300, fork_a), (400, fork_b)] {
+            accounts_package_sender
+                .send(new_fss_for_fork(slot, fork_b))
+                .unwrap();
+            accounts_package_sender
+                .send(new_fss_for_fork(slot, fork_a))
+                .unwrap();
+            let (accounts_package, num_outstanding_accounts_packages, ..) =
+                AccountsHashVerifier::get_next_accounts_package(
+                    &accounts_package_sender,
+                    &accounts_package_receiver,
+                    None,
+                    Some(&mut next_fork_index),
+                    None,
+                )
+                .unwrap();
+            assert_eq!(accounts_package.slot, slot);
+            assert_eq!(num_outstanding_accounts_packages, 2);
+            assert_eq!(
+                AccountsHashVerifier::bank_hash(&accounts_package),
+                Some(expected_fork),
+            );
+            // the other fork's package for the same slot is dropped
+            assert!(accounts_package_receiver.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_load_preferred_slot() {
+        let preferred_slot = AtomicU64::new(NO_PREFERRED_SLOT);
+        assert_eq!(
+            AccountsHashVerifier::load_preferred_slot(&preferred_slot),
+            None
+        );
+        preferred_slot.store(123, Ordering::Relaxed);
+        assert_eq!(
+            AccountsHashVerifier::load_preferred_slot(&preferred_slot),
+            Some(123)
+        );
+    }
 }
@@ -10,17 +10,35 @@ use {
 pub struct PendingSnapshotPackages {
     full: Option<SnapshotPackage>,
     incremental: Option<SnapshotPackage>,
+    /// If set, the maximum number of pending snapshot packages
+    max_pending_snapshot_packages: Option<usize>,
 }
 
 impl PendingSnapshotPackages {
+    /// Creates pending snapshot packages that hold at most `max_pending_snapshot_packages`
+    ///
+    /// Without a maximum, there is at most one pending package of each kind.
+    pub fn new_bounded(max_pending_snapshot_packages: usize) -> Self {
+        assert!(
+            max_pending_snapshot_packages > 0,
+            "at least one snapshot package must be allowed to be pending",
+        );
+        Self {
+            max_pending_snapshot_packages: Some(max_pending_snapshot_packages),
+            ..Self::default()
+        }
+    }
+
     /// Adds `snapshot_package` as a pending snapshot package
     ///
-    /// This will overwrite currently-pending in-kind packages.
+    /// This will overwrite currently-pending in-kind packages.  If bounded, the lowest priority
+    /// packages are then evicted until within the maximum.  Returns the overwritten and evicted
+    /// packages.
     ///
     /// Note: This function will panic if `snapshot_package` is *older*
     /// than any currently-pending in-kind packages.
-    pub fn push(&mut self, snapshot_package: SnapshotPackage) {
-        match snapshot_package.snapshot_kind {
+    pub fn push(&mut self, snapshot_package: SnapshotPackage) -> Vec<SnapshotPackage> {
+        let overwritten = match snapshot_package.snapshot_kind {
             SnapshotKind::FullSnapshot => {
                 if let Some(pending_full_snapshot_package) = self.full.as_ref() {
                     // snapshots are monotonically increasing; only overwrite *old* packages
@@ -41,7 +59,7 @@ impl PendingSnapshotPackages {
                         pending_full_snapshot_package.slot, snapshot_package.slot,
                     );
                 }
-                self.full = Some(snapshot_package)
+                self.full.replace(snapshot_package)
             }
             SnapshotKind::IncrementalSnapshot(_) => {
                 if let Some(pending_incremental_snapshot_package) = self.incremental.as_ref() {
@@ -64,9 +82,29 @@ impl PendingSnapshotPackages {
                         pending_incremental_snapshot_package.slot, snapshot_package.slot,
                     );
                 }
-                self.incremental = Some(snapshot_package)
+                self.incremental.replace(snapshot_package)
+            }
+        };
+
+        let mut evicted: Vec<_> = overwritten.into_iter().collect();
+        if let Some(max_pending_snapshot_packages) = self.max_pending_snapshot_packages {
+            while self.len() > max_pending_snapshot_packages {
+                // Evict the lowest priority package.  Full snapshot packages are higher priority
+                // than incrementals, so a full is only evicted once there is no pending
+                // incremental that may depend on it.
+                let Some(snapshot_package) = self.incremental.take().or_else(|| self.full.take())
+                else {
+                    break;
+                };
+                evicted.push(snapshot_package);
             }
         }
+        evicted
+    }
+
+    /// Returns the number of pending snapshot packages
+    fn len(&self) -> usize {
+        usize::from(self.full.is_some()) + usize::from(self.incremental.is_some())
     }
 
     /// Returns the next pending snapshot package to handle
@@ -199,6 +237,7 @@ mod tests {
         let mut pending_snapshot_packages = PendingSnapshotPackages {
             full: Some(new_full(slot)),
             incremental: None,
+            ..PendingSnapshotPackages::default()
         };
 
         // pushing an older full should panic
@@ -213,6 +252,7 @@ mod tests {
         let mut pending_snapshot_packages = PendingSnapshotPackages {
             full: None,
             incremental: Some(new_incr(slot, base)),
+            ..PendingSnapshotPackages::default()
         };
 
         // pushing an older incremental should panic
@@ -275,6 +315,7 @@ mod tests {
         let mut pending_snapshot_packages = PendingSnapshotPackages {
             full: Some(new_incr(110, 100)), // <-- invalid! `full` is IncrementalSnapshot
             incremental: None,
+            ..PendingSnapshotPackages::default()
         };
         pending_snapshot_packages.pop();
     }
@@ -285,7 +326,53 @@ mod tests {
         let mut pending_snapshot_packages = PendingSnapshotPackages {
             full: None,
             incremental: Some(new_full(100)), // <-- invalid! `incremental` is FullSnapshot
+            ..PendingSnapshotPackages::default()
         };
         pending_snapshot_packages.pop();
     }
+
+    #[test]
+    fn test_push_bounded() {
+        let mut pending_snapshot_packages = PendingSnapshotPackages::new_bounded(1);
+
+        // a full snapshot package fits
+        assert!(pending_snapshot_packages.push(new_full(100)).is_empty());
+
+        // an incremental is lower priority than the full it depends on, so it is evicted
+        let evicted = pending_snapshot_packages.push(new_incr(110, 100));
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].slot, 110);
+        assert_eq!(pending_snapshot_packages.full.as_ref().unwrap().slot, 100);
+        assert!(pending_snapshot_packages.incremental.is_none());
+
+        // a newer full overwrites the older one
+        let evicted = pending_snapshot_packages.push(new_full(200));
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].slot, 100);
+
+        // once the full is handled, an incremental fits
+        assert_eq!(pending_snapshot_packages.pop().unwrap().slot, 200);
+        assert!(pending_snapshot_packages
+            .push(new_incr(210, 200))
+            .is_empty());
+        assert_eq!(
+            pending_snapshot_packages.incremental.as_ref().unwrap().slot,
+            210
+        );
+
+        // a full evicts the pending incremental, rather than being evicted
+        let evicted = pending_snapshot_packages.push(new_full(300));
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].slot, 210);
+        assert_eq!(pending_snapshot_packages.pop().unwrap().slot, 300);
+        assert!(pending_snapshot_packages.pop().is_none());
+
+        // without a bound, both kinds are kept
+        let mut pending_snapshot_packages = PendingSnapshotPackages::default();
+        assert!(pending_snapshot_packages.push(new_full(100)).is_empty());
+        assert!(pending_snapshot_packages
+            .push(new_incr(110, 100))
+            .is_empty());
+        assert_eq!(pending_snapshot_packages.len(), 2);
+    }
 }
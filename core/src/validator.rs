@@ -3,7 +3,7 @@
 pub use solana_perf::report_target_features;
 use {
     crate::{
-        accounts_hash_verifier::AccountsHashVerifier,
+        accounts_hash_verifier::{AccountsHashVerifier, AccountsHashVerifierThreadConfig},
         admin_rpc_post_init::{AdminRpcRequestMetadataPostInit, KeyUpdaterType, KeyUpdaters},
         banking_trace::{self, BankingTracer, TraceError},
         cluster_info_vote_listener::VoteTracker,
@@ -900,6 +900,10 @@ impl Validator {
             pending_snapshot_packages,
             exit.clone(),
             snapshot_controller.clone(),
+            None,
+            None,
+            None,
+            AccountsHashVerifierThreadConfig::default(),
         );
         let snapshot_request_handler = SnapshotRequestHandler {
             snapshot_controller: snapshot_controller.clone(),
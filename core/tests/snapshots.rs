@@ -8,7 +8,7 @@ use {
     solana_accounts_db::accounts_db::{AccountsDbConfig, ACCOUNTS_DB_CONFIG_FOR_TESTING},
     solana_clock::Slot,
     solana_core::{
-        accounts_hash_verifier::AccountsHashVerifier,
+        accounts_hash_verifier::{AccountsHashVerifier, AccountsHashVerifierThreadConfig},
         snapshot_packager_service::{PendingSnapshotPackages, SnapshotPackagerService},
     },
     solana_genesis_config::GenesisConfig,
@@ -668,6 +668,10 @@ fn test_snapshots_with_background_services(verify_snapshot_hash_kind: VerifySnap
         pending_snapshot_packages,
         exit.clone(),
         snapshot_controller.clone(),
+        None,
+        None,
+        None,
+        AccountsHashVerifierThreadConfig::default(),
     );
 
     let accounts_background_service =
@@ -9,8 +9,9 @@ use {
     },
     solana_clock::Slot,
     solana_core::{
-        accounts_hash_verifier::AccountsHashVerifier,
-        snapshot_packager_service::PendingSnapshotPackages, validator::BlockVerificationMethod,
+        accounts_hash_verifier::{AccountsHashVerifier, AccountsHashVerifierThreadConfig},
+        snapshot_packager_service::PendingSnapshotPackages,
+        validator::BlockVerificationMethod,
     },
     solana_genesis_config::GenesisConfig,
     solana_geyser_plugin_manager::geyser_plugin_service::{
@@ -389,6 +390,10 @@ pub fn load_and_process_ledger(
         pending_snapshot_packages,
         exit.clone(),
         snapshot_controller.clone(),
+        None,
+        None,
+        None,
+        AccountsHashVerifierThreadConfig::default(),
     );
     let snapshot_request_handler = SnapshotRequestHandler {
         snapshot_controller: snapshot_controller.clone(),
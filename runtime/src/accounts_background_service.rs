@@ -340,6 +340,7 @@ impl SnapshotRequestHandler {
                         epoch_schedule: snapshot_root_bank.epoch_schedule(),
                         epoch: snapshot_root_bank.epoch(),
                         store_detailed_debug_info_on_failure: false,
+                        progress: None,
                     },
                 );
             assert_eq!(previous_accounts_hash, this_accounts_hash);
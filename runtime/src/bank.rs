@@ -47,7 +47,9 @@ use {
         rent_collector::RentCollectorWithMetrics,
         runtime_config::RuntimeConfig,
         serde_snapshot::BankIncrementalSnapshotPersistence,
+        snapshot_config::SnapshotConfig,
         snapshot_hash::SnapshotHash,
+        snapshot_utils::SnapshotInterval,
         stake_account::StakeAccount,
         stake_weighted_timestamp::{
             calculate_stake_weighted_timestamp, MaxAllowableDrift,
@@ -194,7 +196,12 @@ use {
     solana_nonce_account::{get_system_account_kind, SystemAccountKind},
     solana_program_runtime::{loaded_programs::ProgramCacheForTxBatch, sysvar_cache::SysvarCache},
 };
-pub use {partitioned_epoch_rewards::KeyedRewardsAndNumPartitions, solana_reward_info::RewardType};
+pub use {
+    partitioned_epoch_rewards::KeyedRewardsAndNumPartitions,
+    solana_reward_info::RewardType,
+    stake_utils::{StakeSplitError, StakeWithdrawError},
+    vote_utils::TowerSyncError,
+};
 
 /// params to `verify_accounts_hash`
 struct VerifyAccountsHashConfig {
@@ -213,8 +220,10 @@ mod metrics;
 pub(crate) mod partitioned_epoch_rewards;
 mod recent_blockhashes_account;
 mod serde_snapshot;
+mod stake_utils;
 mod sysvar_cache;
 pub(crate) mod tests;
+mod vote_utils;
 
 pub const SECONDS_PER_YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0;
 
@@ -1596,6 +1605,13 @@ impl Bank {
             .new_warmup_cooldown_rate_epoch(&self.epoch_schedule)
     }
 
+    /// Epoch in which the new cooldown warmup rate for stake was activated, if it has been
+    ///
+    /// This is the epoch to pass to stake calculations, such as `Delegation::stake()`.
+    pub fn warmup_cooldown_rate_epoch(&self) -> Option<Epoch> {
+        self.new_warmup_cooldown_rate_epoch()
+    }
+
     /// process for the start of a new epoch
     fn process_new_epoch(
         &mut self,
@@ -2769,6 +2785,14 @@ impl Bank {
         self.rent_collector.rent.minimum_balance(data_len).max(1)
     }
 
+    /// Returns the minimum balance for an account with `data_len` bytes of data to be rent exempt
+    ///
+    /// Unlike `get_minimum_balance_for_rent_exemption()`, this is exactly the rent's minimum
+    /// balance, even if that is zero.
+    pub fn rent_exempt_minimum(&self, data_len: usize) -> u64 {
+        self.rent_collector.rent.minimum_balance(data_len)
+    }
+
     pub fn get_lamports_per_signature(&self) -> u64 {
         self.fee_rate_governor.lamports_per_signature
     }
@@ -4379,6 +4403,33 @@ impl Bank {
         )
     }
 
+    /// Returns up to `limit` accounts whose balance is below the rent-exempt minimum for their
+    /// size, along with their balance, sorted by address
+    ///
+    /// New accounts must be rent-exempt, so these are leftovers worth cleaning up.  Accounts
+    /// without any lamports do not exist, so they are not included.
+    pub fn rent_paying_accounts(&self, limit: usize) -> ScanResult<Vec<(Pubkey, u64)>> {
+        let rent = &self.rent_collector.rent;
+        let mut rent_paying_accounts = Vec::new();
+        self.scan_all_accounts(
+            |pubkey_account_slot| {
+                if rent_paying_accounts.len() >= limit {
+                    return;
+                }
+                let Some((pubkey, account, _slot)) = pubkey_account_slot else {
+                    return;
+                };
+                if account.lamports() != 0
+                    && !rent.is_exempt(account.lamports(), account.data().len())
+                {
+                    rent_paying_accounts.push((*pubkey, account.lamports()));
+                }
+            },
+            true,
+        )?;
+        Ok(rent_paying_accounts)
+    }
+
     /// Return the accumulated executed transaction count
     pub fn transaction_count(&self) -> u64 {
         self.transaction_count.load(Relaxed)
@@ -4995,6 +5046,7 @@ impl Bank {
             epoch_schedule: &self.epoch_schedule,
             epoch: self.epoch,
             store_detailed_debug_info_on_failure: false,
+            progress: None,
         };
         let storages = self.get_snapshot_storages(Some(base_slot));
         let sorted_storages = SortedStorages::new(&storages);
@@ -5143,6 +5195,21 @@ impl Bank {
         self.block_height
     }
 
+    /// Returns the first slot, at or after this bank's slot, that is a multiple of the full
+    /// snapshot interval in `snapshot_config`
+    ///
+    /// Returns None if full snapshots are disabled.  Note that snapshot requests are triggered by
+    /// block height, so this matches the slot of the next full snapshot only if no slots are
+    /// skipped.
+    pub fn next_full_snapshot_slot(&self, snapshot_config: &SnapshotConfig) -> Option<Slot> {
+        match snapshot_config.full_snapshot_archive_interval {
+            SnapshotInterval::Disabled => None,
+            SnapshotInterval::Slots(interval) => {
+                self.slot().checked_next_multiple_of(interval.get())
+            }
+        }
+    }
+
     /// Return the number of slots per epoch for the given epoch
     pub fn get_slots_in_epoch(&self, epoch: Epoch) -> u64 {
         self.epoch_schedule().get_slots_in_epoch(epoch)
@@ -0,0 +1,627 @@
+use {
+    super::Bank,
+    crate::{inflation_rewards::commission_split, stake_account::StakeAccount},
+    solana_account::ReadableAccount,
+    solana_accounts_db::accounts_index::{ScanConfig, ScanResult},
+    solana_clock::Epoch,
+    solana_pubkey::Pubkey,
+    solana_stake_interface::{
+        self as stake,
+        state::{warmup_cooldown_rate, Delegation, Lockup, StakeStateV2},
+    },
+    std::cmp::Reverse,
+    thiserror::Error,
+};
+
+/// Reasons a stake split would be rejected by the stake program
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum StakeSplitError {
+    #[error("stake account not found")]
+    AccountNotFound,
+    #[error("account is not an initialized or delegated stake account")]
+    InvalidStakeAccount,
+    #[error("split amount must be nonzero and at most the source balance")]
+    InsufficientFunds,
+    #[error("source would be left with {remaining} lamports, below its minimum of {minimum}")]
+    SourceBelowMinimum { remaining: u64, minimum: u64 },
+    #[error("destination would receive {lamports} lamports, below its minimum of {minimum}")]
+    DestinationBelowMinimum { lamports: u64, minimum: u64 },
+    #[error("splitting active stake requires a prefunded destination")]
+    DestinationNotPrefunded,
+    #[error("split would leave a delegation below the minimum delegation")]
+    InsufficientDelegation,
+}
+
+/// Reasons a stake withdrawal would be rejected by the stake program
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum StakeWithdrawError {
+    #[error("stake account not found")]
+    AccountNotFound,
+    #[error("account is not a stake account")]
+    InvalidStakeAccount,
+    #[error("withdrawer is not the stake account's withdraw authority")]
+    Unauthorized,
+    #[error("lockup is in force and the custodian did not authorize the withdrawal")]
+    LockupInForce,
+    #[error("withdrawal exceeds the unstaked balance above the rent-exempt reserve")]
+    InsufficientFunds,
+}
+
+/// How a stake account may be merged, per the stake program's merge rules
+enum StakeMergeKind {
+    /// Undelegated, or fully deactivated
+    Inactive,
+    /// Delegated in the current epoch, and not yet effective
+    ActivationEpoch(Delegation),
+    /// Fully effective, and not deactivating
+    FullyActive(Delegation),
+}
+
+impl StakeMergeKind {
+    fn can_merge_with(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Inactive, Self::Inactive)
+            | (Self::Inactive, Self::ActivationEpoch(_))
+            | (Self::ActivationEpoch(_), Self::Inactive) => true,
+            (Self::ActivationEpoch(delegation), Self::ActivationEpoch(other_delegation))
+            | (Self::FullyActive(delegation), Self::FullyActive(other_delegation)) => {
+                delegation.voter_pubkey == other_delegation.voter_pubkey
+                    && delegation.deactivation_epoch == Epoch::MAX
+                    && other_delegation.deactivation_epoch == Epoch::MAX
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Bank {
+    /// Returns the total effective stake delegated to `vote_pubkey` at `epoch`
+    ///
+    /// Sums the effective stake of every stake account delegated to the vote account, using the
+    /// stake history to account for warmup and cooldown.
+    pub fn active_stake_for_vote(&self, vote_pubkey: &Pubkey, epoch: Epoch) -> u64 {
+        let new_warmup_cooldown_rate_epoch = self.new_warmup_cooldown_rate_epoch();
+        let stakes = self.stakes_cache.stakes();
+        let stake_history = stakes.history();
+        stakes
+            .stake_delegations()
+            .values()
+            .map(StakeAccount::delegation)
+            .filter(|delegation| &delegation.voter_pubkey == vote_pubkey)
+            .map(|delegation| {
+                delegation.stake(epoch, stake_history, new_warmup_cooldown_rate_epoch)
+            })
+            .sum()
+    }
+
+    /// Returns the total effective stake delegated to all vote accounts at `epoch`
+    ///
+    /// Uses the stake history to account for warmup and cooldown, so fully deactivated stakes
+    /// contribute nothing.
+    pub fn total_active_stake(&self, epoch: Epoch) -> u64 {
+        let new_warmup_cooldown_rate_epoch = self.new_warmup_cooldown_rate_epoch();
+        let stakes = self.stakes_cache.stakes();
+        let stake_history = stakes.history();
+        stakes
+            .stake_delegations()
+            .values()
+            .map(|stake_account| {
+                stake_account.delegation().stake(
+                    epoch,
+                    stake_history,
+                    new_warmup_cooldown_rate_epoch,
+                )
+            })
+            .sum()
+    }
+
+    /// Returns the stake weight of each node identity in the leader schedule for `epoch`
+    ///
+    /// The stake delegated to each vote account in the epoch stakes is summed per the vote
+    /// account's node identity.  Sorted by stake, largest first, then by identity.  Empty if there
+    /// are no epoch stakes for `epoch`.
+    pub fn epoch_stake_weights(&self, epoch: Epoch) -> Vec<(Pubkey, u64)> {
+        let Some(staked_nodes) = self.epoch_staked_nodes(epoch) else {
+            return Vec::new();
+        };
+        let mut stake_weights: Vec<_> = staked_nodes
+            .iter()
+            .map(|(node_pubkey, stake)| (*node_pubkey, *stake))
+            .collect();
+        stake_weights.sort_unstable_by_key(|(node_pubkey, stake)| (Reverse(*stake), *node_pubkey));
+        stake_weights
+    }
+
+    /// Returns the stake accounts delegated to `vote_pubkey`, sorted by address
+    ///
+    /// Includes stakes that are still activating or deactivating.
+    pub fn stakes_delegated_to(&self, vote_pubkey: &Pubkey) -> Vec<Pubkey> {
+        let stakes = self.stakes_cache.stakes();
+        let mut stake_pubkeys: Vec<_> = stakes
+            .stake_delegations()
+            .iter()
+            .filter(|(_stake_pubkey, stake_account)| {
+                &stake_account.delegation().voter_pubkey == vote_pubkey
+            })
+            .map(|(stake_pubkey, _stake_account)| *stake_pubkey)
+            .collect();
+        stake_pubkeys.sort_unstable();
+        stake_pubkeys
+    }
+
+    /// Returns the stake accounts delegated to vote accounts that no longer exist, sorted by
+    /// address
+    ///
+    /// E.g. if a vote account is closed, the stakes delegated to it earn no more rewards until
+    /// they are redelegated.
+    pub fn orphaned_stakes(&self) -> Vec<Pubkey> {
+        let stakes = self.stakes_cache.stakes();
+        let vote_accounts = stakes.vote_accounts();
+        let mut stake_pubkeys: Vec<_> = stakes
+            .stake_delegations()
+            .iter()
+            .filter(|(_stake_pubkey, stake_account)| {
+                vote_accounts
+                    .get(&stake_account.delegation().voter_pubkey)
+                    .is_none()
+            })
+            .map(|(stake_pubkey, _stake_account)| *stake_pubkey)
+            .collect();
+        stake_pubkeys.sort_unstable();
+        stake_pubkeys
+    }
+
+    /// Returns the fewest lamports that can be split off of a delegated stake account into a new
+    /// stake account
+    ///
+    /// The new stake account must be rent-exempt, and its delegation must be at least the minimum
+    /// delegation.  See [`Self::can_split_stake`] for the other requirements of a split.
+    pub fn minimum_split_amount(&self) -> u64 {
+        let minimum_delegation = solana_stake_program::get_minimum_delegation(
+            self.feature_set
+                .is_active(&agave_feature_set::stake_raise_minimum_delegation_to_1_sol::id()),
+        );
+        self.rent_exempt_minimum(StakeStateV2::size_of())
+            .saturating_add(minimum_delegation)
+    }
+
+    /// Checks whether splitting `lamports` from the stake account at `source` into a new, unfunded
+    /// stake account would succeed
+    ///
+    /// Mirrors the stake program's split checks: the source must keep at least its rent-exempt
+    /// reserve plus the minimum delegation (unless it is emptied), and the destination must end
+    /// up rent-exempt with at least the minimum delegation.
+    pub fn can_split_stake(&self, source: &Pubkey, lamports: u64) -> Result<(), StakeSplitError> {
+        let account = self
+            .get_account(source)
+            .ok_or(StakeSplitError::AccountNotFound)?;
+        if !stake::program::check_id(account.owner()) {
+            return Err(StakeSplitError::InvalidStakeAccount);
+        }
+        let (meta, stake) = match account.deserialize_data::<StakeStateV2>() {
+            Ok(StakeStateV2::Initialized(meta)) => (meta, None),
+            Ok(StakeStateV2::Stake(meta, stake, _stake_flags)) => (meta, Some(stake)),
+            _ => return Err(StakeSplitError::InvalidStakeAccount),
+        };
+
+        if lamports == 0 || lamports > account.lamports() {
+            return Err(StakeSplitError::InsufficientFunds);
+        }
+
+        // Only delegated stake has to maintain the minimum delegation
+        let minimum_delegation = if stake.is_some() {
+            solana_stake_program::get_minimum_delegation(
+                self.feature_set
+                    .is_active(&agave_feature_set::stake_raise_minimum_delegation_to_1_sol::id()),
+            )
+        } else {
+            0
+        };
+
+        let source_minimum_balance = meta.rent_exempt_reserve.saturating_add(minimum_delegation);
+        let source_remaining_balance = account.lamports().saturating_sub(lamports);
+        if source_remaining_balance != 0 && source_remaining_balance < source_minimum_balance {
+            return Err(StakeSplitError::SourceBelowMinimum {
+                remaining: source_remaining_balance,
+                minimum: source_minimum_balance,
+            });
+        }
+
+        let destination_rent_exempt_reserve = self.rent_exempt_minimum(StakeStateV2::size_of());
+        let destination_minimum_balance =
+            destination_rent_exempt_reserve.saturating_add(minimum_delegation);
+        if lamports < destination_minimum_balance {
+            return Err(StakeSplitError::DestinationBelowMinimum {
+                lamports,
+                minimum: destination_minimum_balance,
+            });
+        }
+
+        if let Some(stake) = stake {
+            let is_active = {
+                let stakes = self.stakes_cache.stakes();
+                stake
+                    .delegation
+                    .stake_activating_and_deactivating(
+                        self.epoch(),
+                        stakes.history(),
+                        self.new_warmup_cooldown_rate_epoch(),
+                    )
+                    .effective
+                    > 0
+            };
+            if is_active && source_remaining_balance != 0 {
+                return Err(StakeSplitError::DestinationNotPrefunded);
+            }
+
+            let split_stake_amount = if source_remaining_balance == 0 {
+                lamports.saturating_sub(meta.rent_exempt_reserve)
+            } else {
+                if stake.delegation.stake.saturating_sub(lamports) < minimum_delegation {
+                    return Err(StakeSplitError::InsufficientDelegation);
+                }
+                lamports.saturating_sub(destination_rent_exempt_reserve)
+            };
+            if split_stake_amount < minimum_delegation {
+                return Err(StakeSplitError::InsufficientDelegation);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stake accounts whose lockup is still in force, sorted by address
+    ///
+    /// Lockups are checked against the current epoch and unix timestamp from the clock sysvar,
+    /// ignoring custodians.
+    pub fn locked_stakes(&self) -> ScanResult<Vec<(Pubkey, Lockup)>> {
+        let clock = self.clock();
+        let mut locked_stakes: Vec<_> = self
+            .get_program_accounts(&stake::program::id(), &ScanConfig::default())?
+            .into_iter()
+            .filter_map(|(pubkey, account)| {
+                let lockup = account.deserialize_data::<StakeStateV2>().ok()?.lockup()?;
+                lockup.is_in_force(&clock, None).then_some((pubkey, lockup))
+            })
+            .collect();
+        locked_stakes.sort_unstable_by_key(|(pubkey, _lockup)| *pubkey);
+        Ok(locked_stakes)
+    }
+
+    /// Returns the stake accounts whose authorized withdrawer is `withdrawer`, sorted by address
+    pub fn stakes_by_withdrawer(&self, withdrawer: &Pubkey) -> ScanResult<Vec<Pubkey>> {
+        let mut stakes: Vec<_> = self
+            .get_program_accounts(&stake::program::id(), &ScanConfig::default())?
+            .into_iter()
+            .filter_map(|(pubkey, account)| {
+                let authorized = account
+                    .deserialize_data::<StakeStateV2>()
+                    .ok()?
+                    .authorized()?;
+                (&authorized.withdrawer == withdrawer).then_some(pubkey)
+            })
+            .collect();
+        stakes.sort_unstable();
+        Ok(stakes)
+    }
+
+    /// Returns the pairs of stake accounts staked by `authority` that could be merged, sorted by
+    /// address
+    ///
+    /// Mirrors the stake program's merge checks: both accounts must have the same authorities, and
+    /// the same lockup unless neither lockup is in force.  Stakes that are partially activated or
+    /// deactivating cannot be merged.  Delegated stakes may only be merged with stakes delegated to
+    /// the same vote account, in the same activation state.
+    pub fn mergeable_stakes(&self, authority: &Pubkey) -> ScanResult<Vec<(Pubkey, Pubkey)>> {
+        let stake_accounts =
+            self.get_program_accounts(&stake::program::id(), &ScanConfig::default())?;
+        let clock = self.clock();
+        let new_warmup_cooldown_rate_epoch = self.new_warmup_cooldown_rate_epoch();
+        let stakes = self.stakes_cache.stakes();
+        let mut candidates: Vec<_> = stake_accounts
+            .into_iter()
+            .filter_map(|(pubkey, account)| {
+                let (meta, merge_kind) = match account.deserialize_data::<StakeStateV2>().ok()? {
+                    StakeStateV2::Initialized(meta) => (meta, StakeMergeKind::Inactive),
+                    StakeStateV2::Stake(meta, stake, _stake_flags) => {
+                        let status = stake.delegation.stake_activating_and_deactivating(
+                            clock.epoch,
+                            stakes.history(),
+                            new_warmup_cooldown_rate_epoch,
+                        );
+                        let merge_kind =
+                            match (status.effective, status.activating, status.deactivating) {
+                                (0, 0, 0) => StakeMergeKind::Inactive,
+                                (0, _, _) => StakeMergeKind::ActivationEpoch(stake.delegation),
+                                (_, 0, 0) => StakeMergeKind::FullyActive(stake.delegation),
+                                // Transient stakes cannot be merged
+                                _ => return None,
+                            };
+                        (meta, merge_kind)
+                    }
+                    _ => return None,
+                };
+                (&meta.authorized.staker == authority).then_some((pubkey, meta, merge_kind))
+            })
+            .collect();
+        candidates.sort_unstable_by_key(|(pubkey, _meta, _merge_kind)| *pubkey);
+
+        let mut mergeable_stakes = Vec::new();
+        for (i, (pubkey, meta, merge_kind)) in candidates.iter().enumerate() {
+            for (other_pubkey, other_meta, other_merge_kind) in &candidates[i + 1..] {
+                let metas_can_merge = meta.authorized == other_meta.authorized
+                    && (meta.lockup == other_meta.lockup
+                        || (!meta.lockup.is_in_force(&clock, None)
+                            && !other_meta.lockup.is_in_force(&clock, None)));
+                if metas_can_merge && merge_kind.can_merge_with(other_merge_kind) {
+                    mergeable_stakes.push((*pubkey, *other_pubkey));
+                }
+            }
+        }
+        Ok(mergeable_stakes)
+    }
+
+    /// Checks whether `withdrawer` could withdraw `lamports` from the stake account at `stake`
+    ///
+    /// Mirrors the stake program's withdraw checks.  If `custodian` is the lockup's custodian,
+    /// the lockup is bypassed, as if the custodian signed the withdrawal.  Delegated stake that
+    /// has not fully cooled down, plus the rent-exempt reserve, may not be withdrawn, unless the
+    /// account is unstaked and withdrawn in full.
+    pub fn can_withdraw_stake(
+        &self,
+        stake: &Pubkey,
+        withdrawer: &Pubkey,
+        custodian: Option<&Pubkey>,
+        lamports: u64,
+    ) -> Result<(), StakeWithdrawError> {
+        let account = self
+            .get_account(stake)
+            .ok_or(StakeWithdrawError::AccountNotFound)?;
+        if !stake::program::check_id(account.owner()) {
+            return Err(StakeWithdrawError::InvalidStakeAccount);
+        }
+        let clock = self.clock();
+        let (lockup, reserve, is_staked) = match account.deserialize_data::<StakeStateV2>() {
+            Ok(StakeStateV2::Stake(meta, stake, _stake_flags)) => {
+                if &meta.authorized.withdrawer != withdrawer {
+                    return Err(StakeWithdrawError::Unauthorized);
+                }
+                let staked = self.unwithdrawable_stake(&stake.delegation, clock.epoch);
+                (
+                    meta.lockup,
+                    staked.saturating_add(meta.rent_exempt_reserve),
+                    staked != 0,
+                )
+            }
+            Ok(StakeStateV2::Initialized(meta)) => {
+                if &meta.authorized.withdrawer != withdrawer {
+                    return Err(StakeWithdrawError::Unauthorized);
+                }
+                (meta.lockup, meta.rent_exempt_reserve, false)
+            }
+            Ok(StakeStateV2::Uninitialized) => {
+                if withdrawer != stake {
+                    return Err(StakeWithdrawError::Unauthorized);
+                }
+                (Lockup::default(), 0, false)
+            }
+            _ => return Err(StakeWithdrawError::InvalidStakeAccount),
+        };
+
+        if lockup.is_in_force(&clock, custodian) {
+            return Err(StakeWithdrawError::LockupInForce);
+        }
+
+        if lamports > account.lamports() {
+            return Err(StakeWithdrawError::InsufficientFunds);
+        }
+        if lamports == account.lamports() {
+            // Active stake accounts may not be closed
+            if is_staked {
+                return Err(StakeWithdrawError::InsufficientFunds);
+            }
+        } else if lamports.saturating_add(reserve) > account.lamports() {
+            return Err(StakeWithdrawError::InsufficientFunds);
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many lamports the stake account at `stake` could withdraw, without closing it
+    ///
+    /// This is the balance, minus the delegated stake that has not fully cooled down, minus the
+    /// rent-exempt reserve.  Returns 0 if the lockup is in force, or if there is no such stake
+    /// account.
+    pub fn withdrawable_stake_lamports(&self, stake: &Pubkey) -> u64 {
+        let Some(account) = self.get_account(stake) else {
+            return 0;
+        };
+        if !stake::program::check_id(account.owner()) {
+            return 0;
+        }
+        let clock = self.clock();
+        let (lockup, reserve) = match account.deserialize_data::<StakeStateV2>() {
+            Ok(StakeStateV2::Stake(meta, stake, _stake_flags)) => {
+                let staked = self.unwithdrawable_stake(&stake.delegation, clock.epoch);
+                (meta.lockup, staked.saturating_add(meta.rent_exempt_reserve))
+            }
+            Ok(StakeStateV2::Initialized(meta)) => (meta.lockup, meta.rent_exempt_reserve),
+            Ok(StakeStateV2::Uninitialized) => (Lockup::default(), 0),
+            _ => return 0,
+        };
+        if lockup.is_in_force(&clock, None) {
+            return 0;
+        }
+        account.lamports().saturating_sub(reserve)
+    }
+
+    /// Returns how much of `delegation` may not be withdrawn at `epoch`
+    ///
+    /// Assumes the full stake unless cooling down, as in the stake program.
+    fn unwithdrawable_stake(&self, delegation: &Delegation, epoch: Epoch) -> u64 {
+        if epoch >= delegation.deactivation_epoch {
+            delegation.stake(
+                epoch,
+                self.stakes_cache.stakes().history(),
+                self.new_warmup_cooldown_rate_epoch(),
+            )
+        } else {
+            delegation.stake
+        }
+    }
+
+    /// Returns the fraction, from 0 to 1, of the stake account's delegation that is effective
+    ///
+    /// Returns None if the account is not a delegated stake account.  Fully warmed up stakes
+    /// return 1, and stakes that are cooling down return the fraction still effective.
+    pub fn stake_warmup_progress(&self, stake: &Pubkey) -> Option<f64> {
+        let account = self.get_account(stake)?;
+        if !stake::program::check_id(account.owner()) {
+            return None;
+        }
+        let StakeStateV2::Stake(_meta, stake, _stake_flags) =
+            account.deserialize_data::<StakeStateV2>().ok()?
+        else {
+            return None;
+        };
+        let target_stake = stake.delegation.stake;
+        if target_stake == 0 {
+            return Some(1.0);
+        }
+        let effective_stake = stake.stake(
+            self.epoch(),
+            self.stakes_cache.stakes().history(),
+            self.new_warmup_cooldown_rate_epoch(),
+        );
+        Some((effective_stake as f64 / target_stake as f64).clamp(0.0, 1.0))
+    }
+
+    /// Returns the epoch the stake account's delegation was activated in
+    ///
+    /// Returns None if the account is not a delegated stake account.
+    pub fn stake_activation_epoch(&self, stake: &Pubkey) -> Option<Epoch> {
+        let account = self.get_account(stake)?;
+        if !stake::program::check_id(account.owner()) {
+            return None;
+        }
+        let StakeStateV2::Stake(_meta, stake, _stake_flags) =
+            account.deserialize_data::<StakeStateV2>().ok()?
+        else {
+            return None;
+        };
+        Some(stake.delegation.activation_epoch)
+    }
+
+    /// Estimates how many more epochs until the stake account's effective stake reaches zero
+    ///
+    /// Returns None if the account is not a delegated stake account, or was not deactivated, and
+    /// Some(0) once it has fully cooled down.  Cooldown is projected from the latest stake history
+    /// entry, assuming no other stake starts activating or deactivating meanwhile, so the estimate
+    /// may change as the cluster's stake does.
+    pub fn stake_deactivation_remaining_epochs(&self, stake: &Pubkey) -> Option<u64> {
+        let account = self.get_account(stake)?;
+        if !stake::program::check_id(account.owner()) {
+            return None;
+        }
+        let StakeStateV2::Stake(_meta, stake, _stake_flags) =
+            account.deserialize_data::<StakeStateV2>().ok()?
+        else {
+            return None;
+        };
+        if stake.delegation.deactivation_epoch == Epoch::MAX {
+            return None;
+        }
+
+        let epoch = self.epoch();
+        let new_warmup_cooldown_rate_epoch = self.new_warmup_cooldown_rate_epoch();
+        let stakes = self.stakes_cache.stakes();
+        let stake_history = stakes.history();
+        let mut effective_stake = stake
+            .delegation
+            .stake_activating_and_deactivating(epoch, stake_history, new_warmup_cooldown_rate_epoch)
+            .effective;
+        let (mut cluster_effective_stake, mut cluster_deactivating_stake) = stake_history
+            .get(epoch.saturating_sub(1))
+            .map(|entry| (entry.effective, entry.deactivating))
+            .unwrap_or_default();
+
+        // Mirrors the stake program's cooldown, one epoch at a time.  The cluster's stake is only
+        // known up to the previous epoch, and may not include this stake's deactivation yet, so
+        // it is never allowed to drop below this stake's.
+        let mut remaining_epochs = 0;
+        while effective_stake > 0 {
+            remaining_epochs += 1;
+            cluster_deactivating_stake = cluster_deactivating_stake.max(effective_stake);
+            cluster_effective_stake = cluster_effective_stake.max(cluster_deactivating_stake);
+            let newly_not_effective_cluster_stake = cluster_effective_stake as f64
+                * warmup_cooldown_rate(epoch + remaining_epochs, new_warmup_cooldown_rate_epoch);
+            let weight = effective_stake as f64 / cluster_deactivating_stake as f64;
+            let newly_not_effective_stake =
+                ((weight * newly_not_effective_cluster_stake) as u64).max(1);
+            effective_stake = effective_stake.saturating_sub(newly_not_effective_stake);
+
+            let newly_not_effective_cluster_stake =
+                (newly_not_effective_cluster_stake as u64).min(cluster_deactivating_stake);
+            cluster_effective_stake -= newly_not_effective_cluster_stake;
+            cluster_deactivating_stake -= newly_not_effective_cluster_stake;
+        }
+        Some(remaining_epochs)
+    }
+
+    /// Returns the vote accounts whose commission is at most `max_commission`, along with their
+    /// commission, sorted by address
+    pub fn vote_accounts_by_commission(&self, max_commission: u8) -> Vec<(Pubkey, u8)> {
+        let stakes = self.stakes_cache.stakes();
+        let mut vote_accounts: Vec<_> = stakes
+            .vote_accounts()
+            .iter()
+            .map(|(vote_pubkey, vote_account)| {
+                (*vote_pubkey, vote_account.vote_state_view().commission())
+            })
+            .filter(|(_vote_pubkey, commission)| *commission <= max_commission)
+            .collect();
+        vote_accounts.sort_unstable_by_key(|(vote_pubkey, _commission)| *vote_pubkey);
+        vote_accounts
+    }
+
+    /// Splits `gross_reward` between the vote account at `vote_pubkey` and its stakers, per the
+    /// vote account's current commission
+    ///
+    /// Returns the `(voter, staker)` portions, as rewards are split when paid out, so fractional
+    /// lamports are discarded from each.  Returns None if there is no such vote account.
+    pub fn split_stake_reward(
+        &self,
+        vote_pubkey: &Pubkey,
+        gross_reward: u64,
+    ) -> Option<(u64, u64)> {
+        let commission = self
+            .stakes_cache
+            .stakes()
+            .vote_accounts()
+            .get(vote_pubkey)?
+            .vote_state_view()
+            .commission();
+        let (voter_portion, staker_portion, _was_split) =
+            commission_split(commission, gross_reward);
+        Some((voter_portion, staker_portion))
+    }
+
+    /// Returns the `(epoch, credits, prev_credits)` history of the vote account at `vote_pubkey`
+    ///
+    /// Ordered oldest epoch first, so the last entry is the latest epoch the account earned credits
+    /// in.  Empty if there is no such vote account.
+    pub fn vote_epoch_credits(&self, vote_pubkey: &Pubkey) -> Vec<(Epoch, u64, u64)> {
+        let stakes = self.stakes_cache.stakes();
+        stakes
+            .vote_accounts()
+            .get(vote_pubkey)
+            .map(|vote_account| {
+                vote_account
+                    .vote_state_view()
+                    .epoch_credits_iter()
+                    .map(Into::into)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
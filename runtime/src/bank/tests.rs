@@ -124,6 +124,7 @@ use {
         convert::TryInto,
         fs::File,
         io::Read,
+        num::NonZeroU64,
         str::FromStr,
         sync::{
             atomic::{
@@ -340,6 +341,62 @@ fn test_bank_block_height() {
     assert_eq!(bank1.block_height(), 1);
 }
 
+#[test]
+fn test_next_full_snapshot_slot() {
+    let (bank0, _bank_forks) = create_simple_test_arc_bank(1);
+    let snapshot_config_with_interval = |interval| SnapshotConfig {
+        full_snapshot_archive_interval: SnapshotInterval::Slots(NonZeroU64::new(interval).unwrap()),
+        ..SnapshotConfig::default()
+    };
+
+    // slot 0 is a multiple of every interval
+    assert_eq!(
+        bank0.next_full_snapshot_slot(&snapshot_config_with_interval(100)),
+        Some(0),
+    );
+    for (slot, interval, expected_slot) in [
+        (1, 100, 100),
+        (99, 100, 100),
+        (100, 100, 100),
+        (101, 100, 200),
+        (101, 1, 101),
+        (12_345, 25_000, 25_000),
+    ] {
+        let bank = Bank::new_from_parent(bank0.clone(), &Pubkey::default(), slot);
+        assert_eq!(
+            bank.next_full_snapshot_slot(&snapshot_config_with_interval(interval)),
+            Some(expected_slot),
+            "slot: {slot}, interval: {interval}",
+        );
+    }
+
+    let snapshot_config = SnapshotConfig {
+        full_snapshot_archive_interval: SnapshotInterval::Disabled,
+        ..SnapshotConfig::default()
+    };
+    assert_eq!(bank0.next_full_snapshot_slot(&snapshot_config), None);
+}
+
+#[test]
+fn test_rent_exempt_minimum() {
+    let (mut genesis_config, _mint_keypair) = create_genesis_config(100_000);
+    genesis_config.rent = Rent::default();
+    let bank = Bank::new_for_tests(&genesis_config);
+    for data_len in [0, 1, StakeStateV2::size_of(), 10 * 1024 * 1024] {
+        assert_eq!(
+            bank.rent_exempt_minimum(data_len),
+            genesis_config.rent.minimum_balance(data_len),
+            "data_len: {data_len}",
+        );
+    }
+
+    // With free rent, the minimum is zero, not clamped like the minimum balance for rent exemption
+    genesis_config.rent = Rent::free();
+    let bank = Bank::new_for_tests(&genesis_config);
+    assert_eq!(bank.rent_exempt_minimum(0), 0);
+    assert_eq!(bank.get_minimum_balance_for_rent_exemption(0), 1);
+}
+
 #[test]
 fn test_bank_update_epoch_stakes() {
     #[allow(non_local_definitions)]
@@ -12155,3 +12212,102 @@ fn test_should_use_vote_keyed_leader_schedule() {
         }
     }
 }
+
+#[test]
+fn test_validate_tower_sync() {
+    let (genesis_config, _mint_keypair) = create_genesis_config(LAMPORTS_PER_SOL);
+    let (bank0, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let collector_id = Pubkey::new_unique();
+    let bank1 = new_bank_from_parent_with_bank_forks(&bank_forks, bank0, &collector_id, 1);
+    // slot 2 is on another fork
+    let _bank2 = new_bank_from_parent_with_bank_forks(&bank_forks, bank1.clone(), &collector_id, 2);
+    let bank3 = new_bank_from_parent_with_bank_forks(&bank_forks, bank1, &collector_id, 3);
+    let bank4 = new_bank_from_parent_with_bank_forks(&bank_forks, bank3.clone(), &collector_id, 4);
+
+    let valid = TowerSync::new_from_slots(vec![1, 3], bank3.hash(), Some(0));
+    assert_eq!(bank4.validate_tower_sync(&valid), Ok(()));
+
+    let out_of_order = TowerSync::new_from_slots(vec![3, 1], bank3.hash(), Some(0));
+    assert_eq!(
+        bank4.validate_tower_sync(&out_of_order),
+        Err(TowerSyncError::SlotsNotOrdered {
+            prev_slot: 3,
+            slot: 1
+        }),
+    );
+
+    let empty = TowerSync::new_from_slots(vec![], bank3.hash(), Some(0));
+    assert_eq!(
+        bank4.validate_tower_sync(&empty),
+        Err(TowerSyncError::EmptySlots),
+    );
+
+    let too_many = TowerSync::new_from_slots(
+        (1..=MAX_LOCKOUT_HISTORY as Slot + 1).collect(),
+        bank3.hash(),
+        None,
+    );
+    assert_eq!(
+        bank4.validate_tower_sync(&too_many),
+        Err(TowerSyncError::TooManyLockouts(MAX_LOCKOUT_HISTORY + 1)),
+    );
+
+    let other_fork = TowerSync::new_from_slots(vec![1, 2], bank3.hash(), Some(0));
+    assert_eq!(
+        bank4.validate_tower_sync(&other_fork),
+        Err(TowerSyncError::SlotNotAncestor(2)),
+    );
+
+    // votes may not be for the bank they land in
+    let current_slot = TowerSync::new_from_slots(vec![3, 4], bank4.hash(), Some(0));
+    assert_eq!(
+        bank4.validate_tower_sync(&current_slot),
+        Err(TowerSyncError::SlotNotAncestor(4)),
+    );
+
+    let root_after_slots = TowerSync::new_from_slots(vec![1, 3], bank3.hash(), Some(1));
+    assert_eq!(
+        bank4.validate_tower_sync(&root_after_slots),
+        Err(TowerSyncError::RootNotOrdered { root: 1, slot: 1 }),
+    );
+
+    let root_on_other_fork = TowerSync::new_from_slots(vec![3], bank3.hash(), Some(2));
+    assert_eq!(
+        bank4.validate_tower_sync(&root_on_other_fork),
+        Err(TowerSyncError::RootNotAncestor(2)),
+    );
+}
+
+#[test]
+fn test_rent_paying_accounts() {
+    let (mut genesis_config, _mint_keypair) = create_genesis_config(LAMPORTS_PER_SOL);
+    genesis_config.rent = Rent::default();
+    let bank = Bank::new_for_tests(&genesis_config);
+    let data_len = 100;
+    let minimum_balance = bank.get_minimum_balance_for_rent_exemption(data_len);
+
+    let rent_paying_pubkey = Pubkey::new_unique();
+    bank.store_account(
+        &rent_paying_pubkey,
+        &AccountSharedData::new(minimum_balance - 1, data_len, &Pubkey::default()),
+    );
+    let rent_exempt_pubkey = Pubkey::new_unique();
+    bank.store_account(
+        &rent_exempt_pubkey,
+        &AccountSharedData::new(minimum_balance, data_len, &Pubkey::default()),
+    );
+
+    let rent_paying_accounts = bank.rent_paying_accounts(usize::MAX).unwrap();
+    assert!(rent_paying_accounts.contains(&(rent_paying_pubkey, minimum_balance - 1)));
+    assert!(!rent_paying_accounts
+        .iter()
+        .any(|(pubkey, _balance)| pubkey == &rent_exempt_pubkey));
+    assert!(rent_paying_accounts.is_sorted_by_key(|(pubkey, _balance)| *pubkey));
+
+    // The limit caps how many are returned
+    assert!(bank.rent_paying_accounts(0).unwrap().is_empty());
+    assert_eq!(
+        bank.rent_paying_accounts(1).unwrap(),
+        rent_paying_accounts[..1],
+    );
+}
@@ -0,0 +1,76 @@
+use {
+    super::Bank,
+    solana_clock::Slot,
+    solana_slot_history::Check,
+    solana_vote_interface::state::{TowerSync, MAX_LOCKOUT_HISTORY},
+    thiserror::Error,
+};
+
+/// Reasons a tower sync would be rejected when voting on a bank
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TowerSyncError {
+    #[error("tower sync has no slots")]
+    EmptySlots,
+    #[error("tower sync has {0} lockouts, more than the maximum of {MAX_LOCKOUT_HISTORY}")]
+    TooManyLockouts(usize),
+    #[error("slot {slot} does not come after the preceding slot {prev_slot}")]
+    SlotsNotOrdered { prev_slot: Slot, slot: Slot },
+    #[error("slot {0} is not an ancestor of this bank")]
+    SlotNotAncestor(Slot),
+    #[error("root {root} does not come before the first slot {slot}")]
+    RootNotOrdered { root: Slot, slot: Slot },
+    #[error("root {0} is not an ancestor of this bank")]
+    RootNotAncestor(Slot),
+}
+
+impl Bank {
+    /// Checks whether `vote` is consistent with this bank's fork, before it is submitted
+    ///
+    /// The slots must be strictly increasing, with no more lockouts than the vote program keeps,
+    /// and the root, if any, must come before them.  Every slot, and the root, must be a strict
+    /// ancestor of this bank, per its ancestors or slot history, since votes are only accepted for
+    /// slots older than the bank they land in.  The vote hash is not checked.
+    pub fn validate_tower_sync(&self, vote: &TowerSync) -> Result<(), TowerSyncError> {
+        let Some(first_slot) = vote.lockouts.front().map(|lockout| lockout.slot()) else {
+            return Err(TowerSyncError::EmptySlots);
+        };
+        if vote.lockouts.len() > MAX_LOCKOUT_HISTORY {
+            return Err(TowerSyncError::TooManyLockouts(vote.lockouts.len()));
+        }
+        for (prev_lockout, lockout) in vote.lockouts.iter().zip(vote.lockouts.iter().skip(1)) {
+            if lockout.slot() <= prev_lockout.slot() {
+                return Err(TowerSyncError::SlotsNotOrdered {
+                    prev_slot: prev_lockout.slot(),
+                    slot: lockout.slot(),
+                });
+            }
+        }
+        if let Some(root) = vote.root {
+            if root >= first_slot {
+                return Err(TowerSyncError::RootNotOrdered {
+                    root,
+                    slot: first_slot,
+                });
+            }
+        }
+
+        let slot_history = self.get_slot_history();
+        let is_ancestor = |slot: Slot| {
+            slot < self.slot()
+                && (self.ancestors.contains_key(&slot) || slot_history.check(slot) == Check::Found)
+        };
+        if let Some(slot) = vote
+            .lockouts
+            .iter()
+            .map(|lockout| lockout.slot())
+            .find(|slot| !is_ancestor(*slot))
+        {
+            return Err(TowerSyncError::SlotNotAncestor(slot));
+        }
+        if let Some(root) = vote.root.filter(|root| !is_ancestor(*root)) {
+            return Err(TowerSyncError::RootNotAncestor(root));
+        }
+
+        Ok(())
+    }
+}
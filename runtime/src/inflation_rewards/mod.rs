@@ -223,7 +223,7 @@ fn calculate_stake_rewards(
 ///   indicate with false for was_split
 ///
 /// DEVELOPER NOTE:  This function used to be a method on VoteState, but was moved here
-fn commission_split(commission: u8, on: u64) -> (u64, u64, bool) {
+pub(crate) fn commission_split(commission: u8, on: u64) -> (u64, u64, bool) {
     match commission.min(100) {
         0 => (0, on, false),
         100 => (on, 0, false),
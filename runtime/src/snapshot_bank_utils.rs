@@ -2167,6 +2167,7 @@ mod tests {
                     epoch_schedule: deserialized_bank.epoch_schedule(),
                     epoch: deserialized_bank.epoch(),
                     store_detailed_debug_info_on_failure: false,
+                    progress: None,
                 },
                 &SortedStorages::new(&other_incremental_snapshot_storages),
                 HashStats::default(),
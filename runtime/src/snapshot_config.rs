@@ -1,6 +1,6 @@
 use {
     crate::snapshot_utils::{self, ArchiveFormat, SnapshotInterval, SnapshotVersion, ZstdConfig},
-    std::{num::NonZeroUsize, path::PathBuf},
+    std::{num::NonZeroUsize, path::PathBuf, time::Duration},
 };
 
 /// Snapshot configuration and runtime information
@@ -39,6 +39,64 @@ pub struct SnapshotConfig {
 
     // Thread niceness adjustment for snapshot packager service
     pub packager_thread_niceness_adj: i8,
+
+    /// Path to the directory where accounts hash capitalization mismatch diagnostics are written
+    /// before panicking.  If None, the diagnostics are only logged.
+    pub accounts_hash_mismatch_dump_dir: Option<PathBuf>,
+
+    /// What to do when the accounts hash of an incremental snapshot's base is missing
+    pub on_missing_incremental_base: OnMissingIncrementalBase,
+
+    /// Store detailed debug info on the initial full accounts hash calculation, instead of only
+    /// when recalculating after a capitalization mismatch
+    pub always_store_hash_debug_info: bool,
+
+    /// When full snapshot packages from different forks are pending for the same slot, rotate
+    /// which fork is handled instead of always picking the same one
+    pub round_robin_full_snapshots_across_forks: bool,
+
+    /// How many slots of accounts hashes, older than the latest handled accounts package, to keep
+    /// when purging old accounts hashes
+    pub retained_accounts_hash_slots: u64,
+
+    /// Check that an incremental accounts hash's capitalization, plus its base's less the base
+    /// lamports of the accounts modified since the base, equals the accounts package's expected
+    /// capitalization.  Finding those base lamports scans the base's storages, and a mismatch
+    /// fails the accounts package.
+    pub verify_incremental_capitalization: bool,
+
+    /// Check that a full accounts hash matches the hash in the name of the full snapshot archive
+    /// for the same slot, if there is one.  Meant for validating archives after a restart.
+    pub verify_against_archives: bool,
+
+    /// Only check that the lamports across each accounts package's storages sum to its expected
+    /// capitalization, skipping the accounts hash calculation.  No snapshot packages are produced.
+    pub capitalization_only_verification: bool,
+
+    /// Sleep a random delay, up to this long, before re-enqueuing each accounts package that was
+    /// not handled, so they do not all reappear at once.  If None, they are re-enqueued at once.
+    pub max_re_enqueue_jitter: Option<Duration>,
+
+    /// Only emit the accounts hash verifier's datapoint for every this many accounts packages
+    /// handled.  Errors are always emitted.  Zero and one emit for every accounts package.
+    pub metrics_sample_rate: u32,
+
+    /// Skip incremental snapshot packages whose base full snapshot was never handed to the
+    /// snapshot packager, per the latest full snapshot slot, instead of submitting them.  They are
+    /// dropped, not held until the base exists.  A warning is logged either way.
+    pub skip_incremental_snapshots_without_base: bool,
+
+    /// Append a JSON line for every accounts hash calculated to this file.  The file is never
+    /// rotated or truncated.
+    pub hash_audit_log: Option<PathBuf>,
+
+    /// For merkle-based accounts packages, also calculate the lattice-based accounts hash from
+    /// the storages, and log it.  It is only for comparison, and is never packaged.
+    pub compute_shadow_lattice_hash: bool,
+
+    /// Log how far along each full accounts hash calculation is, as it scans the storages and
+    /// de-duplicates the accounts
+    pub log_full_accounts_hash_progress: bool,
 }
 
 impl Default for SnapshotConfig {
@@ -63,6 +121,20 @@ impl Default for SnapshotConfig {
             maximum_incremental_snapshot_archives_to_retain:
                 snapshot_utils::DEFAULT_MAX_INCREMENTAL_SNAPSHOT_ARCHIVES_TO_RETAIN,
             packager_thread_niceness_adj: 0,
+            accounts_hash_mismatch_dump_dir: None,
+            on_missing_incremental_base: OnMissingIncrementalBase::default(),
+            always_store_hash_debug_info: false,
+            round_robin_full_snapshots_across_forks: false,
+            retained_accounts_hash_slots: 0,
+            verify_incremental_capitalization: false,
+            verify_against_archives: false,
+            capitalization_only_verification: false,
+            max_re_enqueue_jitter: None,
+            metrics_sample_rate: 1,
+            skip_incremental_snapshots_without_base: false,
+            hash_audit_log: None,
+            compute_shadow_lattice_hash: false,
+            log_full_accounts_hash_progress: false,
         }
     }
 }
@@ -112,3 +184,16 @@ pub enum SnapshotUsage {
     /// generate).  This enables taking snapshots.
     LoadAndGenerate,
 }
+
+/// Specify what to do when handling an incremental snapshot whose base full snapshot's accounts
+/// hash is missing
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum OnMissingIncrementalBase {
+    /// Panic, with diagnostics about the existing accounts hashes and snapshots
+    #[default]
+    Panic,
+    /// Drop the incremental snapshot and continue
+    Drop,
+    /// Drop the incremental snapshot and request a new full snapshot
+    RequestFull,
+}
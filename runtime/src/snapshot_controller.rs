@@ -13,7 +13,7 @@ use {
     solana_measure::measure::Measure,
     std::{
         sync::{
-            atomic::{AtomicU64, Ordering},
+            atomic::{AtomicBool, AtomicU64, Ordering},
             Arc,
         },
         time::Instant,
@@ -29,6 +29,7 @@ pub struct SnapshotController {
     abs_request_sender: SnapshotRequestSender,
     snapshot_config: SnapshotConfig,
     latest_abs_request_slot: AtomicU64,
+    full_snapshot_requested: AtomicBool,
 }
 
 impl SnapshotController {
@@ -41,6 +42,7 @@ impl SnapshotController {
             abs_request_sender,
             snapshot_config,
             latest_abs_request_slot: AtomicU64::new(root_slot),
+            full_snapshot_requested: AtomicBool::new(false),
         }
     }
 
@@ -60,6 +62,14 @@ impl SnapshotController {
         self.latest_abs_request_slot.store(slot, Ordering::Relaxed);
     }
 
+    /// Requests a full snapshot be taken at the next new root, instead of waiting for the full
+    /// snapshot interval
+    ///
+    /// Has no effect if full snapshots are disabled.
+    pub fn request_full_snapshot(&self) {
+        self.full_snapshot_requested.store(true, Ordering::Relaxed);
+    }
+
     pub fn handle_new_roots(
         &self,
         root: Slot,
@@ -74,10 +84,11 @@ impl SnapshotController {
             incremental_snapshot_interval,
         }) = self.snapshot_generation_intervals()
         {
+            let is_full_snapshot_requested = self.full_snapshot_requested.load(Ordering::Relaxed);
             if let Some((bank, request_kind)) = banks.iter().find_map(|bank| {
                 let should_request_full_snapshot =
                     if let SnapshotInterval::Slots(snapshot_interval) = full_snapshot_interval {
-                        bank.block_height() % snapshot_interval == 0
+                        is_full_snapshot_requested || bank.block_height() % snapshot_interval == 0
                     } else {
                         false
                     };
@@ -102,6 +113,9 @@ impl SnapshotController {
             }) {
                 let bank_slot = bank.slot();
                 self.set_latest_abs_request_slot(bank_slot);
+                if request_kind == SnapshotRequestKind::FullSnapshot {
+                    self.full_snapshot_requested.store(false, Ordering::Relaxed);
+                }
                 squash_timing += bank.squash();
 
                 is_root_bank_squashed = bank_slot == root;
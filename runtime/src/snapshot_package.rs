@@ -6,6 +6,7 @@ use {
     },
     agave_feature_set as feature_set,
     log::*,
+    serde::{Deserialize, Serialize},
     solana_accounts_db::{
         accounts::Accounts,
         accounts_db::AccountStorageEntry,
@@ -45,6 +46,10 @@ pub struct AccountsPackage {
     /// The instant this accounts package was send to the queue.
     /// Used to track how long accounts packages wait before processing.
     pub enqueued: Instant,
+
+    /// Optional caller-supplied id used to correlate this package with its
+    /// resulting snapshot package in logs and metrics.
+    pub correlation_id: Option<u64>,
 }
 
 impl AccountsPackage {
@@ -134,6 +139,7 @@ impl AccountsPackage {
             accounts_hash_algorithm,
             snapshot_info,
             enqueued: Instant::now(),
+            correlation_id: None,
         }
     }
 
@@ -163,10 +169,25 @@ impl AccountsPackage {
                 write_version: u64::default(),
             }),
             enqueued: Instant::now(),
+            correlation_id: None,
+        }
+    }
+
+    /// Returns the minimal, serializable metadata of this accounts package
+    pub fn summary(&self) -> AccountsPackageSummary {
+        AccountsPackageSummary {
+            slot: self.slot,
+            block_height: self.block_height,
+            package_kind: self.package_kind,
+            accounts_hash_algorithm: self.accounts_hash_algorithm,
+            expected_capitalization: self.expected_capitalization,
+            epoch_schedule: self.epoch_schedule.clone(),
         }
     }
 }
 
+// Storages, accounts, and supplemental snapshot info are large, so only summarize them, to keep
+// log lines readable
 impl std::fmt::Debug for AccountsPackage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AccountsPackage")
@@ -174,10 +195,23 @@ impl std::fmt::Debug for AccountsPackage {
             .field("slot", &self.slot)
             .field("block_height", &self.block_height)
             .field("accounts_hash_algorithm", &self.accounts_hash_algorithm)
+            .field("num_storages", &self.snapshot_storages.len())
+            .field("correlation_id", &self.correlation_id)
             .finish_non_exhaustive()
     }
 }
 
+/// The metadata of an AccountsPackage, without its storages, that can be sent across processes
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AccountsPackageSummary {
+    pub slot: Slot,
+    pub block_height: Slot,
+    pub package_kind: AccountsPackageKind,
+    pub accounts_hash_algorithm: AccountsHashAlgorithm,
+    pub expected_capitalization: u64,
+    pub epoch_schedule: EpochSchedule,
+}
+
 /// Supplemental information needed for snapshots
 pub struct SupplementalSnapshotInfo {
     pub status_cache_slot_deltas: Vec<BankSlotDelta>,
@@ -190,7 +224,7 @@ pub struct SupplementalSnapshotInfo {
 /// Accounts packages are sent to the Accounts Hash Verifier for processing.  There are multiple
 /// types of accounts packages, which are specified as variants in this enum.  All accounts
 /// packages do share some processing: such as calculating the accounts hash.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum AccountsPackageKind {
     Snapshot(SnapshotKind),
     EpochAccountsHash,
@@ -214,6 +248,9 @@ pub struct SnapshotPackage {
     /// The instant this snapshot package was sent to the queue.
     /// Used to track how long snapshot packages wait before handling.
     pub enqueued: Instant,
+
+    /// The correlation id carried over from the originating accounts package, if any
+    pub correlation_id: Option<u64>,
 }
 
 impl SnapshotPackage {
@@ -276,6 +313,7 @@ impl SnapshotPackage {
             bank_incremental_snapshot_persistence,
             write_version: snapshot_info.write_version,
             enqueued: Instant::now(),
+            correlation_id: accounts_package.correlation_id,
         }
     }
 }
@@ -299,6 +337,7 @@ impl SnapshotPackage {
             bank_incremental_snapshot_persistence: None,
             write_version: u64::default(),
             enqueued: Instant::now(),
+            correlation_id: None,
         }
     }
 }
@@ -309,13 +348,14 @@ impl std::fmt::Debug for SnapshotPackage {
             .field("kind", &self.snapshot_kind)
             .field("slot", &self.slot)
             .field("block_height", &self.block_height)
+            .field("correlation_id", &self.correlation_id)
             .finish_non_exhaustive()
     }
 }
 
 /// Snapshots come in two kinds, Full and Incremental.  The IncrementalSnapshot has a Slot field,
 /// which is the incremental snapshot base slot.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum SnapshotKind {
     FullSnapshot,
     IncrementalSnapshot(Slot),
@@ -331,10 +371,78 @@ impl SnapshotKind {
 }
 
 /// Which algorithm should be used to calculate the accounts hash?
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum AccountsHashAlgorithm {
     /// Merkle-based accounts hash algorithm
     Merkle,
     /// Lattice-based accounts hash algorithm
     Lattice,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounts_package_debug() {
+        let accounts_package = AccountsPackage {
+            package_kind: AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(100)),
+            slot: 123,
+            ..AccountsPackage::default_for_tests()
+        };
+        let debug = format!("{accounts_package:?}");
+        assert!(debug.contains("slot: 123"));
+        assert!(debug.contains("IncrementalSnapshot(100)"));
+        assert!(debug.contains("num_storages: 0"));
+        assert!(!debug.contains("snapshot_storages"));
+        assert!(!debug.contains("accounts:"));
+        assert!(!debug.contains("snapshot_info"));
+    }
+
+    #[test]
+    fn test_accounts_package_summary() {
+        let accounts_package = AccountsPackage {
+            package_kind: AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(100)),
+            slot: 123,
+            block_height: 120,
+            expected_capitalization: 456_789,
+            epoch_schedule: EpochSchedule::custom(64, 64, false),
+            accounts_hash_algorithm: AccountsHashAlgorithm::Lattice,
+            ..AccountsPackage::default_for_tests()
+        };
+        let summary = accounts_package.summary();
+        assert_eq!(summary.slot, accounts_package.slot);
+        assert_eq!(summary.block_height, accounts_package.block_height);
+        assert_eq!(summary.package_kind, accounts_package.package_kind);
+        assert_eq!(
+            summary.accounts_hash_algorithm,
+            accounts_package.accounts_hash_algorithm,
+        );
+        assert_eq!(
+            summary.expected_capitalization,
+            accounts_package.expected_capitalization,
+        );
+        assert_eq!(summary.epoch_schedule, accounts_package.epoch_schedule);
+    }
+
+    #[test]
+    fn test_accounts_package_summary_serde() {
+        for package_kind in [
+            AccountsPackageKind::Snapshot(SnapshotKind::FullSnapshot),
+            AccountsPackageKind::Snapshot(SnapshotKind::IncrementalSnapshot(100)),
+            AccountsPackageKind::EpochAccountsHash,
+        ] {
+            let summary = AccountsPackage {
+                package_kind,
+                slot: 123,
+                block_height: 120,
+                expected_capitalization: 456_789,
+                ..AccountsPackage::default_for_tests()
+            }
+            .summary();
+            let bytes = bincode::serialize(&summary).unwrap();
+            let deserialized: AccountsPackageSummary = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(deserialized, summary);
+        }
+    }
+}
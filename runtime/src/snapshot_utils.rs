@@ -834,6 +834,7 @@ pub fn serialize_and_archive_snapshot_package(
         bank_incremental_snapshot_persistence,
         write_version,
         enqueued: _,
+        correlation_id: _,
     } = snapshot_package;
 
     let bank_snapshot_info = serialize_snapshot(
@@ -10,10 +10,13 @@ use {
     solana_pubkey::Pubkey,
     solana_rent::Rent,
     solana_runtime::{
-        bank::Bank,
+        bank::{Bank, StakeSplitError, StakeWithdrawError},
         bank_client::BankClient,
         bank_forks::BankForks,
-        genesis_utils::{create_genesis_config_with_leader, GenesisConfigInfo},
+        genesis_utils::{
+            create_genesis_config_with_leader, create_genesis_config_with_vote_accounts,
+            GenesisConfigInfo, ValidatorVoteKeypairs,
+        },
     },
     solana_signer::Signer,
     solana_stake_interface::{
@@ -102,6 +105,79 @@ fn fill_epoch_with_votes(
     bank
 }
 
+/// Creates a vote account with `commission`, whose authorities are all the mint
+fn create_vote_account(bank_client: &BankClient, mint_keypair: &Keypair, commission: u8) -> Pubkey {
+    let mint_pubkey = mint_keypair.pubkey();
+    let vote_keypair = Keypair::new();
+    let vote_pubkey = vote_keypair.pubkey();
+    let vote_balance = bank_client
+        .get_minimum_balance_for_rent_exemption(VoteStateV3::size_of())
+        .unwrap();
+    let message = Message::new(
+        &vote_instruction::create_account_with_config(
+            &mint_pubkey,
+            &vote_pubkey,
+            &VoteInit {
+                node_pubkey: mint_pubkey,
+                authorized_voter: mint_pubkey,
+                authorized_withdrawer: mint_pubkey,
+                commission,
+            },
+            vote_balance,
+            vote_instruction::CreateVoteAccountConfig {
+                space: VoteStateVersions::vote_state_size_of(true) as u64,
+                ..vote_instruction::CreateVoteAccountConfig::default()
+            },
+        ),
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[mint_keypair, &vote_keypair], message)
+        .expect("failed to create vote account");
+    vote_pubkey
+}
+
+/// Creates a stake account of `lamports`, whose authorities are the mint, and delegates it to
+/// `vote_pubkey`
+fn create_delegated_stake(
+    bank_client: &BankClient,
+    mint_keypair: &Keypair,
+    vote_pubkey: &Pubkey,
+    lamports: u64,
+) -> Pubkey {
+    let mint_pubkey = mint_keypair.pubkey();
+    let stake_keypair = Keypair::new();
+    let stake_pubkey = stake_keypair.pubkey();
+    let message = Message::new(
+        &stake_instruction::create_account_and_delegate_stake(
+            &mint_pubkey,
+            &stake_pubkey,
+            vote_pubkey,
+            &Authorized::auto(&mint_pubkey),
+            &Lockup::default(),
+            lamports,
+        ),
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[mint_keypair, &stake_keypair], message)
+        .expect("failed to create and delegate stake account");
+    stake_pubkey
+}
+
+/// Creates a vote account, and a stake account of `lamports` delegated to it
+///
+/// Returns the vote and stake account pubkeys; the mint is the authority of both.
+fn create_vote_and_delegated_stake(
+    bank_client: &BankClient,
+    mint_keypair: &Keypair,
+    lamports: u64,
+) -> (Pubkey, Pubkey) {
+    let vote_pubkey = create_vote_account(bank_client, mint_keypair, 0);
+    let stake_pubkey = create_delegated_stake(bank_client, mint_keypair, &vote_pubkey, lamports);
+    (vote_pubkey, stake_pubkey)
+}
+
 fn warmed_up(bank: &Bank, stake_pubkey: &Pubkey) -> bool {
     let stake = stake_state::stake_from(&bank.get_account(stake_pubkey).unwrap()).unwrap();
 
@@ -112,7 +188,7 @@ fn warmed_up(bank: &Bank, stake_pubkey: &Pubkey) -> bool {
                 &bank.get_account(&sysvar::stake_history::id()).unwrap(),
             )
             .unwrap(),
-            bank.new_warmup_cooldown_rate_epoch(),
+            bank.warmup_cooldown_rate_epoch(),
         )
 }
 
@@ -149,15 +225,7 @@ fn test_stake_create_and_split_single_signature() {
 
     let authorized = Authorized::auto(&staker_pubkey);
 
-    let lamports = {
-        let rent = &bank.rent_collector().rent;
-        let rent_exempt_reserve = rent.minimum_balance(StakeStateV2::size_of());
-        let minimum_delegation = solana_stake_program::get_minimum_delegation(
-            bank.feature_set
-                .is_active(&agave_feature_set::stake_raise_minimum_delegation_to_1_sol::id()),
-        );
-        2 * (rent_exempt_reserve + minimum_delegation)
-    };
+    let lamports = 2 * bank.minimum_split_amount();
 
     // Create stake account with seed
     let message = Message::new(
@@ -224,15 +292,7 @@ fn test_stake_create_and_split_to_existing_system_account() {
 
     let authorized = Authorized::auto(&staker_pubkey);
 
-    let lamports = {
-        let rent = &bank.rent_collector().rent;
-        let rent_exempt_reserve = rent.minimum_balance(StakeStateV2::size_of());
-        let minimum_delegation = solana_stake_program::get_minimum_delegation(
-            bank.feature_set
-                .is_active(&agave_feature_set::stake_raise_minimum_delegation_to_1_sol::id()),
-        );
-        2 * (rent_exempt_reserve + minimum_delegation)
-    };
+    let lamports = 2 * bank.minimum_split_amount();
 
     // Create stake account with seed
     let message = Message::new(
@@ -430,6 +490,12 @@ fn test_stake_account_lifetime() {
     // one vote per slot, might be more slots than 32 in the epoch
     assert!(vote_state.credits() >= 1);
 
+    // The latest epoch credits entry shows the credits earned by the votes
+    let (epoch, credits, prev_credits) = *bank.vote_epoch_credits(&vote_pubkey).last().unwrap();
+    assert!(epoch <= bank.epoch());
+    assert!(credits > prev_credits);
+    assert_eq!(credits, vote_state.credits());
+
     bank = fill_epoch_with_votes(
         bank,
         bank_forks.as_ref(),
@@ -543,6 +609,10 @@ fn test_stake_account_lifetime() {
     // but we can withdraw unstaked
     let split_unstaked = split_balance - split_staked - stake_rent_exempt_reserve;
     assert!(split_unstaked > 0);
+    assert_eq!(
+        bank.withdrawable_stake_lamports(&split_stake_pubkey),
+        split_unstaked,
+    );
     let message = Message::new(
         &[stake_instruction::withdraw(
             &split_stake_pubkey,
@@ -556,6 +626,7 @@ fn test_stake_account_lifetime() {
     assert!(bank_client
         .send_and_confirm_message(&[&mint_keypair, &stake_keypair], message)
         .is_ok());
+    assert_eq!(bank.withdrawable_stake_lamports(&split_stake_pubkey), 0);
 
     // finish cooldown
     loop {
@@ -667,3 +738,886 @@ fn test_create_stake_account_from_seed() {
         panic!("wrong account type found")
     }
 }
+
+#[test]
+fn test_active_stake_for_vote() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.epoch_schedule = EpochSchedule::new(MINIMUM_SLOTS_PER_EPOCH);
+    genesis_config.rent = Rent::default();
+    let (mut bank, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    // Create two stake accounts, of different sizes, and delegate both to the same vote account
+    let vote_pubkey = create_vote_account(&bank_client, &mint_keypair, 50);
+    let stake_pubkeys = [1, 2].map(|num_minimum_split_amounts| {
+        create_delegated_stake(
+            &bank_client,
+            &mint_keypair,
+            &vote_pubkey,
+            num_minimum_split_amounts * bank.minimum_split_amount() + 1_000_000_000,
+        )
+    });
+    let sum_of_staked = |bank: &Bank| {
+        stake_pubkeys
+            .iter()
+            .map(|stake_pubkey| get_staked(bank, stake_pubkey))
+            .sum::<u64>()
+    };
+
+    // Nothing is active yet
+    assert_eq!(bank.active_stake_for_vote(&vote_pubkey, bank.epoch()), 0);
+
+    // Check the sum throughout warmup
+    loop {
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+        assert_eq!(
+            bank.active_stake_for_vote(&vote_pubkey, bank.epoch()),
+            sum_of_staked(&bank),
+        );
+        if stake_pubkeys
+            .iter()
+            .all(|stake_pubkey| warmed_up(&bank, stake_pubkey))
+        {
+            break;
+        }
+    }
+    assert!(sum_of_staked(&bank) > 0);
+
+    // Stake delegated to other vote accounts is not counted
+    assert_eq!(
+        bank.active_stake_for_vote(&solana_pubkey::new_rand(), bank.epoch()),
+        0,
+    );
+}
+
+#[test]
+fn test_can_split_stake() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.rent = Rent::default();
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    let minimum_split_amount = bank.minimum_split_amount();
+
+    // Create stake account and delegate to vote account, with enough lamports to split in half
+    let stake_balance = 2 * minimum_split_amount;
+    let (_vote_pubkey, stake_pubkey) =
+        create_vote_and_delegated_stake(&bank_client, &mint_keypair, stake_balance);
+
+    // Only stake accounts can be split
+    assert_eq!(
+        bank.can_split_stake(&solana_pubkey::new_rand(), 1),
+        Err(StakeSplitError::AccountNotFound),
+    );
+    assert_eq!(
+        bank.can_split_stake(&mint_pubkey, 1),
+        Err(StakeSplitError::InvalidStakeAccount),
+    );
+
+    // The destination would not be rent-exempt
+    let underfunded_split_lamports = bank.rent_exempt_minimum(StakeStateV2::size_of()) - 1;
+    assert_eq!(
+        bank.can_split_stake(&stake_pubkey, underfunded_split_lamports),
+        Err(StakeSplitError::DestinationBelowMinimum {
+            lamports: underfunded_split_lamports,
+            minimum: minimum_split_amount,
+        }),
+    );
+    let split_stake_keypair = Keypair::new();
+    let message = Message::new(
+        &stake_instruction::split(
+            &stake_pubkey,
+            &mint_pubkey,
+            underfunded_split_lamports,
+            &split_stake_keypair.pubkey(),
+        ),
+        Some(&mint_pubkey),
+    );
+    assert!(bank_client
+        .send_and_confirm_message(&[&mint_keypair, &split_stake_keypair], message)
+        .is_err());
+
+    // Splitting in half leaves both accounts at exactly their minimum balance
+    let split_lamports = stake_balance / 2;
+    assert_eq!(bank.can_split_stake(&stake_pubkey, split_lamports), Ok(()));
+    let message = Message::new(
+        &stake_instruction::split(
+            &stake_pubkey,
+            &mint_pubkey,
+            split_lamports,
+            &split_stake_keypair.pubkey(),
+        ),
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[&mint_keypair, &split_stake_keypair], message)
+        .expect("failed to split stake account");
+    assert_eq!(
+        bank.get_balance(&split_stake_keypair.pubkey()),
+        split_lamports
+    );
+}
+
+#[test]
+fn test_minimum_split_amount() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.rent = Rent::default();
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    let minimum_split_amount = bank.minimum_split_amount();
+    let stake_rent_exempt_reserve = bank.rent_exempt_minimum(StakeStateV2::size_of());
+    assert!(minimum_split_amount > stake_rent_exempt_reserve);
+
+    // Create a vote account, and a stake account delegated to it with plenty to split off
+    let (_vote_pubkey, stake_pubkey) =
+        create_vote_and_delegated_stake(&bank_client, &mint_keypair, 3 * minimum_split_amount);
+
+    let split = |split_lamports| {
+        let split_stake_keypair = Keypair::new();
+        let message = Message::new(
+            &stake_instruction::split(
+                &stake_pubkey,
+                &mint_pubkey,
+                split_lamports,
+                &split_stake_keypair.pubkey(),
+            ),
+            Some(&mint_pubkey),
+        );
+        bank_client
+            .send_and_confirm_message(&[&mint_keypair, &split_stake_keypair], message)
+            .map(|_signature| split_stake_keypair.pubkey())
+    };
+
+    // One lamport less would leave the new stake account below the minimum delegation
+    assert!(bank
+        .can_split_stake(&stake_pubkey, minimum_split_amount - 1)
+        .is_err());
+    assert!(split(minimum_split_amount - 1).is_err());
+
+    assert_eq!(
+        bank.can_split_stake(&stake_pubkey, minimum_split_amount),
+        Ok(()),
+    );
+    let split_stake_pubkey = split(minimum_split_amount).expect("failed to split stake account");
+    assert_eq!(bank.get_balance(&split_stake_pubkey), minimum_split_amount);
+}
+
+#[test]
+fn test_locked_stakes() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(100_000_000_000, &solana_pubkey::new_rand(), 1_000_000);
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+    let stake_balance = bank.rent_exempt_minimum(StakeStateV2::size_of());
+
+    let clock = bank.clock();
+    let locked_lockup = Lockup {
+        unix_timestamp: 0,
+        epoch: clock.epoch + 100,
+        custodian: solana_pubkey::new_rand(),
+    };
+    let unlocked_lockup = Lockup {
+        unix_timestamp: clock.unix_timestamp,
+        epoch: clock.epoch,
+        custodian: solana_pubkey::new_rand(),
+    };
+
+    let locked_stake_keypair = Keypair::new();
+    let unlocked_stake_keypair = Keypair::new();
+    for (stake_keypair, lockup) in [
+        (&locked_stake_keypair, &locked_lockup),
+        (&unlocked_stake_keypair, &unlocked_lockup),
+    ] {
+        let stake_pubkey = stake_keypair.pubkey();
+        let message = Message::new(
+            &stake_instruction::create_account(
+                &mint_pubkey,
+                &stake_pubkey,
+                &Authorized::auto(&stake_pubkey),
+                lockup,
+                stake_balance,
+            ),
+            Some(&mint_pubkey),
+        );
+        bank_client
+            .send_and_confirm_message(&[&mint_keypair, stake_keypair], message)
+            .expect("failed to create stake account");
+    }
+
+    assert_eq!(
+        bank.locked_stakes().unwrap(),
+        vec![(locked_stake_keypair.pubkey(), locked_lockup)],
+    );
+}
+
+#[test]
+fn test_stakes_by_withdrawer() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(100_000_000_000, &solana_pubkey::new_rand(), 1_000_000);
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+    let stake_balance = bank.rent_exempt_minimum(StakeStateV2::size_of());
+
+    let withdrawer_a = solana_pubkey::new_rand();
+    let withdrawer_b = solana_pubkey::new_rand();
+    let staker = solana_pubkey::new_rand();
+    let mut stakes_a = Vec::new();
+    let mut stakes_b = Vec::new();
+    for (withdrawer, stakes) in [
+        (withdrawer_a, &mut stakes_a),
+        (withdrawer_b, &mut stakes_b),
+        (withdrawer_a, &mut stakes_a),
+    ] {
+        let stake_keypair = Keypair::new();
+        let message = Message::new(
+            &stake_instruction::create_account(
+                &mint_pubkey,
+                &stake_keypair.pubkey(),
+                &Authorized { staker, withdrawer },
+                &Lockup::default(),
+                stake_balance,
+            ),
+            Some(&mint_pubkey),
+        );
+        bank_client
+            .send_and_confirm_message(&[&mint_keypair, &stake_keypair], message)
+            .expect("failed to create stake account");
+        stakes.push(stake_keypair.pubkey());
+    }
+    stakes_a.sort_unstable();
+
+    assert_eq!(bank.stakes_by_withdrawer(&withdrawer_a).unwrap(), stakes_a);
+    assert_eq!(bank.stakes_by_withdrawer(&withdrawer_b).unwrap(), stakes_b);
+    // The staker is not a withdrawer of any of the stake accounts
+    assert!(bank.stakes_by_withdrawer(&staker).unwrap().is_empty());
+}
+
+#[test]
+fn test_can_withdraw_stake_with_custodian() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(100_000_000_000, &solana_pubkey::new_rand(), 1_000_000);
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+    let stake_rent_exempt_reserve = bank.rent_exempt_minimum(StakeStateV2::size_of());
+
+    let stake_keypair = Keypair::new();
+    let stake_pubkey = stake_keypair.pubkey();
+    let custodian_keypair = Keypair::new();
+    let custodian_pubkey = custodian_keypair.pubkey();
+    let lockup = Lockup {
+        unix_timestamp: 0,
+        epoch: bank.epoch() + 100,
+        custodian: custodian_pubkey,
+    };
+    let withdrawable_lamports = 1_000_000;
+    let message = Message::new(
+        &stake_instruction::create_account(
+            &mint_pubkey,
+            &stake_pubkey,
+            &Authorized::auto(&stake_pubkey),
+            &lockup,
+            stake_rent_exempt_reserve + withdrawable_lamports,
+        ),
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[&mint_keypair, &stake_keypair], message)
+        .expect("failed to create stake account");
+
+    // Only the withdraw authority may withdraw
+    assert_eq!(
+        bank.can_withdraw_stake(
+            &stake_pubkey,
+            &mint_pubkey,
+            Some(&custodian_pubkey),
+            withdrawable_lamports,
+        ),
+        Err(StakeWithdrawError::Unauthorized),
+    );
+
+    // The lockup is in force without its custodian
+    assert_eq!(
+        bank.can_withdraw_stake(&stake_pubkey, &stake_pubkey, None, withdrawable_lamports),
+        Err(StakeWithdrawError::LockupInForce),
+    );
+    assert_eq!(
+        bank.can_withdraw_stake(
+            &stake_pubkey,
+            &stake_pubkey,
+            Some(&mint_pubkey),
+            withdrawable_lamports,
+        ),
+        Err(StakeWithdrawError::LockupInForce),
+    );
+    let message = Message::new(
+        &[stake_instruction::withdraw(
+            &stake_pubkey,
+            &stake_pubkey,
+            &mint_pubkey,
+            withdrawable_lamports,
+            None,
+        )],
+        Some(&mint_pubkey),
+    );
+    assert!(bank_client
+        .send_and_confirm_message(&[&mint_keypair, &stake_keypair], message)
+        .is_err());
+
+    // The rent-exempt reserve may not be withdrawn, even with the custodian
+    assert_eq!(
+        bank.can_withdraw_stake(
+            &stake_pubkey,
+            &stake_pubkey,
+            Some(&custodian_pubkey),
+            withdrawable_lamports + 1,
+        ),
+        Err(StakeWithdrawError::InsufficientFunds),
+    );
+
+    // The custodian unlocks the withdrawal
+    assert_eq!(
+        bank.can_withdraw_stake(
+            &stake_pubkey,
+            &stake_pubkey,
+            Some(&custodian_pubkey),
+            withdrawable_lamports,
+        ),
+        Ok(()),
+    );
+    let message = Message::new(
+        &[stake_instruction::withdraw(
+            &stake_pubkey,
+            &stake_pubkey,
+            &mint_pubkey,
+            withdrawable_lamports,
+            Some(&custodian_pubkey),
+        )],
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(
+            &[&mint_keypair, &stake_keypair, &custodian_keypair],
+            message,
+        )
+        .expect("failed to withdraw with custodian");
+    assert_eq!(bank.get_balance(&stake_pubkey), stake_rent_exempt_reserve);
+}
+
+#[test]
+fn test_stake_warmup_progress() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.epoch_schedule = EpochSchedule::new(MINIMUM_SLOTS_PER_EPOCH);
+    genesis_config.rent = Rent::default();
+    let (mut bank, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    // Only delegated stake accounts have warmup progress
+    let vote_pubkey = create_vote_account(&bank_client, &mint_keypair, 50);
+    assert_eq!(bank.stake_warmup_progress(&solana_pubkey::new_rand()), None);
+    assert_eq!(bank.stake_warmup_progress(&vote_pubkey), None);
+
+    // Create stake account and delegate to vote account
+    let stake_pubkey = create_delegated_stake(
+        &bank_client,
+        &mint_keypair,
+        &vote_pubkey,
+        bank.minimum_split_amount() + 1_000_000_000,
+    );
+
+    let mut progress = bank.stake_warmup_progress(&stake_pubkey).unwrap();
+    assert!(progress < 1.0);
+
+    loop {
+        if warmed_up(&bank, &stake_pubkey) {
+            break;
+        }
+        // Cycle thru banks until we're fully warmed up
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+        let next_progress = bank.stake_warmup_progress(&stake_pubkey).unwrap();
+        assert!((0.0..=1.0).contains(&next_progress));
+        assert!(next_progress >= progress);
+        progress = next_progress;
+    }
+    assert_eq!(bank.stake_warmup_progress(&stake_pubkey), Some(1.0));
+}
+
+#[test]
+fn test_vote_accounts_by_commission() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    // Create two vote accounts, one with a low commission and one with a high commission
+    let [low_commission_vote_pubkey, high_commission_vote_pubkey] =
+        [10, 90].map(|commission| create_vote_account(&bank_client, &mint_keypair, commission));
+
+    // Ignore the genesis vote account
+    let created_vote_accounts = |max_commission| {
+        bank.vote_accounts_by_commission(max_commission)
+            .into_iter()
+            .filter(|(vote_pubkey, _commission)| {
+                [low_commission_vote_pubkey, high_commission_vote_pubkey].contains(vote_pubkey)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        created_vote_accounts(50),
+        vec![(low_commission_vote_pubkey, 10)],
+    );
+    assert_eq!(created_vote_accounts(9), vec![]);
+    assert_eq!(created_vote_accounts(90).len(), 2);
+}
+
+#[test]
+fn test_split_stake_reward() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    let [half_commission_vote_pubkey, no_commission_vote_pubkey] =
+        [50, 0].map(|commission| create_vote_account(&bank_client, &mint_keypair, commission));
+
+    assert_eq!(
+        bank.split_stake_reward(&half_commission_vote_pubkey, 1_000),
+        Some((500, 500)),
+    );
+    // Fractional lamports are discarded from both portions
+    assert_eq!(
+        bank.split_stake_reward(&half_commission_vote_pubkey, 1_001),
+        Some((500, 500)),
+    );
+    assert_eq!(
+        bank.split_stake_reward(&no_commission_vote_pubkey, 1_000),
+        Some((0, 1_000)),
+    );
+    assert_eq!(
+        bank.split_stake_reward(&solana_pubkey::new_rand(), 1_000),
+        None,
+    );
+}
+
+#[test]
+fn test_epoch_stake_weights() {
+    let validator_vote_keypairs = [
+        ValidatorVoteKeypairs::new_rand(),
+        ValidatorVoteKeypairs::new_rand(),
+    ];
+    let stakes = vec![10_000_000_000, 20_000_000_000];
+    let GenesisConfigInfo { genesis_config, .. } = create_genesis_config_with_vote_accounts(
+        1_000_000_000,
+        &validator_vote_keypairs,
+        stakes.clone(),
+    );
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let [node0_pubkey, node1_pubkey] = validator_vote_keypairs
+        .each_ref()
+        .map(|keypairs| keypairs.node_keypair.pubkey());
+
+    // the genesis stakes are fully active, and the larger stake comes first
+    let expected_stake_weights = vec![(node1_pubkey, stakes[1]), (node0_pubkey, stakes[0])];
+    assert_eq!(
+        bank.epoch_stake_weights(bank.epoch()),
+        expected_stake_weights
+    );
+    let leader_schedule_epoch = bank.get_leader_schedule_epoch(bank.slot());
+    assert_eq!(
+        bank.epoch_stake_weights(leader_schedule_epoch),
+        expected_stake_weights,
+    );
+    for (node_pubkey, stake) in expected_stake_weights {
+        assert_eq!(
+            bank.epoch_node_id_to_stake(bank.epoch(), &node_pubkey),
+            Some(stake),
+        );
+    }
+
+    // no epoch stakes that far out
+    assert!(bank
+        .epoch_stake_weights(leader_schedule_epoch + 1)
+        .is_empty());
+}
+
+#[test]
+fn test_stakes_delegated_to() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    let stake_balance = bank.minimum_split_amount();
+
+    // Delegate two stakes to the first vote account, and one to the other
+    let (vote_pubkey, stake_pubkey) =
+        create_vote_and_delegated_stake(&bank_client, &mint_keypair, stake_balance);
+    let second_stake_pubkey =
+        create_delegated_stake(&bank_client, &mint_keypair, &vote_pubkey, stake_balance);
+    let (other_vote_pubkey, other_stake_pubkey) =
+        create_vote_and_delegated_stake(&bank_client, &mint_keypair, stake_balance);
+
+    let mut expected_stake_pubkeys = vec![stake_pubkey, second_stake_pubkey];
+    expected_stake_pubkeys.sort_unstable();
+    assert_eq!(
+        bank.stakes_delegated_to(&vote_pubkey),
+        expected_stake_pubkeys
+    );
+    assert_eq!(
+        bank.stakes_delegated_to(&other_vote_pubkey),
+        vec![other_stake_pubkey],
+    );
+    assert!(bank
+        .stakes_delegated_to(&solana_pubkey::new_rand())
+        .is_empty());
+}
+
+#[test]
+fn test_orphaned_stakes() {
+    let GenesisConfigInfo {
+        genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    let (bank, _bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    let stake_balance = bank.minimum_split_amount();
+
+    // Create two vote accounts, with a stake delegated to each
+    let [(vote_pubkey, stake_pubkey), _other_vote_and_stake_pubkeys] = [(); 2]
+        .map(|()| create_vote_and_delegated_stake(&bank_client, &mint_keypair, stake_balance));
+    assert!(bank.orphaned_stakes().is_empty());
+
+    // Close the first vote account, by withdrawing all of its lamports
+    let message = Message::new(
+        &[vote_instruction::withdraw(
+            &vote_pubkey,
+            &mint_pubkey,
+            bank.get_balance(&vote_pubkey),
+            &mint_pubkey,
+        )],
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[&mint_keypair], message)
+        .expect("failed to close vote account");
+    assert!(bank.get_account(&vote_pubkey).is_none());
+
+    // Only the stake delegated to the closed vote account is orphaned
+    assert_eq!(bank.orphaned_stakes(), vec![stake_pubkey]);
+}
+
+#[test]
+fn test_mergeable_stakes() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.epoch_schedule = EpochSchedule::new(MINIMUM_SLOTS_PER_EPOCH);
+    genesis_config.rent = Rent::default();
+    let (mut bank, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    let stake_balance = bank.minimum_split_amount();
+
+    // Delegate three stakes to the same vote account, all under the mint's authority
+    let vote_pubkey = create_vote_account(&bank_client, &mint_keypair, 0);
+    let stake_pubkeys = [(); 3]
+        .map(|()| create_delegated_stake(&bank_client, &mint_keypair, &vote_pubkey, stake_balance));
+
+    while !stake_pubkeys
+        .iter()
+        .all(|stake_pubkey| warmed_up(&bank, stake_pubkey))
+    {
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+    }
+
+    // Deactivating the third stake makes it incompatible with the other two
+    let bank_client = BankClient::new_shared(bank.clone());
+    let message = Message::new(
+        &[stake_instruction::deactivate_stake(
+            &stake_pubkeys[2],
+            &mint_pubkey,
+        )],
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[&mint_keypair], message)
+        .expect("failed to deactivate stake account");
+
+    let mut expected_pair = [stake_pubkeys[0], stake_pubkeys[1]];
+    expected_pair.sort_unstable();
+    assert_eq!(
+        bank.mergeable_stakes(&mint_pubkey).unwrap(),
+        vec![(expected_pair[0], expected_pair[1])],
+    );
+    assert!(bank
+        .mergeable_stakes(&solana_pubkey::new_rand())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_total_active_stake() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.epoch_schedule = EpochSchedule::new(MINIMUM_SLOTS_PER_EPOCH);
+    genesis_config.rent = Rent::default();
+    let (mut bank, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    // The bootstrap validator's stake is active from genesis
+    let bootstrap_stake = bank.total_active_stake(bank.epoch());
+    assert!(bootstrap_stake > 0);
+
+    let stake_balance = bank.minimum_split_amount();
+
+    // Delegate two stakes
+    let (vote_pubkey, stake_pubkey) =
+        create_vote_and_delegated_stake(&bank_client, &mint_keypair, stake_balance);
+    let other_stake_pubkey =
+        create_delegated_stake(&bank_client, &mint_keypair, &vote_pubkey, stake_balance);
+    // Delegated stake is not effective until it warms up
+    assert_eq!(bank.total_active_stake(bank.epoch()), bootstrap_stake);
+
+    while !warmed_up(&bank, &stake_pubkey) || !warmed_up(&bank, &other_stake_pubkey) {
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+    }
+    assert_eq!(
+        bank.total_active_stake(bank.epoch()),
+        bootstrap_stake + get_staked(&bank, &stake_pubkey) + get_staked(&bank, &other_stake_pubkey),
+    );
+
+    // Once cooled down, the deactivated stake contributes nothing
+    let bank_client = BankClient::new_shared(bank.clone());
+    let message = Message::new(
+        &[stake_instruction::deactivate_stake(
+            &other_stake_pubkey,
+            &mint_pubkey,
+        )],
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[&mint_keypair], message)
+        .expect("failed to deactivate stake account");
+    let expected_total_active_stake = bootstrap_stake + get_staked(&bank, &stake_pubkey);
+    for _ in 0..10 {
+        if bank.total_active_stake(bank.epoch()) == expected_total_active_stake {
+            break;
+        }
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+    }
+    assert_eq!(
+        bank.total_active_stake(bank.epoch()),
+        expected_total_active_stake
+    );
+}
+
+#[test]
+fn test_stake_activation_epoch() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.epoch_schedule = EpochSchedule::new(MINIMUM_SLOTS_PER_EPOCH);
+    genesis_config.rent = Rent::default();
+    let (bank, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 1);
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    assert_eq!(
+        bank.warmup_cooldown_rate_epoch(),
+        bank.new_warmup_cooldown_rate_epoch(),
+    );
+
+    // Only delegated stake accounts have an activation epoch
+    let vote_pubkey = create_vote_account(&bank_client, &mint_keypair, 50);
+    assert_eq!(
+        bank.stake_activation_epoch(&solana_pubkey::new_rand()),
+        None
+    );
+    assert_eq!(bank.stake_activation_epoch(&vote_pubkey), None);
+
+    // Create stake account and delegate to vote account
+    let stake_pubkey = create_delegated_stake(
+        &bank_client,
+        &mint_keypair,
+        &vote_pubkey,
+        bank.minimum_split_amount(),
+    );
+
+    let activation_epoch = bank.epoch();
+    assert_ne!(activation_epoch, 0);
+    assert_eq!(
+        bank.stake_activation_epoch(&stake_pubkey),
+        Some(activation_epoch),
+    );
+
+    // The activation epoch does not change once later epochs are reached
+    let bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+    assert_ne!(bank.epoch(), activation_epoch);
+    assert_eq!(
+        bank.stake_activation_epoch(&stake_pubkey),
+        Some(activation_epoch),
+    );
+}
+
+#[test]
+fn test_stake_deactivation_remaining_epochs() {
+    let GenesisConfigInfo {
+        mut genesis_config,
+        mint_keypair,
+        ..
+    } = create_genesis_config_with_leader(
+        100_000_000_000,
+        &solana_pubkey::new_rand(),
+        2_000_000_000,
+    );
+    genesis_config.epoch_schedule = EpochSchedule::new(MINIMUM_SLOTS_PER_EPOCH);
+    genesis_config.rent = Rent::default();
+    let (mut bank, bank_forks) = Bank::new_with_bank_forks_for_tests(&genesis_config);
+    let mint_pubkey = mint_keypair.pubkey();
+    let bank_client = BankClient::new_shared(bank.clone());
+
+    // Delegate a stake much larger than the bootstrap validator's, so it takes several epochs to
+    // cool down
+    let (vote_pubkey, stake_pubkey) =
+        create_vote_and_delegated_stake(&bank_client, &mint_keypair, 50_000_000_000);
+
+    // Only deactivated stakes have a cooldown
+    assert_eq!(
+        bank.stake_deactivation_remaining_epochs(&stake_pubkey),
+        None
+    );
+    assert_eq!(bank.stake_deactivation_remaining_epochs(&vote_pubkey), None);
+    while !warmed_up(&bank, &stake_pubkey) {
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+    }
+    assert_eq!(
+        bank.stake_deactivation_remaining_epochs(&stake_pubkey),
+        None
+    );
+
+    let bank_client = BankClient::new_shared(bank.clone());
+    let message = Message::new(
+        &[stake_instruction::deactivate_stake(
+            &stake_pubkey,
+            &mint_pubkey,
+        )],
+        Some(&mint_pubkey),
+    );
+    bank_client
+        .send_and_confirm_message(&[&mint_keypair], message)
+        .expect("failed to deactivate stake account");
+
+    let initial_remaining_epochs = bank
+        .stake_deactivation_remaining_epochs(&stake_pubkey)
+        .unwrap();
+    assert!(initial_remaining_epochs > 1);
+    let mut remaining_epochs = initial_remaining_epochs;
+    while get_staked(&bank, &stake_pubkey) > 0 {
+        bank = next_epoch_and_n_slots(bank, bank_forks.as_ref(), 0);
+        let next_remaining_epochs = bank
+            .stake_deactivation_remaining_epochs(&stake_pubkey)
+            .unwrap();
+        assert!(next_remaining_epochs <= remaining_epochs);
+        remaining_epochs = next_remaining_epochs;
+    }
+    assert_eq!(remaining_epochs, 0);
+    assert_eq!(
+        bank.stake_deactivation_remaining_epochs(&stake_pubkey),
+        Some(0),
+    );
+}
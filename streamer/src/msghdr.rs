@@ -25,3 +25,18 @@ pub(crate) fn create_msghdr(
     msg_hdr.msg_flags = 0;
     msg_hdr
 }
+
+/// Like [`create_msghdr`], but without a name, for sockets whose source address is already known
+pub(crate) fn create_unnamed_msghdr(iov: &mut MaybeUninit<iovec>) -> msghdr {
+    // Cannot construct msghdr directly on musl
+    // See https://github.com/rust-lang/libc/issues/2344 for more info
+    let mut msg_hdr: msghdr = unsafe { zeroed() };
+    msg_hdr.msg_name = ptr::null::<libc::c_void>() as *mut _;
+    msg_hdr.msg_namelen = 0;
+    msg_hdr.msg_iov = iov.as_mut_ptr();
+    msg_hdr.msg_iovlen = 1;
+    msg_hdr.msg_control = ptr::null::<libc::c_void>() as *mut _;
+    msg_hdr.msg_controllen = 0;
+    msg_hdr.msg_flags = 0;
+    msg_hdr
+}
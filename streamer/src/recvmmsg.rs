@@ -3,55 +3,669 @@
 pub use solana_perf::packet::PACKETS_PER_BATCH;
 #[cfg(target_os = "linux")]
 use {
-    crate::msghdr::create_msghdr,
+    crate::msghdr::{create_msghdr, create_unnamed_msghdr},
     itertools::izip,
     libc::{iovec, mmsghdr, sockaddr_storage, socklen_t, AF_INET, AF_INET6, MSG_WAITFORONE},
     std::{
-        mem::{self, MaybeUninit},
-        net::{SocketAddr, SocketAddrV4, SocketAddrV6},
+        net::{Ipv4Addr, SocketAddrV4, SocketAddrV6},
         os::unix::io::AsRawFd,
+        sync::{atomic::AtomicBool, OnceLock},
     },
 };
 use {
-    crate::packet::{Meta, Packet},
-    std::{cmp, io, net::UdpSocket},
+    crate::packet::{Meta, Packet, PacketBatch, PACKET_DATA_SIZE},
+    std::{
+        cmp,
+        collections::{HashMap, HashSet, VecDeque},
+        hash::{DefaultHasher, Hash, Hasher},
+        io,
+        mem::{self, MaybeUninit},
+        net::{IpAddr, SocketAddr, UdpSocket},
+        ops::{Deref, DerefMut, Range},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        },
+        time::{Duration, Instant},
+    },
 };
 
 #[cfg(not(target_os = "linux"))]
 pub fn recv_mmsg(socket: &UdpSocket, packets: &mut [Packet]) -> io::Result</*num packets:*/ usize> {
+    recv_mmsg_up_to(socket, packets, PACKETS_PER_BATCH)
+}
+
+/// Whether recvmmsg(7) works here
+///
+/// Sandboxes may filter out the syscall, so the first call probes it with a non-blocking receive
+/// on a throwaway socket, and the result is reused after that.  When it does not work,
+/// [`recv_mmsg`] falls back to one recv_from() call per packet.
+#[cfg(target_os = "linux")]
+pub fn recvmmsg_supported() -> bool {
+    static RECVMMSG_SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *RECVMMSG_SUPPORTED.get_or_init(probe_recvmmsg)
+}
+
+/// Whether recvmmsg(7) works here
+///
+/// It is Linux-only, so [`recv_mmsg`] always uses one recv_from() call per packet elsewhere.
+#[cfg(not(target_os = "linux"))]
+pub fn recvmmsg_supported() -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn probe_recvmmsg() -> bool {
+    let Ok(sock) = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)) else {
+        // Without a socket to probe with, assume the syscall works, as it usually does
+        return true;
+    };
+    let mut buffer = [0u8; 1];
+    let mut iov = MaybeUninit::new(iovec {
+        iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buffer.len(),
+    });
+    let mut hdr = mmsghdr {
+        msg_len: 0,
+        msg_hdr: create_unnamed_msghdr(&mut iov),
+    };
+    // SAFETY: `hdr` points to `iov`, which points to `buffer`, and all of them outlive the call
+    // TODO: remove .try_into().unwrap() once rust libc fixes recvmmsg types for musl
+    #[allow(clippy::useless_conversion)]
+    let nrecv = unsafe {
+        libc::recvmmsg(
+            sock.as_raw_fd(),
+            &mut hdr,
+            1,
+            libc::MSG_DONTWAIT.try_into().unwrap(),
+            std::ptr::null_mut(),
+        )
+    };
+    if nrecv >= 0 {
+        return true;
+    }
+    // Nothing was sent, so a working recvmmsg(7) would report there is nothing to receive
+    let err = io::Error::last_os_error();
+    !matches!(
+        err.raw_os_error(),
+        Some(libc::ENOSYS | libc::EPERM | libc::EACCES)
+    )
+}
+
+/// The outcome of [`try_recv_mmsg`]
+///
+/// Packets may have been received even if an error occurred; the first `packets_received`
+/// packets are valid either way.
+#[derive(Debug)]
+pub struct RecvMmsgResult {
+    /// Number of packets received
+    pub packets_received: usize,
+    /// The error that stopped receiving, if any
+    pub error: Option<io::Error>,
+}
+
+/// Receive multiple messages from `sock` into `packets`, keeping the packets received before an
+/// error occurred
+///
+/// Behaves like [`recv_mmsg`], except that an error does not discard the packets already
+/// received.  recvmmsg(7) receives atomically, so on Linux either packets or an error are
+/// returned, never both.
+#[cfg(target_os = "linux")]
+pub fn try_recv_mmsg(sock: &UdpSocket, packets: &mut [Packet]) -> RecvMmsgResult {
+    match recv_mmsg(sock, packets) {
+        Ok(packets_received) => RecvMmsgResult {
+            packets_received,
+            error: None,
+        },
+        Err(err) => RecvMmsgResult {
+            packets_received: 0,
+            error: Some(err),
+        },
+    }
+}
+
+/// Receive multiple messages from `sock` into `packets`, keeping the packets received before an
+/// error occurred
+///
+/// Behaves like [`recv_mmsg`], except that an error does not discard the packets already
+/// received.
+#[cfg(not(target_os = "linux"))]
+pub fn try_recv_mmsg(sock: &UdpSocket, packets: &mut [Packet]) -> RecvMmsgResult {
+    try_recv_mmsg_up_to(sock, packets, PACKETS_PER_BATCH)
+}
+
+/// Receive up to `batch_size` packets, one recv_from() call at a time
+///
+/// This is the implementation of [`recv_mmsg`] on platforms without recvmmsg(7), and on Linux when
+/// [`recvmmsg_supported`] finds that recvmmsg(7) does not work.
+fn recv_mmsg_up_to(
+    socket: &UdpSocket,
+    packets: &mut [Packet],
+    batch_size: usize,
+) -> io::Result</*num packets:*/ usize> {
+    match try_recv_mmsg_up_to(socket, packets, batch_size) {
+        RecvMmsgResult {
+            packets_received: 0,
+            error: Some(err),
+        } => Err(err),
+        RecvMmsgResult {
+            packets_received, ..
+        } => Ok(packets_received),
+    }
+}
+
+/// Like [`recv_mmsg_up_to`], but reports the packets received before an error occurred
+fn try_recv_mmsg_up_to(
+    socket: &UdpSocket,
+    packets: &mut [Packet],
+    batch_size: usize,
+) -> RecvMmsgResult {
+    try_recv_mmsg_up_to_with(packets, batch_size, |i, buffer| {
+        if i == 0 {
+            // Only block until the first packet arrives
+            socket.recv_from(buffer)
+        } else {
+            recv_from_nonblocking(socket, buffer)
+        }
+    })
+}
+
+/// Receive a packet from `socket` into `buffer` without blocking, whatever the socket's mode
+///
+/// The fallbacks read the rest of a batch with this once the first packet arrives, so they never
+/// change whether the socket is blocking.
+fn recv_from_nonblocking(socket: &UdpSocket, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    let (size, from) = socket2::SockRef::from(socket)
+        .recv_from_with_flags(as_uninit_buffer(buffer), libc::MSG_DONTWAIT)?;
+    let from = from
+        .as_socket()
+        .ok_or_else(|| io::Error::other("received from a non-IP address"))?;
+    Ok((size, from))
+}
+
+/// Receive a packet from the connected `socket` into `buffer` without blocking, whatever the
+/// socket's mode
+#[cfg(not(target_os = "linux"))]
+fn recv_nonblocking(socket: &UdpSocket, buffer: &mut [u8]) -> io::Result<usize> {
+    socket2::SockRef::from(socket).recv_with_flags(as_uninit_buffer(buffer), libc::MSG_DONTWAIT)
+}
+
+fn as_uninit_buffer(buffer: &mut [u8]) -> &mut [MaybeUninit<u8>] {
+    // SAFETY: `MaybeUninit<u8>` has the same layout as `u8`, and receiving only ever writes
+    // initialized bytes, so `buffer` stays initialized
+    unsafe { &mut *(buffer as *mut [u8] as *mut [MaybeUninit<u8>]) }
+}
+
+/// Receive up to `batch_size` packets, one `recv_from(packet index, buffer)` call at a time
+///
+/// Stops at the first error.  Running out of packets to read, once at least one was received,
+/// is expected and not reported as an error.
+fn try_recv_mmsg_up_to_with(
+    packets: &mut [Packet],
+    batch_size: usize,
+    mut recv_from: impl FnMut(usize, &mut [u8]) -> io::Result<(usize, SocketAddr)>,
+) -> RecvMmsgResult {
     debug_assert!(packets.iter().all(|pkt| pkt.meta() == &Meta::default()));
-    let mut i = 0;
-    let count = cmp::min(PACKETS_PER_BATCH, packets.len());
+    let mut packets_received = 0;
+    let count = cmp::min(batch_size, packets.len());
     for p in packets.iter_mut().take(count) {
         p.meta_mut().size = 0;
-        match socket.recv_from(p.buffer_mut()) {
-            Err(_) if i > 0 => {
+        match recv_from(packets_received, p.buffer_mut()) {
+            Err(e) if packets_received > 0 && e.kind() == io::ErrorKind::WouldBlock => {
                 break;
             }
             Err(e) => {
-                return Err(e);
+                return RecvMmsgResult {
+                    packets_received,
+                    error: Some(e),
+                };
             }
             Ok((nrecv, from)) => {
                 p.meta_mut().size = nrecv;
                 p.meta_mut().set_socket_addr(&from);
-                if i == 0 {
-                    socket.set_nonblocking(true)?;
-                }
             }
         }
-        i += 1;
+        packets_received += 1;
+    }
+    RecvMmsgResult {
+        packets_received,
+        error: None,
+    }
+}
+
+/// Reusable scratch buffers for [`recv_mmsg_buffered`]
+///
+/// [`recv_mmsg`] receives at most [`PACKETS_PER_BATCH`] packets per call, since its scratch space
+/// lives on the stack.  These buffers are heap-allocated once by the caller and reused across
+/// calls, so the batch size may be chosen at runtime.
+pub struct RecvMmsgBuffers {
+    #[cfg(target_os = "linux")]
+    iovs: Vec<MaybeUninit<iovec>>,
+    #[cfg(target_os = "linux")]
+    addrs: Vec<MaybeUninit<sockaddr_storage>>,
+    #[cfg(target_os = "linux")]
+    hdrs: Vec<MaybeUninit<mmsghdr>>,
+    batch_size: usize,
+}
+
+impl RecvMmsgBuffers {
+    /// Allocates buffers able to receive up to `batch_size` packets per call
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            #[cfg(target_os = "linux")]
+            iovs: vec![MaybeUninit::uninit(); batch_size],
+            #[cfg(target_os = "linux")]
+            addrs: vec![MaybeUninit::zeroed(); batch_size],
+            #[cfg(target_os = "linux")]
+            hdrs: vec![MaybeUninit::uninit(); batch_size],
+            batch_size,
+        }
+    }
+
+    /// The maximum number of packets received per call
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+/// Receive multiple messages from `sock` into `packets`, using the caller-owned `buffers` as
+/// scratch space.
+///
+/// Behaves like [`recv_mmsg`], except that up to `buffers.batch_size()` packets are received per
+/// call, instead of [`PACKETS_PER_BATCH`].
+#[cfg(not(target_os = "linux"))]
+pub fn recv_mmsg_buffered(
+    socket: &UdpSocket,
+    packets: &mut [Packet],
+    buffers: &mut RecvMmsgBuffers,
+) -> io::Result</*num packets:*/ usize> {
+    recv_mmsg_up_to(socket, packets, buffers.batch_size())
+}
+
+/// A pool of reusable packet buffers for [`recv_mmsg_pooled`]
+///
+/// Buffers are handed out by [`PacketBatchPool::checkout`] and returned to the pool when the
+/// [`PooledPacketBatch`] is dropped.  Every packet's `Meta` is reset on return, so the buffers are
+/// always ready for the next receive.
+pub struct PacketBatchPool {
+    batches: Mutex<Vec<Vec<Packet>>>,
+    batch_size: usize,
+}
+
+impl PacketBatchPool {
+    /// Creates an empty pool that hands out buffers of `batch_size` packets
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batches: Mutex::default(),
+            batch_size,
+        }
+    }
+
+    /// Takes a buffer from the pool, allocating a new one if the pool is empty
+    pub fn checkout(&self) -> PooledPacketBatch<'_> {
+        let packets = self
+            .batches
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| vec![Packet::default(); self.batch_size]);
+        PooledPacketBatch {
+            packets,
+            len: 0,
+            pool: self,
+        }
+    }
+
+    /// The number of buffers currently available in the pool
+    pub fn num_available(&self) -> usize {
+        self.batches.lock().unwrap().len()
+    }
+
+    fn recycle(&self, mut packets: Vec<Packet>) {
+        packets
+            .iter_mut()
+            .for_each(|pkt| *pkt.meta_mut() = Meta::default());
+        self.batches.lock().unwrap().push(packets);
+    }
+}
+
+/// A packet buffer checked out from a [`PacketBatchPool`]
+///
+/// Dereferences to the packets that were received into it.  The buffer is returned to its pool
+/// on drop.
+pub struct PooledPacketBatch<'a> {
+    packets: Vec<Packet>,
+    len: usize,
+    pool: &'a PacketBatchPool,
+}
+
+impl Deref for PooledPacketBatch<'_> {
+    type Target = [Packet];
+
+    fn deref(&self) -> &Self::Target {
+        &self.packets[..self.len]
+    }
+}
+
+impl DerefMut for PooledPacketBatch<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.packets[..self.len]
+    }
+}
+
+impl Drop for PooledPacketBatch<'_> {
+    fn drop(&mut self) {
+        self.pool.recycle(mem::take(&mut self.packets));
+    }
+}
+
+/// Receive multiple messages from `sock` into a buffer checked out from `pool`
+///
+/// Behaves like [`recv_mmsg`], but returns the received packets in a [`PooledPacketBatch`], which
+/// goes back to `pool` once dropped.
+pub fn recv_mmsg_pooled<'a>(
+    sock: &UdpSocket,
+    pool: &'a PacketBatchPool,
+) -> io::Result<PooledPacketBatch<'a>> {
+    let mut batch = pool.checkout();
+    batch.len = recv_mmsg(sock, &mut batch.packets)?;
+    Ok(batch)
+}
+
+/// A source of packets that can be received in batches
+///
+/// Implemented for [`UdpSocket`], and, for tests, by [`InMemorySocket`], which yields packets in
+/// a controlled order instead of relying on the kernel's.
+pub trait RecvMmsg {
+    /// Receive multiple messages into `packets`, as [`recv_mmsg`] does
+    fn recv_mmsg(&self, packets: &mut [Packet]) -> io::Result</*num packets:*/ usize>;
+}
+
+impl RecvMmsg for UdpSocket {
+    fn recv_mmsg(&self, packets: &mut [Packet]) -> io::Result</*num packets:*/ usize> {
+        recv_mmsg(self, packets)
+    }
+}
+
+/// An in-memory packet source, which yields packets in the order they were pushed
+///
+/// Behaves like a nonblocking socket: receiving when no packets are queued fails with
+/// [`io::ErrorKind::WouldBlock`].
+#[cfg(feature = "dev-context-only-utils")]
+#[derive(Debug, Default)]
+pub struct InMemorySocket {
+    queue: Mutex<VecDeque<(SocketAddr, Vec<u8>)>>,
+}
+
+#[cfg(feature = "dev-context-only-utils")]
+impl InMemorySocket {
+    /// Queue a packet with `data`, sent from `from`
+    pub fn push(&self, from: SocketAddr, data: &[u8]) {
+        self.queue.lock().unwrap().push_back((from, data.to_vec()));
+    }
+
+    /// Returns the number of packets queued
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Returns true if no packets are queued
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(feature = "dev-context-only-utils")]
+impl RecvMmsg for InMemorySocket {
+    fn recv_mmsg(&self, packets: &mut [Packet]) -> io::Result</*num packets:*/ usize> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let count = cmp::min(PACKETS_PER_BATCH, packets.len());
+        let mut nrecv = 0;
+        for packet in packets.iter_mut().take(count) {
+            let Some((from, data)) = queue.pop_front() else {
+                break;
+            };
+            // Like a datagram socket, truncate data that does not fit
+            let buffer = packet.buffer_mut();
+            let size = cmp::min(buffer.len(), data.len());
+            buffer[..size].copy_from_slice(&data[..size]);
+            let meta = packet.meta_mut();
+            meta.size = size;
+            meta.set_socket_addr(&from);
+            nrecv += 1;
+        }
+        Ok(nrecv)
+    }
+}
+
+/// Why a received packet was discarded
+///
+/// [`Meta`] only has a flag for whether a packet was discarded, so the receive functions that
+/// discard packets report why in a slice of reasons parallel to `packets`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiscardReason {
+    /// The packet was smaller than the minimum valid size
+    TooSmall,
+    /// The packet was sent from a source that is not allowed
+    SourceNotAllowed,
+    /// The packet's payload was already seen
+    Duplicate,
+}
+
+/// Receive multiple messages from `sock` into `packets`, discarding any not sent from `allow`
+///
+/// Packets from sources outside of `allow` are still received, but have their size zeroed and
+/// are marked as discarded, so they are cheap for downstream stages to skip.  Returns the number
+/// of packets received, and the number of those that were kept.  Receives at most as many
+/// packets as `discard_reasons` holds, and sets each received packet's reason.
+pub fn recv_mmsg_filtered(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    allow: &HashSet<IpAddr>,
+    discard_reasons: &mut [Option<DiscardReason>],
+) -> io::Result<(/*num packets:*/ usize, /*num kept:*/ usize)> {
+    recv_mmsg_discarding(sock, packets, discard_reasons, |packet| {
+        (!allow.contains(&packet.meta().addr)).then_some(DiscardReason::SourceNotAllowed)
+    })
+}
+
+/// Receive multiple messages from `sock` into `packets`, discarding any smaller than `min_size`
+///
+/// Packets that are too small to be valid are still received, but have their size zeroed and are
+/// marked as discarded, so sigverify can skip them without parsing.  Returns the number of
+/// packets received, and the number of those that were kept.  Receives at most as many packets
+/// as `discard_reasons` holds, and sets each received packet's reason.
+pub fn recv_mmsg_min_size(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    min_size: usize,
+    discard_reasons: &mut [Option<DiscardReason>],
+) -> io::Result<(/*num packets:*/ usize, /*num kept:*/ usize)> {
+    recv_mmsg_discarding(sock, packets, discard_reasons, |packet| {
+        (packet.meta().size < min_size).then_some(DiscardReason::TooSmall)
+    })
+}
+
+/// Receive multiple messages from `sock` into `packets`, discarding those already `seen`
+///
+/// A cheap first pass before the full dedup downstream: each packet's payload is hashed, and
+/// packets whose hash is already in `seen` are discarded, like [`recv_mmsg_filtered`] does, while
+/// new hashes are added to it.  Returns the number of packets received, and the number of those
+/// that were kept.  Receives at most as many packets as `discard_reasons` holds, and sets each
+/// received packet's reason.
+///
+/// `seen` only ever grows, so callers should clear it periodically, e.g. once per slot, to bound
+/// its memory and to let legitimately repeated payloads through again.
+pub fn recv_mmsg_dedup(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    seen: &mut HashSet<u64>,
+    discard_reasons: &mut [Option<DiscardReason>],
+) -> io::Result<(/*num packets:*/ usize, /*num kept:*/ usize)> {
+    recv_mmsg_discarding(sock, packets, discard_reasons, |packet| {
+        let mut hasher = DefaultHasher::new();
+        packet.data(..).unwrap_or_default().hash(&mut hasher);
+        (!seen.insert(hasher.finish())).then_some(DiscardReason::Duplicate)
+    })
+}
+
+/// Receive multiple messages from `sock` into `packets`, discarding those `should_discard`
+/// returns a reason for
+///
+/// Discarded packets have their size zeroed and are marked as discarded.
+fn recv_mmsg_discarding(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    discard_reasons: &mut [Option<DiscardReason>],
+    mut should_discard: impl FnMut(&Packet) -> Option<DiscardReason>,
+) -> io::Result<(/*num packets:*/ usize, /*num kept:*/ usize)> {
+    let count = cmp::min(packets.len(), discard_reasons.len());
+    let nrecv = recv_mmsg(sock, &mut packets[..count])?;
+    let mut nkept = 0;
+    for (packet, discard_reason) in packets.iter_mut().zip(discard_reasons).take(nrecv) {
+        *discard_reason = should_discard(packet);
+        let meta = packet.meta_mut();
+        if discard_reason.is_some() {
+            meta.size = 0;
+            meta.set_discard(true);
+        } else {
+            nkept += 1;
+        }
+    }
+    Ok((nrecv, nkept))
+}
+
+/// Receive multiple messages from `sock` into `packets`, counting the packets from each source
+///
+/// `counts` is incremented, not reset, so callers may pass the same map across calls to
+/// aggregate counts over multiple batches.
+pub fn recv_mmsg_with_counts(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    counts: &mut HashMap<IpAddr, u32>,
+) -> io::Result</*num packets:*/ usize> {
+    let nrecv = recv_mmsg(sock, packets)?;
+    for packet in packets.iter().take(nrecv) {
+        let count = counts.entry(packet.meta().addr).or_default();
+        *count = count.saturating_add(1);
+    }
+    Ok(nrecv)
+}
+
+/// Receive multiple messages from `sock` into `packets`, grouping consecutive packets by source
+///
+/// Returns the received packets as contiguous ranges of `packets` that share a source address,
+/// in order.  Only adjacent packets are grouped, so a source whose packets are interleaved with
+/// another's gets a range for each run.
+pub fn recv_mmsg_grouped(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result<Vec<(SocketAddr, Range<usize>)>> {
+    let nrecv = recv_mmsg(sock, packets)?;
+    let mut groups: Vec<(SocketAddr, Range<usize>)> = Vec::new();
+    for (index, packet) in packets.iter().enumerate().take(nrecv) {
+        let addr = packet.meta().socket_addr();
+        match groups.last_mut() {
+            Some((group_addr, range)) if *group_addr == addr => range.end = index + 1,
+            _ => groups.push((addr, index..index + 1)),
+        }
     }
-    Ok(i)
+    Ok(groups)
+}
+
+/// Receive multiple messages from `sock` into `packets`, recording how many were received
+///
+/// Each successful call increments the `histogram` bucket for the number of packets received,
+/// which shows whether receives are mostly small (latency bound) or full (throughput bound).
+pub fn recv_mmsg_instrumented(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    histogram: &mut [u64; PACKETS_PER_BATCH + 1],
+) -> io::Result</*num packets:*/ usize> {
+    let nrecv = recv_mmsg(sock, packets)?;
+    histogram[nrecv] = histogram[nrecv].saturating_add(1);
+    Ok(nrecv)
+}
+
+/// Receive multiple messages from `sock` into `packets`, along with how long the receive took
+///
+/// The duration is the wall-clock time spent in [`recv_mmsg`], which is nearly all the time spent
+/// waiting in recvmmsg(7), so ingest threads can tell time blocked waiting for packets apart from
+/// time spent processing them.  If packets are already queued, it is short.  If the socket is idle,
+/// it includes blocking until the first packet arrives, which is up to the socket's read timeout,
+/// if any, and is not bounded by the 1 second recvmmsg(7) timeout, see [`recv_mmsg`].
+pub fn recv_mmsg_timed(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result<(
+    /*num packets:*/ usize,
+    /*recv duration:*/ Duration,
+)> {
+    let start = Instant::now();
+    let nrecv = recv_mmsg(sock, packets)?;
+    Ok((nrecv, start.elapsed()))
+}
+
+/// Receive multiple messages from `sock` into `packets`, along with the CPU that received them
+///
+/// Behaves like [`recv_mmsg`], and additionally returns the socket's `SO_INCOMING_CPU`.  The
+/// kernel only reports the CPU that most recently processed a packet for the socket, not one per
+/// packet, so this is a hint for the whole batch.  Returns None for the CPU if it is unknown, or
+/// unsupported on this platform.
+pub fn recv_mmsg_with_cpu(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result<(
+    /*num packets:*/ usize,
+    /*incoming cpu:*/ Option<u32>,
+)> {
+    let nrecv = recv_mmsg(sock, packets)?;
+    Ok((nrecv, incoming_cpu(sock)))
+}
+
+#[cfg(target_os = "linux")]
+fn incoming_cpu(sock: &UdpSocket) -> Option<u32> {
+    let mut cpu: libc::c_int = -1;
+    let mut len = mem::size_of_val(&cpu) as socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_INCOMING_CPU,
+            &mut cpu as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    // The kernel reports -1 until a packet has been received
+    (ret == 0).then(|| u32::try_from(cpu).ok()).flatten()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn incoming_cpu(_sock: &UdpSocket) -> Option<u32> {
+    None
 }
 
 #[cfg(target_os = "linux")]
 fn cast_socket_addr(addr: &sockaddr_storage, hdr: &mmsghdr) -> Option<SocketAddr> {
+    let socket_addr = sockaddr_to_socket_addr(addr, hdr.msg_hdr.msg_namelen);
+    if socket_addr.is_none() {
+        error!(
+            "recvmmsg unexpected ss_family:{} msg_namelen:{}",
+            addr.ss_family, hdr.msg_hdr.msg_namelen
+        );
+    }
+    socket_addr
+}
+
+#[cfg(target_os = "linux")]
+fn sockaddr_to_socket_addr(addr: &sockaddr_storage, addr_len: socklen_t) -> Option<SocketAddr> {
     use libc::{sa_family_t, sockaddr_in, sockaddr_in6};
     const SOCKADDR_IN_SIZE: usize = std::mem::size_of::<sockaddr_in>();
     const SOCKADDR_IN6_SIZE: usize = std::mem::size_of::<sockaddr_in6>();
-    if addr.ss_family == AF_INET as sa_family_t
-        && hdr.msg_hdr.msg_namelen == SOCKADDR_IN_SIZE as socklen_t
-    {
+    if addr.ss_family == AF_INET as sa_family_t && addr_len == SOCKADDR_IN_SIZE as socklen_t {
         // ref: https://github.com/rust-lang/socket2/blob/65085d9dff270e588c0fbdd7217ec0b392b05ef2/src/sockaddr.rs#L167-L172
         let addr = unsafe { &*(addr as *const _ as *const sockaddr_in) };
         return Some(SocketAddr::V4(SocketAddrV4::new(
@@ -59,9 +673,7 @@ fn cast_socket_addr(addr: &sockaddr_storage, hdr: &mmsghdr) -> Option<SocketAddr
             u16::from_be(addr.sin_port),
         )));
     }
-    if addr.ss_family == AF_INET6 as sa_family_t
-        && hdr.msg_hdr.msg_namelen == SOCKADDR_IN6_SIZE as socklen_t
-    {
+    if addr.ss_family == AF_INET6 as sa_family_t && addr_len == SOCKADDR_IN6_SIZE as socklen_t {
         // ref: https://github.com/rust-lang/socket2/blob/65085d9dff270e588c0fbdd7217ec0b392b05ef2/src/sockaddr.rs#L174-L189
         let addr = unsafe { &*(addr as *const _ as *const sockaddr_in6) };
         return Some(SocketAddr::V6(SocketAddrV6::new(
@@ -71,13 +683,123 @@ fn cast_socket_addr(addr: &sockaddr_storage, hdr: &mmsghdr) -> Option<SocketAddr
             addr.sin6_scope_id,
         )));
     }
-    error!(
-        "recvmmsg unexpected ss_family:{} msg_namelen:{}",
-        addr.ss_family, hdr.msg_hdr.msg_namelen
-    );
     None
 }
 
+/// Enables queueing of extended errors (e.g. ICMP errors) on `sock`
+///
+/// Once enabled, the queued errors may be read with [`recv_mmsg_errqueue`].
+#[cfg(target_os = "linux")]
+pub fn set_recv_err(sock: &UdpSocket) -> io::Result<()> {
+    let (level, name) = match sock.local_addr()? {
+        SocketAddr::V4(_) => (libc::SOL_IP, libc::IP_RECVERR),
+        SocketAddr::V6(_) => (libc::SOL_IPV6, libc::IPV6_RECVERR),
+    };
+    let enable: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            &enable as *const libc::c_int as *const libc::c_void,
+            mem::size_of_val(&enable) as socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Drain the error queue of `sock`, appending each queued error to `out`
+///
+/// Errors are reported as the address of the offender (e.g. the host that sent an ICMP error)
+/// along with the errno (e.g. `ECONNREFUSED` for an ICMP port-unreachable).  If the error does
+/// not have an offender, the original destination address is reported instead.
+///
+/// [`set_recv_err`] must have been called on `sock`, otherwise the kernel does not queue errors.
+/// This function never blocks; it returns once the error queue is empty.
+///
+/// Returns the number of errors appended to `out`.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_errqueue(
+    sock: &UdpSocket,
+    out: &mut Vec<(SocketAddr, io::Error)>,
+) -> io::Result</*num errors:*/ usize> {
+    const SOCKADDR_STORAGE_SIZE: socklen_t = mem::size_of::<sockaddr_storage>() as socklen_t;
+    const SOCK_EXTENDED_ERR_SIZE: usize = mem::size_of::<libc::sock_extended_err>();
+
+    let sock_fd = sock.as_raw_fd();
+    let num_errors = out.len();
+    loop {
+        // The payload of the packet that caused the error is not needed
+        let mut iov = MaybeUninit::new(iovec {
+            iov_base: std::ptr::null_mut(),
+            iov_len: 0,
+        });
+        let mut addr = MaybeUninit::zeroed();
+        // u64s to ensure the control messages are properly aligned
+        let mut control = [0u64; 64];
+        let mut msg_hdr = create_msghdr(&mut addr, SOCKADDR_STORAGE_SIZE, &mut iov);
+        msg_hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg_hdr.msg_controllen = mem::size_of_val(&control) as _;
+
+        let nrecv = unsafe {
+            libc::recvmsg(
+                sock_fd,
+                &mut msg_hdr,
+                libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+            )
+        };
+        if nrecv < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                break;
+            }
+            return Err(err);
+        }
+
+        // SAFETY: recvmsg() initialized the address, up to msg_namelen
+        let destination =
+            sockaddr_to_socket_addr(unsafe { addr.assume_init_ref() }, msg_hdr.msg_namelen);
+        // SAFETY: `msg_hdr` points to `control`, which recvmsg() populated, and the CMSG_*
+        // functions do not step past msg_controllen
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg_hdr) };
+        while let Some(cmsg_ref) = unsafe { cmsg.as_ref() } {
+            let is_recv_err = (cmsg_ref.cmsg_level == libc::SOL_IP
+                && cmsg_ref.cmsg_type == libc::IP_RECVERR)
+                || (cmsg_ref.cmsg_level == libc::SOL_IPV6
+                    && cmsg_ref.cmsg_type == libc::IPV6_RECVERR);
+            if is_recv_err {
+                let data = unsafe { libc::CMSG_DATA(cmsg) };
+                let data_len = cmsg_ref.cmsg_len as usize - unsafe { libc::CMSG_LEN(0) } as usize;
+                // SAFETY: an IP_RECVERR/IPV6_RECVERR control message always holds a
+                // sock_extended_err, immediately followed by the offender's address
+                let extended_err =
+                    unsafe { (data as *const libc::sock_extended_err).read_unaligned() };
+                let offender_len = data_len.saturating_sub(SOCK_EXTENDED_ERR_SIZE);
+                let mut offender: sockaddr_storage = unsafe { mem::zeroed() };
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        data.add(SOCK_EXTENDED_ERR_SIZE),
+                        &mut offender as *mut sockaddr_storage as *mut u8,
+                        cmp::min(offender_len, mem::size_of::<sockaddr_storage>()),
+                    );
+                }
+                let offender = sockaddr_to_socket_addr(&offender, offender_len as socklen_t);
+                if let Some(addr) = offender.or(destination) {
+                    out.push((
+                        addr,
+                        io::Error::from_raw_os_error(extended_err.ee_errno as i32),
+                    ));
+                }
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg_hdr, cmsg) };
+        }
+    }
+    Ok(out.len() - num_errors)
+}
+
 /** Receive multiple messages from `sock` into buffer provided in `packets`.
 This is a wrapper around recvmmsg(7) call.
 
@@ -89,42 +811,213 @@ this function
  due to a bug in the linux kernel.
  You may want to call `sock.set_read_timeout(Some(Duration::from_secs(1)));` or similar
  prior to calling this function if you require this to actually time out after 1 second.
+
+ If recvmmsg(7) does not work here, see [`recvmmsg_supported`], packets are received with one
+ recv_from() call each instead.
 */
 #[cfg(target_os = "linux")]
 pub fn recv_mmsg(sock: &UdpSocket, packets: &mut [Packet]) -> io::Result</*num packets:*/ usize> {
-    // Should never hit this, but bail if the caller didn't provide any Packets
-    // to receive into
-    if packets.is_empty() {
-        return Ok(0);
+    if !recvmmsg_supported() {
+        return recv_mmsg_up_to(sock, packets, PACKETS_PER_BATCH);
     }
-    // Assert that there are no leftovers in packets.
-    debug_assert!(packets.iter().all(|pkt| pkt.meta() == &Meta::default()));
-    const SOCKADDR_STORAGE_SIZE: socklen_t = mem::size_of::<sockaddr_storage>() as socklen_t;
-
     let mut iovs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
     let mut addrs = [MaybeUninit::zeroed(); PACKETS_PER_BATCH];
     let mut hdrs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
 
-    let sock_fd = sock.as_raw_fd();
-    let count = cmp::min(iovs.len(), packets.len());
-
-    for (packet, hdr, iov, addr) in
-        izip!(packets.iter_mut(), &mut hdrs, &mut iovs, &mut addrs).take(count)
-    {
-        let buffer = packet.buffer_mut();
-        iov.write(iovec {
-            iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
-            iov_len: buffer.len(),
-        });
+    recv_mmsg_with_scratch(
+        sock,
+        packets,
+        RecvMmsgScratch::new(&mut iovs, &mut hdrs, Some(&mut addrs), None),
+        MSG_WAITFORONE,
+        |_index, _packet, _message| {},
+    )
+}
 
-        let msg_hdr = create_msghdr(addr, SOCKADDR_STORAGE_SIZE, iov);
+/// Set once `MSG_WAITFORONE` has been rejected, so later receives go without it
+#[cfg(target_os = "linux")]
+static WAITFORONE_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
 
-        hdr.write(mmsghdr {
-            msg_len: 0,
-            msg_hdr,
-        });
+/// Calls `recv` with `MSG_WAITFORONE`, or with no flags if that has been found to be unsupported
+///
+/// Some container runtimes reject the flag with `EINVAL`.  If so, and `recv` succeeds once it is
+/// dropped, `unsupported` is set so later calls skip straight to receiving without it.  Without
+/// the flag, recvmmsg(7) blocks until the whole batch is received or the timeout expires.
+#[cfg(target_os = "linux")]
+fn recv_with_waitforone_fallback(
+    unsupported: &AtomicBool,
+    mut recv: impl FnMut(libc::c_int) -> io::Result</*num packets:*/ usize>,
+) -> io::Result</*num packets:*/ usize> {
+    if unsupported.load(Ordering::Relaxed) {
+        return recv(0);
+    }
+    match recv(MSG_WAITFORONE) {
+        Err(err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            let result = recv(0);
+            // If the retry is rejected too, the flag was not the problem
+            if !matches!(&result, Err(err) if err.raw_os_error() == Some(libc::EINVAL)) {
+                warn!("recvmmsg() does not support MSG_WAITFORONE, receiving without it");
+                unsupported.store(true, Ordering::Relaxed);
+            }
+            result
+        }
+        result => result,
+    }
+}
+
+/// Scratch space for receiving multiple messages, one element of each slice per message
+///
+/// This is where the unsafe setup and calls of every recvmmsg(7) wrapper live.  What is received
+/// is chosen per wrapper: each message's source address only if `addrs` are given, e.g. not on
+/// connected sockets, and its control messages only if `controls` are given.
+#[cfg(target_os = "linux")]
+struct RecvMmsgScratch<'a> {
+    iovs: &'a mut [MaybeUninit<iovec>],
+    hdrs: &'a mut [MaybeUninit<mmsghdr>],
+    addrs: Option<&'a mut [MaybeUninit<sockaddr_storage>]>,
+    controls: Option<&'a mut [[u64; CONTROL_LEN]]>,
+    /// The number of messages received by the latest [`RecvMmsgScratch::recv`]
+    nrecv: usize,
+}
+
+// The scratch elements are plain data, so the initialized ones never need dropping
+#[cfg(target_os = "linux")]
+const _: () = assert!(
+    !mem::needs_drop::<iovec>()
+        && !mem::needs_drop::<mmsghdr>()
+        && !mem::needs_drop::<sockaddr_storage>()
+);
+
+/// A message received by [`RecvMmsgScratch::recv`]
+#[cfg(target_os = "linux")]
+struct RecvMmsgMessage<'a> {
+    size: usize,
+    /// The source address, if addresses were requested and it is an IP address
+    addr: Option<SocketAddr>,
+    msg_hdr: &'a libc::msghdr,
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> RecvMmsgScratch<'a> {
+    /// At most as many messages as `hdrs` holds are received per call.  The other slices must be
+    /// the same length.
+    fn new(
+        iovs: &'a mut [MaybeUninit<iovec>],
+        hdrs: &'a mut [MaybeUninit<mmsghdr>],
+        addrs: Option<&'a mut [MaybeUninit<sockaddr_storage>]>,
+        controls: Option<&'a mut [[u64; CONTROL_LEN]]>,
+    ) -> Self {
+        debug_assert_eq!(iovs.len(), hdrs.len());
+        debug_assert!(addrs.as_ref().is_none_or(|addrs| addrs.len() == hdrs.len()));
+        debug_assert!(controls
+            .as_ref()
+            .is_none_or(|controls| controls.len() == hdrs.len()));
+        Self {
+            iovs,
+            hdrs,
+            addrs,
+            controls,
+            nrecv: 0,
+        }
+    }
+
+    /// Receive a message into each of `buffers`, up to the scratch space's length, returning the
+    /// number of messages received
+    ///
+    /// `flags` are passed to recvmmsg(7), and `MSG_WAITFORONE` is dropped if it is found to be
+    /// unsupported, per [`recv_with_waitforone_fallback`].  If recvmmsg(7) does not work here, see
+    /// [`recvmmsg_supported`], each message is received with its own recvmsg(2) call instead.
+    fn recv<'b>(
+        &mut self,
+        sock: &UdpSocket,
+        buffers: impl IntoIterator<Item = &'b mut [MaybeUninit<u8>]>,
+        flags: libc::c_int,
+    ) -> io::Result</*num packets:*/ usize> {
+        const SOCKADDR_STORAGE_SIZE: socklen_t = mem::size_of::<sockaddr_storage>() as socklen_t;
+
+        let Self {
+            iovs,
+            hdrs,
+            addrs,
+            controls,
+            nrecv,
+        } = self;
+        *nrecv = 0;
+        let mut count = 0;
+        for (index, (buffer, iov, hdr)) in
+            izip!(buffers, iovs.iter_mut(), hdrs.iter_mut()).enumerate()
+        {
+            iov.write(iovec {
+                iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+                iov_len: buffer.len(),
+            });
+            let mut msg_hdr = match addrs.as_deref_mut() {
+                Some(addrs) => {
+                    // The scratch space may be reused across calls, so clear out any previous
+                    // address
+                    addrs[index] = MaybeUninit::zeroed();
+                    create_msghdr(&mut addrs[index], SOCKADDR_STORAGE_SIZE, iov)
+                }
+                None => create_unnamed_msghdr(iov),
+            };
+            if let Some(controls) = controls.as_deref_mut() {
+                let control = &mut controls[index];
+                msg_hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+                msg_hdr.msg_controllen = mem::size_of_val(control) as _;
+            }
+            hdr.write(mmsghdr {
+                msg_len: 0,
+                msg_hdr,
+            });
+            count = index + 1;
+        }
+        // Should never hit this, but bail if the caller didn't provide any buffers to receive
+        // into
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let hdrs = &mut hdrs[..count];
+        *nrecv = if !recvmmsg_supported() {
+            recvmsg_each(sock, hdrs, flags)?
+        } else if flags & MSG_WAITFORONE != 0 {
+            recv_with_waitforone_fallback(&WAITFORONE_UNSUPPORTED, |waitforone| {
+                recvmmsg_hdrs(sock, hdrs, (flags & !MSG_WAITFORONE) | waitforone)
+            })?
+        } else {
+            recvmmsg_hdrs(sock, hdrs, flags)?
+        };
+        Ok(*nrecv)
+    }
+
+    /// The messages received by the latest [`RecvMmsgScratch::recv`], in order
+    fn received(&self) -> impl Iterator<Item = RecvMmsgMessage<'_>> {
+        let addrs = self.addrs.as_deref();
+        self.hdrs[..self.nrecv]
+            .iter()
+            .enumerate()
+            .map(move |(index, hdr)| {
+                // SAFETY: recv() initialized the first `count` headers, and addresses if
+                // requested, and the kernel populated the first `nrecv <= count` of them
+                let hdr = unsafe { hdr.assume_init_ref() };
+                let addr = addrs.and_then(|addrs| {
+                    cast_socket_addr(unsafe { addrs[index].assume_init_ref() }, hdr)
+                });
+                RecvMmsgMessage {
+                    size: hdr.msg_len as usize,
+                    addr,
+                    msg_hdr: &hdr.msg_hdr,
+                }
+            })
     }
+}
 
+/// Calls recvmmsg(7) on `sock` for the initialized `hdrs`, with a one second timeout
+#[cfg(target_os = "linux")]
+fn recvmmsg_hdrs(
+    sock: &UdpSocket,
+    hdrs: &mut [MaybeUninit<mmsghdr>],
+    flags: libc::c_int,
+) -> io::Result</*num packets:*/ usize> {
     let mut ts = libc::timespec {
         tv_sec: 1,
         tv_nsec: 0,
@@ -133,72 +1026,861 @@ pub fn recv_mmsg(sock: &UdpSocket, packets: &mut [Packet]) -> io::Result</*num p
     #[allow(clippy::useless_conversion)]
     let nrecv = unsafe {
         libc::recvmmsg(
-            sock_fd,
+            sock.as_raw_fd(),
             hdrs[0].assume_init_mut(),
-            count as u32,
-            MSG_WAITFORONE.try_into().unwrap(),
+            hdrs.len() as u32,
+            flags.try_into().unwrap(),
             &mut ts,
         )
     };
-    let nrecv = if nrecv < 0 {
-        return Err(io::Error::last_os_error());
+    if nrecv < 0 {
+        Err(io::Error::last_os_error())
     } else {
-        usize::try_from(nrecv).unwrap()
+        Ok(usize::try_from(nrecv).unwrap())
+    }
+}
+
+/// Calls recvmsg(2) on `sock` for each of the initialized `hdrs`, for when recvmmsg(7) does not
+/// work here
+///
+/// With `MSG_WAITFORONE`, only the first receive waits, as it would with recvmmsg(7).  Running out
+/// of messages to read, or any other error, once at least one was received, ends the batch.
+#[cfg(target_os = "linux")]
+fn recvmsg_each(
+    sock: &UdpSocket,
+    hdrs: &mut [MaybeUninit<mmsghdr>],
+    flags: libc::c_int,
+) -> io::Result</*num packets:*/ usize> {
+    let (first_flags, rest_flags) = if flags & MSG_WAITFORONE != 0 {
+        let flags = flags & !MSG_WAITFORONE;
+        (flags, flags | libc::MSG_DONTWAIT)
+    } else {
+        (flags, flags)
     };
-    for (addr, hdr, pkt) in izip!(addrs, hdrs, packets.iter_mut()).take(nrecv) {
-        // SAFETY: We initialized `count` elements of `hdrs` above. `count` is
-        // passed to recvmmsg() as the limit of messages that can be read. So,
-        // `nrevc <= count` which means we initialized this `hdr` and
-        // recvmmsg() will have updated it appropriately
-        let hdr_ref = unsafe { hdr.assume_init_ref() };
-        // SAFETY: Similar to above, we initialized this `addr` and recvmmsg()
-        // will have populated it
-        let addr_ref = unsafe { addr.assume_init_ref() };
-        pkt.meta_mut().size = hdr_ref.msg_len as usize;
-        if let Some(addr) = cast_socket_addr(addr_ref, hdr_ref) {
-            pkt.meta_mut().set_socket_addr(&addr);
-        }
-    }
-
-    for (iov, addr, hdr) in izip!(&mut iovs, &mut addrs, &mut hdrs).take(count) {
-        // SAFETY: We initialized `count` elements of each array above
-        //
-        // It may be that `packets.len() != PACKETS_PER_BATCH`; thus, some elements
-        // in `iovs` / `addrs` / `hdrs` may not get initialized. So, we must
-        // manually drop `count` elements from each array instead of being able
-        // to convert [MaybeUninit<T>] to [T] and letting `Drop` do the work
-        // for us when these items go out of scope at the end of the function
-        unsafe {
-            iov.assume_init_drop();
-            addr.assume_init_drop();
-            hdr.assume_init_drop();
+    let mut nrecv = 0;
+    for hdr in hdrs.iter_mut() {
+        let flags = if nrecv == 0 { first_flags } else { rest_flags };
+        // SAFETY: The caller initialized `hdrs`, and what they point to outlives the call
+        let hdr = unsafe { hdr.assume_init_mut() };
+        let size = unsafe { libc::recvmsg(sock.as_raw_fd(), &mut hdr.msg_hdr, flags) };
+        if size < 0 {
+            if nrecv == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            break;
         }
+        hdr.msg_len = size as libc::c_uint;
+        nrecv += 1;
     }
-
     Ok(nrecv)
 }
 
-#[cfg(test)]
-mod tests {
-    use {
-        crate::{packet::PACKET_DATA_SIZE, recvmmsg::*},
-        solana_net_utils::sockets::{
-            bind_in_range_with_config, localhost_port_range_for_tests,
-            SocketConfiguration as SocketConfig,
-        },
-        std::{
-            net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
-            time::{Duration, Instant},
-        },
-    };
+/// Receive multiple messages from `sock` into `packets`, passing `flags` to recvmmsg(7)
+///
+/// Behaves like [`recv_mmsg`], which passes `MSG_WAITFORONE`, except for the flags.  E.g. pass
+/// `MSG_DONTWAIT` to never block, or no flags to block until every packet is received or the
+/// timeout expires.  If nothing is queued and the call would block, `Ok(0)` is returned rather
+/// than a [`io::ErrorKind::WouldBlock`] error.
+///
+/// If [`recvmmsg_supported`] finds that recvmmsg(7) does not work, each packet is received with
+/// its own recvmsg(2) call, passing the same flags.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_with_flags(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    flags: i32,
+) -> io::Result</*num packets:*/ usize> {
+    let mut iovs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let mut addrs = [MaybeUninit::zeroed(); PACKETS_PER_BATCH];
+    let mut hdrs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
 
-    type TestConfig = (UdpSocket, SocketAddr, UdpSocket, SocketAddr);
+    match recv_mmsg_with_scratch(
+        sock,
+        packets,
+        RecvMmsgScratch::new(&mut iovs, &mut hdrs, Some(&mut addrs), None),
+        flags,
+        |_index, _packet, _message| {},
+    ) {
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(0),
+        result => result,
+    }
+}
 
-    fn test_setup_reader_sender(ip: IpAddr) -> io::Result<TestConfig> {
-        let port_range = localhost_port_range_for_tests();
-        let reader = bind_in_range_with_config(ip, port_range, SocketConfig::default())?.1;
-        let reader_addr = reader.local_addr()?;
-        let sender = bind_in_range_with_config(ip, port_range, SocketConfig::default())?.1;
+/// Receive multiple messages from `sock` into `packets`, using the caller-owned `buffers` as
+/// scratch space.
+///
+/// Behaves like [`recv_mmsg`], except that up to `buffers.batch_size()` packets are received per
+/// call, instead of [`PACKETS_PER_BATCH`].
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_buffered(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    buffers: &mut RecvMmsgBuffers,
+) -> io::Result</*num packets:*/ usize> {
+    let RecvMmsgBuffers {
+        iovs, addrs, hdrs, ..
+    } = buffers;
+    recv_mmsg_with_scratch(
+        sock,
+        packets,
+        RecvMmsgScratch::new(iovs, hdrs, Some(addrs.as_mut_slice()), None),
+        MSG_WAITFORONE,
+        |_index, _packet, _message| {},
+    )
+}
+
+/// Peek at the next message queued on `sock`, receiving it into `packets` without consuming it
+///
+/// This is a wrapper around recvmmsg(7) with `MSG_PEEK` set, so the message stays queued and the
+/// next receive, peeking or not, gets it again.  The packet's size and address are populated just
+/// as by [`recv_mmsg`].
+///
+/// Peeking never advances past the first queued message, so at most one packet is received.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_peek_socket(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result</*num packets:*/ usize> {
+    let mut iovs = [MaybeUninit::uninit(); 1];
+    let mut addrs = [MaybeUninit::zeroed(); 1];
+    let mut hdrs = [MaybeUninit::uninit(); 1];
+
+    recv_mmsg_with_scratch(
+        sock,
+        packets,
+        RecvMmsgScratch::new(&mut iovs, &mut hdrs, Some(&mut addrs), None),
+        libc::MSG_PEEK,
+        |_index, _packet, _message| {},
+    )
+}
+
+/// Generic Receive Offload (GRO) details of a received packet, see [`recv_mmsg_gro_info`]
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct GroInfo {
+    /// Size of each coalesced datagram, or 0 if the packet was not coalesced
+    pub segment_size: u16,
+    /// Number of datagrams coalesced into the packet
+    pub num_segments: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl GroInfo {
+    /// Whether multiple datagrams were coalesced into the packet
+    pub fn coalesced(&self) -> bool {
+        self.num_segments > 1
+    }
+}
+
+/// Receive buffer size, in bytes, that holds a few full batches of full-size packets
+///
+/// Packets that arrive while the receive buffer is full are dropped, so the buffer should cover
+/// the packets that can arrive between two calls to [`recv_mmsg`].
+pub const fn recommended_recv_buffer_for_batch() -> usize {
+    const NUM_BATCHES: usize = 4;
+    NUM_BATCHES * PACKETS_PER_BATCH * PACKET_DATA_SIZE
+}
+
+/// Set the receive buffer size (SO_RCVBUF) of `sock` to `bytes`, returning the size granted
+///
+/// The kernel may grant less than requested, e.g. Linux caps it at `net.core.rmem_max`.  Linux
+/// also doubles the requested size, to leave room for bookkeeping, and reports the doubled size;
+/// the granted size is halved there, so it can be compared to `bytes`.
+pub fn set_recv_buffer_size(sock: &UdpSocket, bytes: usize) -> io::Result<usize> {
+    let sock = socket2::SockRef::from(sock);
+    sock.set_recv_buffer_size(bytes)?;
+    let granted = sock.recv_buffer_size()?;
+    #[cfg(target_os = "linux")]
+    let granted = granted / 2;
+    Ok(granted)
+}
+
+/// Enable UDP Generic Receive Offload (GRO) on `sock`
+///
+/// The kernel may then coalesce consecutive datagrams from the same source into a single
+/// packet.  Use [`recv_mmsg_gro_info`] to detect when this happens.
+#[cfg(target_os = "linux")]
+pub fn set_udp_gro(sock: &UdpSocket) -> io::Result<()> {
+    set_sockopt_int(sock, libc::SOL_UDP, libc::UDP_GRO, 1)
+}
+
+/// Enable SO_RXQ_OVFL on `sock`
+///
+/// The kernel then reports, with received packets, how many packets it has dropped because the
+/// socket's receive buffer was full.  Use [`recv_mmsg_rxq_ovfl`] to read the count.
+#[cfg(target_os = "linux")]
+pub fn set_rxq_ovfl(sock: &UdpSocket) -> io::Result<()> {
+    set_sockopt_int(sock, libc::SOL_SOCKET, libc::SO_RXQ_OVFL, 1)
+}
+
+/// Enable SO_TIMESTAMPING receive timestamps on `sock`
+///
+/// Both hardware and software timestamps are requested, so packets are timestamped in software
+/// when the NIC does not support hardware timestamps.  Hardware timestamping may additionally
+/// need to be enabled on the NIC itself, such as with `hwstamp_ctl`.  Use
+/// [`recv_mmsg_with_hw_timestamps`] to read the timestamps.
+#[cfg(target_os = "linux")]
+pub fn set_rx_timestamping(sock: &UdpSocket) -> io::Result<()> {
+    let flags = libc::SOF_TIMESTAMPING_RX_HARDWARE
+        | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+        | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_SOFTWARE;
+    set_sockopt_int(
+        sock,
+        libc::SOL_SOCKET,
+        libc::SO_TIMESTAMPING,
+        flags as libc::c_int,
+    )
+}
+
+/// Enable packet info control messages on `sock`
+///
+/// The kernel then reports, with each received packet, the interface it arrived on, via
+/// IP_PKTINFO for IPv4 sockets and IPV6_PKTINFO for IPv6 sockets.  Use [`recv_mmsg_with_ifindex`]
+/// to read the interface indexes.
+#[cfg(target_os = "linux")]
+pub fn set_pktinfo(sock: &UdpSocket) -> io::Result<()> {
+    if sock.local_addr()?.is_ipv4() {
+        set_sockopt_int(sock, libc::IPPROTO_IP, libc::IP_PKTINFO, 1)
+    } else {
+        set_sockopt_int(sock, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, 1)
+    }
+}
+
+/// When a packet was received, per SO_TIMESTAMPING
+#[cfg(target_os = "linux")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RecvTimestamp {
+    /// Timestamped by the NIC, relative to its hardware clock
+    Hardware(Duration),
+    /// Timestamped by the kernel, since the Unix epoch
+    Software(Duration),
+}
+
+#[cfg(target_os = "linux")]
+fn set_sockopt_int(
+    sock: &UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of_val(&value) as socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receive multiple messages from `sock` into `packets`, recording whether GRO coalesced them
+///
+/// Behaves like [`recv_mmsg`], and additionally fills `gro_info` with the UDP_GRO segment size
+/// reported for each received packet.  Coalesced packets are not split.  If a coalesced packet
+/// was truncated, only the received datagrams are counted.
+///
+/// This is a diagnostic, as coalescing skews per-packet metrics.  GRO must be enabled with
+/// [`set_udp_gro`] for the kernel to coalesce packets.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_gro_info(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    gro_info: &mut [GroInfo],
+) -> io::Result</*num packets:*/ usize> {
+    let count = cmp::min(packets.len(), gro_info.len());
+    recv_mmsg_with_control(sock, &mut packets[..count], |index, size, msg_hdr| {
+        gro_info[index] = match udp_gro_segment_size(msg_hdr) {
+            Some(segment_size) if segment_size > 0 => GroInfo {
+                segment_size,
+                num_segments: size.div_ceil(usize::from(segment_size)),
+            },
+            _ => GroInfo {
+                segment_size: 0,
+                num_segments: 1,
+            },
+        };
+    })
+}
+
+/// Receive multiple messages from `sock` into `packets`, along with when each was received
+///
+/// Behaves like [`recv_mmsg`], and additionally fills `recv_timestamps` with each received
+/// packet's hardware timestamp, falling back to its software timestamp if the NIC did not
+/// timestamp it, or None if neither is reported.  `Meta` has no room for the timestamp, so at most
+/// as many packets as `recv_timestamps` holds are received.  Timestamping must be enabled with
+/// [`set_rx_timestamping`].
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_with_hw_timestamps(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    recv_timestamps: &mut [Option<RecvTimestamp>],
+) -> io::Result</*num packets:*/ usize> {
+    let count = cmp::min(packets.len(), recv_timestamps.len());
+    recv_mmsg_with_control(sock, &mut packets[..count], |index, _size, msg_hdr| {
+        // An SCM_TIMESTAMPING control message holds the software timestamp, a deprecated
+        // timestamp, and the raw hardware timestamp; unreported timestamps are zero
+        let timestamps = control_message::<[libc::timespec; 3]>(
+            msg_hdr,
+            libc::SOL_SOCKET,
+            libc::SCM_TIMESTAMPING,
+        );
+        let to_duration = |timespec: &libc::timespec| {
+            let duration = Duration::new(
+                u64::try_from(timespec.tv_sec).ok()?,
+                u32::try_from(timespec.tv_nsec).ok()?,
+            );
+            (!duration.is_zero()).then_some(duration)
+        };
+        recv_timestamps[index] = timestamps.and_then(|[software, _, hardware]| {
+            to_duration(&hardware)
+                .map(RecvTimestamp::Hardware)
+                .or_else(|| to_duration(&software).map(RecvTimestamp::Software))
+        });
+    })
+}
+
+/// Receive multiple messages from `sock` into `packets`, along with the interface each arrived on
+///
+/// Behaves like [`recv_mmsg`], and additionally fills `if_indexes` with the index of the network
+/// interface each received packet was delivered by, or None if it is not reported.  `Meta` has no
+/// room for the index, so at most as many packets as `if_indexes` holds are received.  Packet info
+/// must be enabled with [`set_pktinfo`].
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_with_ifindex(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    if_indexes: &mut [Option<u32>],
+) -> io::Result</*num packets:*/ usize> {
+    let count = cmp::min(packets.len(), if_indexes.len());
+    recv_mmsg_with_control(sock, &mut packets[..count], |index, _size, msg_hdr| {
+        if_indexes[index] =
+            control_message::<libc::in_pktinfo>(msg_hdr, libc::IPPROTO_IP, libc::IP_PKTINFO)
+                .and_then(|pktinfo| u32::try_from(pktinfo.ipi_ifindex).ok())
+                .or_else(|| {
+                    control_message::<libc::in6_pktinfo>(
+                        msg_hdr,
+                        libc::IPPROTO_IPV6,
+                        libc::IPV6_PKTINFO,
+                    )
+                    .map(|pktinfo| pktinfo.ipi6_ifindex)
+                });
+    })
+}
+
+/// Receive multiple messages from `sock` into `packets`, along with how many packets the kernel
+/// dropped since the previous call
+///
+/// `drop_count` holds the socket's cumulative drop count as of the previous call, and is updated.
+/// The kernel only reports the count with packets queued after a drop, so drops are reported
+/// once the socket catches up.  SO_RXQ_OVFL must be enabled with [`set_rxq_ovfl`].
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_rxq_ovfl(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    drop_count: &mut u32,
+) -> io::Result<(
+    /*num packets:*/ usize,
+    /*num dropped since the previous call:*/ u32,
+)> {
+    let mut latest_drop_count = None;
+    let nrecv = recv_mmsg_with_control(sock, packets, |_index, _size, msg_hdr| {
+        if let Some(drop_count) =
+            control_message::<u32>(msg_hdr, libc::SOL_SOCKET, libc::SO_RXQ_OVFL)
+        {
+            latest_drop_count = Some(drop_count);
+        }
+    })?;
+    let num_dropped = latest_drop_count.map_or(0, |latest_drop_count| {
+        // The kernel's counter wraps
+        let num_dropped = latest_drop_count.wrapping_sub(*drop_count);
+        *drop_count = latest_drop_count;
+        num_dropped
+    });
+    Ok((nrecv, num_dropped))
+}
+
+/// The length, in u64s, of the control buffer each message is received with by
+/// [`recv_mmsg_with_control`]
+///
+/// u64s to ensure the control messages are properly aligned; large enough for a UDP_GRO, an
+/// SO_RXQ_OVFL, an SCM_TIMESTAMPING, and an IP_PKTINFO or (larger) IPV6_PKTINFO control message.
+#[cfg(target_os = "linux")]
+const CONTROL_LEN: usize = (cmsg_space(mem::size_of::<libc::c_int>())
+    + cmsg_space(mem::size_of::<u32>())
+    + cmsg_space(mem::size_of::<[libc::timespec; 3]>())
+    + cmsg_space(mem::size_of::<libc::in6_pktinfo>()))
+.div_ceil(mem::size_of::<u64>());
+
+/// Receive multiple messages from `sock` into `packets`, along with their control messages
+///
+/// Behaves like [`recv_mmsg`], and additionally calls `on_control(index, size, msg_hdr)` for each
+/// received packet, so its control messages can be read from `msg_hdr`.
+#[cfg(target_os = "linux")]
+fn recv_mmsg_with_control(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    mut on_control: impl FnMut(usize, usize, &libc::msghdr),
+) -> io::Result</*num packets:*/ usize> {
+    let mut iovs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let mut addrs = [MaybeUninit::zeroed(); PACKETS_PER_BATCH];
+    let mut hdrs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let mut controls = [[0u64; CONTROL_LEN]; PACKETS_PER_BATCH];
+
+    recv_mmsg_with_scratch(
+        sock,
+        packets,
+        RecvMmsgScratch::new(&mut iovs, &mut hdrs, Some(&mut addrs), Some(&mut controls)),
+        MSG_WAITFORONE,
+        |index, _packet, message| on_control(index, message.size, message.msg_hdr),
+    )
+}
+
+/// Returns the space a control message with `len` bytes of data takes in a control buffer
+///
+/// The same as `libc::CMSG_SPACE`, which is not usable in constants.
+#[cfg(target_os = "linux")]
+const fn cmsg_space(len: usize) -> usize {
+    // control messages are aligned to size_t
+    const fn align(len: usize) -> usize {
+        len.next_multiple_of(mem::size_of::<usize>())
+    }
+    align(mem::size_of::<libc::cmsghdr>()) + align(len)
+}
+
+/// Returns the segment size from the UDP_GRO control message of `msg_hdr`, if any
+#[cfg(target_os = "linux")]
+fn udp_gro_segment_size(msg_hdr: &libc::msghdr) -> Option<u16> {
+    // a UDP_GRO control message holds the segment size as an int
+    let segment_size = control_message::<libc::c_int>(msg_hdr, libc::SOL_UDP, libc::UDP_GRO)?;
+    u16::try_from(segment_size).ok()
+}
+
+/// Returns the data of the first control message of `msg_hdr` with `level` and `type`, if any
+///
+/// The caller must ensure such a control message holds a `T`.
+#[cfg(target_os = "linux")]
+fn control_message<T: Copy>(
+    msg_hdr: &libc::msghdr,
+    level: libc::c_int,
+    r#type: libc::c_int,
+) -> Option<T> {
+    // SAFETY: recvmmsg() populated the control messages, and updated msg_controllen, and the
+    // CMSG_* functions do not step past msg_controllen
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg_hdr) };
+    while let Some(cmsg_ref) = unsafe { cmsg.as_ref() } {
+        if cmsg_ref.cmsg_level == level && cmsg_ref.cmsg_type == r#type {
+            // SAFETY: the caller ensures this control message holds a `T`
+            return Some(unsafe { (libc::CMSG_DATA(cmsg) as *const T).read_unaligned() });
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(msg_hdr, cmsg) };
+    }
+    None
+}
+
+/// Receive multiple messages from the connected `sock` into `packets`
+///
+/// A connected socket only receives from its peer, so source addresses are not requested from the
+/// kernel or parsed; every packet's address is set to the peer's.  Fails if `sock` is not
+/// connected.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_connected(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result</*num packets:*/ usize> {
+    let peer_addr = sock.peer_addr()?;
+    let mut iovs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let mut hdrs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+
+    recv_mmsg_with_scratch(
+        sock,
+        packets,
+        RecvMmsgScratch::new(&mut iovs, &mut hdrs, None, None),
+        MSG_WAITFORONE,
+        |_index, packet, _message| packet.meta_mut().set_socket_addr(&peer_addr),
+    )
+}
+
+/// Receive multiple messages from the connected `sock` into `packets`
+///
+/// A connected socket only receives from its peer, so every packet's address is set to the
+/// peer's.  Fails if `sock` is not connected.
+#[cfg(not(target_os = "linux"))]
+pub fn recv_mmsg_connected(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result</*num packets:*/ usize> {
+    let peer_addr = sock.peer_addr()?;
+    match try_recv_mmsg_up_to_with(packets, PACKETS_PER_BATCH, |i, buffer| {
+        let size = if i == 0 {
+            // Only block until the first packet arrives
+            sock.recv(buffer)?
+        } else {
+            recv_nonblocking(sock, buffer)?
+        };
+        Ok((size, peer_addr))
+    }) {
+        RecvMmsgResult {
+            packets_received: 0,
+            error: Some(err),
+        } => Err(err),
+        RecvMmsgResult {
+            packets_received, ..
+        } => Ok(packets_received),
+    }
+}
+
+/// Peek at the next message queued on `sock`, receiving it into `packets` without consuming it
+///
+/// The message stays queued, so the next receive, peeking or not, gets it again.  The packet's
+/// size and address are populated just as by [`recv_mmsg`].
+///
+/// Peeking never advances past the first queued message, so at most one packet is received.
+#[cfg(not(target_os = "linux"))]
+pub fn recv_mmsg_peek_socket(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+) -> io::Result</*num packets:*/ usize> {
+    let Some(packet) = packets.first_mut() else {
+        return Ok(0);
+    };
+    let (size, from) = sock.peek_from(packet.buffer_mut())?;
+    packet.meta_mut().size = size;
+    packet.meta_mut().set_socket_addr(&from);
+    Ok(1)
+}
+
+/// Receive multiple messages from `sock` into `scratch`, returning slices of the received data
+///
+/// Each message is received into its own `PACKET_DATA_SIZE` chunk of `scratch`, so no [`Packet`]s
+/// are needed.  The returned slices borrow `scratch`, so they must be dropped before `scratch` is
+/// reused for the next call.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_borrowed<'a>(
+    sock: &UdpSocket,
+    scratch: &'a mut [u8; PACKET_DATA_SIZE * PACKETS_PER_BATCH],
+) -> io::Result<Vec<(&'a [u8], SocketAddr)>> {
+    let mut iovs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let mut addrs = [MaybeUninit::zeroed(); PACKETS_PER_BATCH];
+    let mut hdrs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+
+    let mut recv_scratch = RecvMmsgScratch::new(&mut iovs, &mut hdrs, Some(&mut addrs), None);
+    recv_scratch.recv(
+        sock,
+        scratch
+            .chunks_exact_mut(PACKET_DATA_SIZE)
+            .map(as_uninit_buffer),
+        MSG_WAITFORONE,
+    )?;
+
+    let scratch: &'a [u8; PACKET_DATA_SIZE * PACKETS_PER_BATCH] = scratch;
+    let received = recv_scratch
+        .received()
+        .zip(scratch.chunks_exact(PACKET_DATA_SIZE))
+        .filter_map(|(message, buffer)| {
+            let size = cmp::min(message.size, PACKET_DATA_SIZE);
+            Some((&buffer[..size], message.addr?))
+        })
+        .collect();
+    Ok(received)
+}
+
+/// Receive multiple messages from `sock` into `scratch`, returning slices of the received data
+///
+/// Each message is received into its own `PACKET_DATA_SIZE` chunk of `scratch`, so no [`Packet`]s
+/// are needed.  The returned slices borrow `scratch`, so they must be dropped before `scratch` is
+/// reused for the next call.
+#[cfg(not(target_os = "linux"))]
+pub fn recv_mmsg_borrowed<'a>(
+    sock: &UdpSocket,
+    scratch: &'a mut [u8; PACKET_DATA_SIZE * PACKETS_PER_BATCH],
+) -> io::Result<Vec<(&'a [u8], SocketAddr)>> {
+    let mut received = Vec::new();
+    for buffer in scratch.chunks_exact_mut(PACKET_DATA_SIZE) {
+        let result = if received.is_empty() {
+            // Only block until the first packet arrives
+            sock.recv_from(buffer)
+        } else {
+            recv_from_nonblocking(sock, buffer)
+        };
+        match result {
+            Err(err) if received.is_empty() => return Err(err),
+            // Running out of packets to read, or any other error, ends the batch
+            Err(_) => break,
+            Ok((size, from)) => {
+                let buffer: &'a [u8] = buffer;
+                received.push((&buffer[..size], from));
+            }
+        }
+    }
+    Ok(received)
+}
+
+/// Returns the shard, out of `num_shards`, for packets from `addr`
+///
+/// The shard only depends on the source IP and port, so all packets from the same source map to
+/// the same shard.  `num_shards` must not be zero.
+pub fn shard_for_addr(addr: &SocketAddr, num_shards: u8) -> u8 {
+    debug_assert_ne!(num_shards, 0);
+    let mut hasher = DefaultHasher::new();
+    addr.ip().hash(&mut hasher);
+    addr.port().hash(&mut hasher);
+    (hasher.finish() % u64::from(num_shards)) as u8
+}
+
+/// Receive multiple messages from `sock` into `packets`, assigning each a shard by its source
+///
+/// Behaves like [`recv_mmsg`], and also returns the shard of each received packet, per
+/// [`shard_for_addr`], so packets can be spread across workers by source.  Fails with
+/// [`io::ErrorKind::InvalidInput`] if `num_shards` is zero.
+pub fn recv_mmsg_sharded(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    num_shards: u8,
+) -> io::Result<(
+    /*num packets:*/ usize,
+    /*shard per packet:*/ Vec<u8>,
+)> {
+    if num_shards == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the number of shards must not be zero",
+        ));
+    }
+    let nrecv = recv_mmsg(sock, packets)?;
+    let shards = packets
+        .iter()
+        .take(nrecv)
+        .map(|packet| shard_for_addr(&packet.meta().socket_addr(), num_shards))
+        .collect();
+    Ok((nrecv, shards))
+}
+
+/// Receive at most `max` messages from `sock` into `packets`
+///
+/// Only `min(max, packets.len())` packets are offered to the receive, so any further messages stay
+/// queued in the kernel for the next call.  This enforces a per-call limit, e.g. for rate-limited
+/// ingest, independent of how much the socket's receive buffer holds.
+pub fn recv_mmsg_capped(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    max: usize,
+) -> io::Result</*num packets:*/ usize> {
+    let count = cmp::min(max, packets.len());
+    recv_mmsg(sock, &mut packets[..count])
+}
+
+/// Receive a single message from `sock`, e.g. on a control-plane socket
+///
+/// A convenience over [`recv_mmsg`] that needs no batch to be allocated.  The packet's size and
+/// source address are populated.  Returns None if the receive times out, per the socket's read
+/// timeout, or would block.
+pub fn recv_one(sock: &UdpSocket) -> io::Result<Option<Packet>> {
+    let mut packets = [Packet::default()];
+    match recv_mmsg(sock, &mut packets) {
+        Ok(0) => Ok(None),
+        Ok(_) => {
+            let [packet] = packets;
+            Ok(Some(packet))
+        }
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Receive multiple messages from `sock` into `packets`, assigning each a receive sequence number
+///
+/// Sequence numbers are taken from `seq`, which the caller shares between every receiver whose
+/// packets should be ordered together.  `Meta` has no room for the sequence number, so the numbers
+/// of the received packets are returned instead: `packets[i]` has sequence number `range.start +
+/// i`, and `range.len()` packets were received.
+pub fn recv_mmsg_sequenced(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    seq: &AtomicU64,
+) -> io::Result</*recv seqs:*/ Range<u64>> {
+    let nrecv = recv_mmsg(sock, packets)? as u64;
+    let start = seq.fetch_add(nrecv, Ordering::Relaxed);
+    Ok(start..start + nrecv)
+}
+
+/// Receive multiple messages from `sock`, appending them to `batch`
+///
+/// The batch is grown to at most [`PACKETS_PER_BATCH`] packets, received into directly, and then
+/// truncated to the packets actually received.  Only pinned batches hold [`Packet`]s that can be
+/// received into; other batches are rejected with [`io::ErrorKind::Unsupported`].
+pub fn recv_mmsg_batch(
+    sock: &UdpSocket,
+    batch: &mut PacketBatch,
+) -> io::Result</*num packets:*/ usize> {
+    let PacketBatch::Pinned(batch) = batch else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only pinned packet batches can be received into",
+        ));
+    };
+    let len = batch.len();
+    batch.resize(cmp::max(len, PACKETS_PER_BATCH), Packet::default());
+    let result = recv_mmsg(sock, &mut batch[len..]);
+    let nrecv = *result.as_ref().unwrap_or(&0);
+    batch.truncate(len + nrecv);
+    result
+}
+
+/// Receive multiple messages from `sock` into `packets`, which do not need to be initialized
+///
+/// Behaves like [`recv_mmsg`], but skips zero-initializing the packets beforehand.  Returns the
+/// number of packets received, `nrecv`; only the first `nrecv` elements of `packets` are
+/// initialized, and the rest must not be assumed initialized.
+#[cfg(not(target_os = "linux"))]
+pub fn recv_mmsg_uninit(
+    sock: &UdpSocket,
+    packets: &mut [MaybeUninit<Packet>],
+) -> io::Result</*num packets:*/ usize> {
+    let count = cmp::min(PACKETS_PER_BATCH, packets.len());
+    let packets = &mut packets[..count];
+    for packet in packets.iter_mut() {
+        packet.write(Packet::default());
+    }
+    // SAFETY: All `count` packets were initialized above
+    let packets = unsafe { &mut *(packets as *mut [MaybeUninit<Packet>] as *mut [Packet]) };
+    recv_mmsg(sock, packets)
+}
+
+/// Receive multiple messages from `sock` into `packets`, which do not need to be initialized
+///
+/// Behaves like [`recv_mmsg`], but skips zero-initializing the packets beforehand.  Returns the
+/// number of packets received, `nrecv`; only the first `nrecv` elements of `packets` are
+/// initialized, and the rest must not be assumed initialized.
+#[cfg(target_os = "linux")]
+pub fn recv_mmsg_uninit(
+    sock: &UdpSocket,
+    packets: &mut [MaybeUninit<Packet>],
+) -> io::Result</*num packets:*/ usize> {
+    let mut iovs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let mut addrs = [MaybeUninit::zeroed(); PACKETS_PER_BATCH];
+    let mut hdrs = [MaybeUninit::uninit(); PACKETS_PER_BATCH];
+    let (buffer_offset, meta_offset) = packet_field_offsets();
+
+    let mut recv_scratch = RecvMmsgScratch::new(&mut iovs, &mut hdrs, Some(&mut addrs), None);
+    let buffers = packets.iter_mut().map(|packet| {
+        // SAFETY: The buffer lies within the packet, and receiving into it only writes initialized
+        // bytes
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                packet
+                    .as_mut_ptr()
+                    .cast::<MaybeUninit<u8>>()
+                    .add(buffer_offset),
+                PACKET_DATA_SIZE,
+            )
+        }
+    });
+    let nrecv = recv_scratch.recv(sock, buffers, MSG_WAITFORONE)?;
+    for (message, packet) in recv_scratch.received().zip(packets.iter_mut()) {
+        let size = cmp::min(message.size, PACKET_DATA_SIZE);
+        let mut meta = Meta {
+            size,
+            ..Meta::default()
+        };
+        if let Some(addr) = message.addr {
+            meta.set_socket_addr(&addr);
+        }
+        let packet = packet.as_mut_ptr().cast::<u8>();
+        // SAFETY: recvmmsg() wrote the first `size` bytes of the buffer.  Zeroing the remainder
+        // and writing the meta initializes every field of the packet.
+        unsafe {
+            packet
+                .add(buffer_offset + size)
+                .write_bytes(0, PACKET_DATA_SIZE - size);
+            packet.add(meta_offset).cast::<Meta>().write(meta);
+        }
+    }
+
+    Ok(nrecv)
+}
+
+// A Packet must consist of only its buffer and its meta, so initializing both initializes it
+#[cfg(target_os = "linux")]
+const _: () = assert!(mem::size_of::<Packet>() == PACKET_DATA_SIZE + mem::size_of::<Meta>());
+
+/// Returns the byte offsets of the buffer and the meta within a [`Packet`]
+#[cfg(target_os = "linux")]
+fn packet_field_offsets() -> (/*buffer:*/ usize, /*meta:*/ usize) {
+    let mut packet = Packet::default();
+    let base = &packet as *const Packet as usize;
+    let meta_offset = packet.meta() as *const Meta as usize - base;
+    let buffer_offset = packet.buffer_mut().as_ptr() as usize - base;
+    (buffer_offset, meta_offset)
+}
+
+/// The shared implementation of the recvmmsg(7) wrappers that receive into [`Packet`]s
+///
+/// Each received packet's size, and address if requested, are set, then `on_recv(index, packet,
+/// message)` is called with it.
+#[cfg(target_os = "linux")]
+fn recv_mmsg_with_scratch(
+    sock: &UdpSocket,
+    packets: &mut [Packet],
+    mut scratch: RecvMmsgScratch<'_>,
+    flags: libc::c_int,
+    mut on_recv: impl FnMut(usize, &mut Packet, &RecvMmsgMessage<'_>),
+) -> io::Result</*num packets:*/ usize> {
+    // Assert that there are no leftovers in packets.
+    debug_assert!(packets.iter().all(|pkt| pkt.meta() == &Meta::default()));
+
+    let nrecv = scratch.recv(
+        sock,
+        packets
+            .iter_mut()
+            .map(|packet| as_uninit_buffer(packet.buffer_mut())),
+        flags,
+    )?;
+    for (index, (message, packet)) in scratch.received().zip(packets.iter_mut()).enumerate() {
+        packet.meta_mut().size = message.size;
+        if let Some(addr) = message.addr {
+            packet.meta_mut().set_socket_addr(&addr);
+        }
+        on_recv(index, packet, &message);
+    }
+
+    Ok(nrecv)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{packet::PinnedPacketBatch, recvmmsg::*},
+        assert_matches::assert_matches,
+        solana_net_utils::sockets::{
+            bind_in_range_with_config, localhost_port_range_for_tests,
+            SocketConfiguration as SocketConfig,
+        },
+        std::{
+            net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+            time::{Duration, Instant},
+        },
+    };
+
+    type TestConfig = (UdpSocket, SocketAddr, UdpSocket, SocketAddr);
+
+    fn test_setup_reader_sender(ip: IpAddr) -> io::Result<TestConfig> {
+        let port_range = localhost_port_range_for_tests();
+        let reader = bind_in_range_with_config(ip, port_range, SocketConfig::default())?.1;
+        let reader_addr = reader.local_addr()?;
+        let sender = bind_in_range_with_config(ip, port_range, SocketConfig::default())?.1;
         let sender_addr = sender.local_addr()?;
         Ok((reader, reader_addr, sender, sender_addr))
     }
@@ -297,37 +1979,25 @@ mod tests {
 
     #[test]
     pub fn test_recv_mmsg_multi_addrs() {
-        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
-        let port_range = localhost_port_range_for_tests();
-        let reader = bind_in_range_with_config(ip, port_range, SocketConfig::default())
-            .unwrap()
-            .1;
-        let reader_addr = reader.local_addr().unwrap();
-        let sender1 = bind_in_range_with_config(ip, port_range, SocketConfig::default())
-            .unwrap()
-            .1;
-        let sender1_addr = sender1.local_addr().unwrap();
+        let reader = InMemorySocket::default();
+        let sender1_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 8001));
         let sent1 = TEST_NUM_MSGS - 1;
-
-        let sender2 = bind_in_range_with_config(ip, port_range, SocketConfig::default())
-            .unwrap()
-            .1;
-        let sender_addr = sender2.local_addr().unwrap();
+        let sender_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 8002));
         let sent2 = TEST_NUM_MSGS + 1;
 
         for _ in 0..sent1 {
             let data = [0; PACKET_DATA_SIZE];
-            sender1.send_to(&data[..], reader_addr).unwrap();
+            reader.push(sender1_addr, &data[..]);
         }
 
         for _ in 0..sent2 {
             let data = [0; PACKET_DATA_SIZE];
-            sender2.send_to(&data[..], reader_addr).unwrap();
+            reader.push(sender_addr, &data[..]);
         }
 
         let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
 
-        let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+        let recv = reader.recv_mmsg(&mut packets[..]).unwrap();
         assert_eq!(TEST_NUM_MSGS, recv);
         for packet in packets.iter().take(sent1) {
             assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
@@ -341,11 +2011,1096 @@ mod tests {
         packets
             .iter_mut()
             .for_each(|pkt| *pkt.meta_mut() = Meta::default());
-        let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+        let recv = reader.recv_mmsg(&mut packets[..]).unwrap();
         assert_eq!(sent1 + sent2 - TEST_NUM_MSGS, recv);
         for packet in packets.iter().take(recv) {
             assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
             assert_eq!(packet.meta().socket_addr(), sender_addr);
         }
+        assert!(reader.is_empty());
+        assert_eq!(
+            reader.recv_mmsg(&mut packets[..]).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock,
+        );
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_buffered() {
+        const TEST_BATCH_SIZE: usize = 64;
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let send_batch = || {
+            for i in 0..TEST_BATCH_SIZE {
+                let data = [i as u8; PACKET_DATA_SIZE];
+                sender.send_to(&data[..=i], addr).unwrap();
+            }
+        };
+
+        // Receive a batch through the default path to compare against
+        send_batch();
+        let mut expected_packets = vec![Packet::default(); TEST_BATCH_SIZE];
+        let recv = recv_mmsg(&reader, &mut expected_packets[..]).unwrap();
+        assert_eq!(TEST_BATCH_SIZE, recv);
+
+        // Reuse the same buffers for multiple batches
+        let mut buffers = RecvMmsgBuffers::new(TEST_BATCH_SIZE);
+        assert_eq!(buffers.batch_size(), TEST_BATCH_SIZE);
+        for _ in 0..2 {
+            send_batch();
+            let mut packets = vec![Packet::default(); TEST_BATCH_SIZE];
+            let recv = recv_mmsg_buffered(&reader, &mut packets[..], &mut buffers).unwrap();
+            assert_eq!(TEST_BATCH_SIZE, recv);
+            for (i, (packet, expected_packet)) in packets.iter().zip(&expected_packets).enumerate()
+            {
+                assert_eq!(packet.meta().size, i + 1);
+                assert_eq!(packet.meta().socket_addr(), saddr);
+                assert_eq!(packet.meta(), expected_packet.meta());
+                assert_eq!(packet.data(..), expected_packet.data(..));
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_filtered() {
+        let (reader, addr, allowed_sender, allowed_addr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        // all of 127.0.0.0/8 is loopback on linux, so this is a second, distinct source
+        let disallowed_sender = bind_in_range_with_config(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            localhost_port_range_for_tests(),
+            SocketConfig::default(),
+        )
+        .unwrap()
+        .1;
+        let allow = HashSet::from([allowed_addr.ip()]);
+
+        let data = [0; PACKET_DATA_SIZE];
+        for i in 0..TEST_NUM_MSGS {
+            let sender = if i % 2 == 0 {
+                &allowed_sender
+            } else {
+                &disallowed_sender
+            };
+            sender.send_to(&data[..], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let mut discard_reasons = [None; TEST_NUM_MSGS];
+        let (recv, kept) =
+            recv_mmsg_filtered(&reader, &mut packets[..], &allow, &mut discard_reasons).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        assert_eq!(kept, TEST_NUM_MSGS / 2);
+        for (packet, discard_reason) in packets.iter().zip(discard_reasons).take(recv) {
+            if packet.meta().socket_addr() == allowed_addr {
+                assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
+                assert!(!packet.meta().discard());
+                assert_eq!(discard_reason, None);
+            } else {
+                assert_eq!(packet.meta().addr, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)));
+                assert_eq!(packet.meta().size, 0);
+                assert!(packet.meta().discard());
+                assert_eq!(discard_reason, Some(DiscardReason::SourceNotAllowed));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_min_size() {
+        const MIN_SIZE: usize = 64;
+        let (reader, addr, sender, _) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+
+        let data = [0; PACKET_DATA_SIZE];
+        for i in 0..TEST_NUM_MSGS {
+            let size = if i % 2 == 0 { PACKET_DATA_SIZE } else { 1 };
+            sender.send_to(&data[..size], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let mut discard_reasons = [None; TEST_NUM_MSGS];
+        let (recv, kept) =
+            recv_mmsg_min_size(&reader, &mut packets[..], MIN_SIZE, &mut discard_reasons).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        assert_eq!(kept, TEST_NUM_MSGS / 2);
+        for (i, (packet, discard_reason)) in
+            packets.iter().zip(discard_reasons).take(recv).enumerate()
+        {
+            if i % 2 == 0 {
+                assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
+                assert!(!packet.meta().discard());
+                assert_eq!(discard_reason, None);
+            } else {
+                assert_eq!(packet.meta().size, 0);
+                assert!(packet.meta().discard());
+                assert_eq!(discard_reason, Some(DiscardReason::TooSmall));
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_dedup() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        sender.send_to(&[1; 10], addr).unwrap();
+        sender.send_to(&[2; 10], addr).unwrap();
+        sender.send_to(&[1; 10], addr).unwrap();
+
+        let mut seen = HashSet::new();
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let mut discard_reasons = [None; TEST_NUM_MSGS];
+        let (recv, kept) =
+            recv_mmsg_dedup(&reader, &mut packets[..], &mut seen, &mut discard_reasons).unwrap();
+        assert_eq!(recv, 3);
+        assert_eq!(kept, 2);
+        assert_eq!(seen.len(), 2);
+        assert_eq!(
+            discard_reasons[..recv],
+            [None, None, Some(DiscardReason::Duplicate)]
+        );
+        assert!(!packets[0].meta().discard());
+        assert!(!packets[1].meta().discard());
+        assert!(packets[2].meta().discard());
+        assert_eq!(packets[2].meta().socket_addr(), saddr);
+        assert_eq!(packets[2].meta().size, 0);
+
+        // Payloads seen in earlier calls are duplicates too, until the caller clears `seen`
+        sender.send_to(&[2; 10], addr).unwrap();
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let (recv, kept) =
+            recv_mmsg_dedup(&reader, &mut packets[..], &mut seen, &mut discard_reasons).unwrap();
+        assert_eq!((recv, kept), (1, 0));
+        assert_eq!(discard_reasons[0], Some(DiscardReason::Duplicate));
+        assert!(packets[0].meta().discard());
+
+        seen.clear();
+        sender.send_to(&[2; 10], addr).unwrap();
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let (recv, kept) =
+            recv_mmsg_dedup(&reader, &mut packets[..], &mut seen, &mut discard_reasons).unwrap();
+        assert_eq!((recv, kept), (1, 1));
+        assert_eq!(discard_reasons[0], None);
+        assert!(!packets[0].meta().discard());
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_grouped() {
+        let (reader, addr, sender1, sender1_addr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let sender2 = bind_in_range_with_config(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            localhost_port_range_for_tests(),
+            SocketConfig::default(),
+        )
+        .unwrap()
+        .1;
+        let sender2_addr = sender2.local_addr().unwrap();
+
+        // Interleave the senders, with runs of different lengths
+        let senders = [&sender1, &sender1, &sender2, &sender1, &sender2, &sender2];
+        for (i, sender) in senders.iter().enumerate() {
+            sender.send_to(&[i as u8; 10], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let groups = recv_mmsg_grouped(&reader, &mut packets[..]).unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                (sender1_addr, 0..2),
+                (sender2_addr, 2..3),
+                (sender1_addr, 3..4),
+                (sender2_addr, 4..6),
+            ],
+        );
+        for (i, packet) in packets.iter().enumerate().take(senders.len()) {
+            assert_eq!(packet.data(..), Some(&[i as u8; 10][..]));
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_with_counts() {
+        let (reader, addr, sender1, sender1_addr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        // all of 127.0.0.0/8 is loopback on linux, so this is a second, distinct source
+        let sender2_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        let sender2 = bind_in_range_with_config(
+            sender2_ip,
+            localhost_port_range_for_tests(),
+            SocketConfig::default(),
+        )
+        .unwrap()
+        .1;
+
+        const SENDER1_NUM_MSGS: usize = 10;
+        const SENDER2_NUM_MSGS: usize = 6;
+        let data = [0; PACKET_DATA_SIZE];
+        for _ in 0..SENDER1_NUM_MSGS {
+            sender1.send_to(&data[..], addr).unwrap();
+        }
+        for _ in 0..SENDER2_NUM_MSGS {
+            sender2.send_to(&data[..], addr).unwrap();
+        }
+
+        let mut counts = HashMap::new();
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg_with_counts(&reader, &mut packets[..], &mut counts).unwrap();
+        assert_eq!(recv, SENDER1_NUM_MSGS + SENDER2_NUM_MSGS);
+        assert_eq!(
+            counts,
+            HashMap::from([
+                (sender1_addr.ip(), SENDER1_NUM_MSGS as u32),
+                (sender2_ip, SENDER2_NUM_MSGS as u32),
+            ]),
+        );
+
+        // counts accumulate across calls
+        sender2.send_to(&data[..], addr).unwrap();
+        packets
+            .iter_mut()
+            .for_each(|pkt| *pkt.meta_mut() = Meta::default());
+        let recv = recv_mmsg_with_counts(&reader, &mut packets[..], &mut counts).unwrap();
+        assert_eq!(recv, 1);
+        assert_eq!(counts[&sender2_ip], SENDER2_NUM_MSGS as u32 + 1);
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_instrumented() {
+        let (reader, addr, sender, _) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let mut histogram = [0; PACKETS_PER_BATCH + 1];
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+
+        let bursts = [1, 1, 5, TEST_NUM_MSGS];
+        for burst in bursts {
+            for _ in 0..burst {
+                let data = [0; PACKET_DATA_SIZE];
+                sender.send_to(&data[..], addr).unwrap();
+            }
+            packets
+                .iter_mut()
+                .for_each(|pkt| *pkt.meta_mut() = Meta::default());
+            let recv = recv_mmsg_instrumented(&reader, &mut packets[..], &mut histogram).unwrap();
+            assert_eq!(recv, burst);
+        }
+
+        assert_eq!(histogram.iter().sum::<u64>(), bursts.len() as u64);
+        assert_eq!(histogram[1], 2);
+        assert_eq!(histogram[5], 1);
+        assert_eq!(histogram[TEST_NUM_MSGS], 1);
+    }
+
+    /// Send `data` as datagrams of `segment_size` bytes each, in a single UDP_SEGMENT send
+    #[cfg(target_os = "linux")]
+    fn send_segmented(sock: &UdpSocket, data: &[u8], segment_size: u16) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        // u64s to ensure the control message is properly aligned
+        let mut control = [0u64; 4];
+        let mut msg_hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg_hdr.msg_iov = &mut iov;
+        msg_hdr.msg_iovlen = 1;
+        msg_hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg_hdr.msg_controllen =
+            unsafe { libc::CMSG_SPACE(std::mem::size_of::<u16>() as u32) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg_hdr);
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<u16>() as u32) as _;
+            (libc::CMSG_DATA(cmsg) as *mut u16).write_unaligned(segment_size);
+        }
+        if unsafe { libc::sendmsg(sock.as_raw_fd(), &msg_hdr, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_with_hw_timestamps() {
+        let (reader, addr, sender, _) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        set_rx_timestamping(&reader).unwrap();
+
+        let data = [0; PACKET_DATA_SIZE];
+        for _ in 0..TEST_NUM_MSGS {
+            sender.send_to(&data[..], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let mut recv_timestamps = [None; TEST_NUM_MSGS];
+        let recv =
+            recv_mmsg_with_hw_timestamps(&reader, &mut packets[..], &mut recv_timestamps).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        // Loopback has no NIC to timestamp in hardware, so the software timestamps are used
+        for recv_timestamp in recv_timestamps {
+            assert_matches!(recv_timestamp, Some(RecvTimestamp::Software(duration)) if !duration.is_zero());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_with_ifindex() {
+        // SAFETY: the interface name is a valid C string
+        let loopback_index = unsafe { libc::if_nametoindex(c"lo".as_ptr()) };
+        assert_ne!(loopback_index, 0);
+
+        for ip in [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ] {
+            let Ok((reader, addr, sender, saddr)) = test_setup_reader_sender(ip) else {
+                // IPv6 may be disabled
+                continue;
+            };
+            set_pktinfo(&reader).unwrap();
+            for _ in 0..TEST_NUM_MSGS {
+                sender.send_to(&[0; 10], addr).unwrap();
+            }
+
+            let mut packets = vec![Packet::default(); TEST_NUM_MSGS + 1];
+            let mut if_indexes = [None; TEST_NUM_MSGS + 1];
+            let recv = recv_mmsg_with_ifindex(&reader, &mut packets[..], &mut if_indexes).unwrap();
+            assert_eq!(recv, TEST_NUM_MSGS);
+            for (packet, if_index) in packets.iter().zip(if_indexes).take(recv) {
+                assert_eq!(packet.meta().socket_addr(), saddr);
+                assert_eq!(if_index, Some(loopback_index));
+            }
+            assert_eq!(if_indexes[recv], None);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_rxq_ovfl() {
+        const NUM_FLOOD_MSGS: usize = 1_000;
+        let (reader, addr, sender, _) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        set_rxq_ovfl(&reader).unwrap();
+        // The kernel rounds this up to its minimum, which only fits a few packets
+        set_sockopt_int(&reader, libc::SOL_SOCKET, libc::SO_RCVBUF, 1).unwrap();
+
+        let data = [0; PACKET_DATA_SIZE];
+        for _ in 0..NUM_FLOOD_MSGS {
+            sender.send_to(&data[..], addr).unwrap();
+        }
+
+        // Drain the packets that fit.  They were queued before the drops, so may not report them.
+        reader.set_nonblocking(true).unwrap();
+        let mut drop_count = 0;
+        let mut num_received = 0;
+        let mut num_dropped = 0;
+        loop {
+            let mut packets = vec![Packet::default(); PACKETS_PER_BATCH];
+            match recv_mmsg_rxq_ovfl(&reader, &mut packets, &mut drop_count) {
+                Ok((nrecv, dropped)) => {
+                    num_received += nrecv;
+                    num_dropped += dropped;
+                }
+                Err(err) => {
+                    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+                    break;
+                }
+            }
+        }
+        assert!(num_received < NUM_FLOOD_MSGS);
+
+        // A packet queued after the drops reports them
+        reader.set_nonblocking(false).unwrap();
+        sender.send_to(&data[..], addr).unwrap();
+        let mut packets = vec![Packet::default(); PACKETS_PER_BATCH];
+        let (nrecv, dropped) = recv_mmsg_rxq_ovfl(&reader, &mut packets, &mut drop_count).unwrap();
+        assert_eq!(nrecv, 1);
+        num_dropped += dropped;
+        assert_ne!(num_dropped, 0);
+        assert_eq!(num_dropped, drop_count);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_gro_info() {
+        const SEGMENT_SIZE: usize = 100;
+        let (reader, addr, sender, _) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        if let Err(err) = set_udp_gro(&reader) {
+            warn!("Skipping test, UDP_GRO is not supported: {err}");
+            return;
+        }
+        sender.connect(addr).unwrap();
+
+        // A segmented send is delivered over loopback as a single, coalesced packet
+        let data = [7; 2 * SEGMENT_SIZE];
+        if let Err(err) = send_segmented(&sender, &data, SEGMENT_SIZE as u16) {
+            warn!("Skipping test, UDP_SEGMENT is not supported: {err}");
+            return;
+        }
+        sender.send(&data[..SEGMENT_SIZE]).unwrap();
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let mut gro_info = [GroInfo::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg_gro_info(&reader, &mut packets[..], &mut gro_info).unwrap();
+        assert_eq!(recv, 2);
+
+        assert_eq!(packets[0].meta().size, 2 * SEGMENT_SIZE);
+        assert!(gro_info[0].coalesced());
+        assert_eq!(
+            gro_info[0],
+            GroInfo {
+                segment_size: SEGMENT_SIZE as u16,
+                num_segments: 2,
+            }
+        );
+
+        assert_eq!(packets[1].meta().size, SEGMENT_SIZE);
+        assert!(!gro_info[1].coalesced());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_with_control_all_options() {
+        const SEGMENT_SIZE: usize = 100;
+        for ip in [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ] {
+            let Ok((reader, addr, sender, _)) = test_setup_reader_sender(ip) else {
+                // IPv6 may be disabled
+                continue;
+            };
+            set_rxq_ovfl(&reader).unwrap();
+            set_rx_timestamping(&reader).unwrap();
+            set_pktinfo(&reader).unwrap();
+            // Coalesced packets carry a UDP_GRO control message as well, when supported
+            let gro = set_udp_gro(&reader).is_ok();
+            sender.connect(addr).unwrap();
+            let data = [7; 2 * SEGMENT_SIZE];
+            if !gro || send_segmented(&sender, &data, SEGMENT_SIZE as u16).is_err() {
+                sender.send(&data[..SEGMENT_SIZE]).unwrap();
+            }
+
+            let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+            let mut num_controls = 0;
+            let recv =
+                recv_mmsg_with_control(&reader, &mut packets[..], |_index, _size, msg_hdr| {
+                    assert_eq!(msg_hdr.msg_flags & libc::MSG_CTRUNC, 0);
+                    // SAFETY: recvmmsg() populated the control messages, and updated msg_controllen
+                    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg_hdr) };
+                    while !cmsg.is_null() {
+                        num_controls += 1;
+                        cmsg = unsafe { libc::CMSG_NXTHDR(msg_hdr, cmsg) };
+                    }
+                })
+                .unwrap();
+            assert_eq!(recv, 1);
+            // At least the timestamp and the packet info
+            assert!(num_controls >= 2);
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_with_cpu() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        for _ in 0..TEST_NUM_MSGS {
+            let data = [0; PACKET_DATA_SIZE];
+            sender.send_to(&data[..], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let (recv, incoming_cpu) = recv_mmsg_with_cpu(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        for packet in packets.iter().take(recv) {
+            assert_eq!(packet.meta().socket_addr(), saddr);
+        }
+        if cfg!(target_os = "linux") {
+            assert!(incoming_cpu.is_some());
+        } else {
+            assert!(incoming_cpu.is_none());
+        }
+    }
+
+    #[test]
+    pub fn test_try_recv_mmsg_up_to_partial() {
+        let from = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1234);
+
+        // The first receive succeeds, and the second fails
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let result = try_recv_mmsg_up_to_with(&mut packets, TEST_NUM_MSGS, |i, buffer| match i {
+            0 => {
+                buffer[..3].copy_from_slice(&[1, 2, 3]);
+                Ok((3, from))
+            }
+            _ => Err(io::Error::from(io::ErrorKind::ConnectionRefused)),
+        });
+        assert_eq!(result.packets_received, 1);
+        assert_eq!(
+            result.error.unwrap().kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+        assert_eq!(packets[0].data(..), Some(&[1, 2, 3][..]));
+        assert_eq!(packets[0].meta().socket_addr(), from);
+        assert_eq!(packets[1].meta().size, 0);
+
+        // Running out of packets after the first is not an error
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let result = try_recv_mmsg_up_to_with(&mut packets, TEST_NUM_MSGS, |i, _buffer| match i {
+            0 => Ok((0, from)),
+            _ => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+        });
+        assert_eq!(result.packets_received, 1);
+        assert!(result.error.is_none());
+
+        // An error before any packet is received is reported with no packets
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let result = try_recv_mmsg_up_to_with(&mut packets, TEST_NUM_MSGS, |_i, _buffer| {
+            Err(io::Error::from(io::ErrorKind::WouldBlock))
+        });
+        assert_eq!(result.packets_received, 0);
+        assert_eq!(result.error.unwrap().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    pub fn test_try_recv_mmsg() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        for _ in 0..TEST_NUM_MSGS {
+            let data = [0; PACKET_DATA_SIZE];
+            sender.send_to(&data[..], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let result = try_recv_mmsg(&reader, &mut packets[..]);
+        assert!(result.error.is_none());
+        assert_eq!(result.packets_received, TEST_NUM_MSGS);
+        for packet in packets.iter().take(result.packets_received) {
+            assert_eq!(packet.meta().socket_addr(), saddr);
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_connected() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        assert_eq!(
+            recv_mmsg_connected(&reader, &mut packets[..])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotConnected,
+        );
+
+        reader.connect(saddr).unwrap();
+        for i in 0..TEST_NUM_MSGS {
+            let data = [i as u8; PACKET_DATA_SIZE];
+            sender.send_to(&data[..], addr).unwrap();
+        }
+        let recv = recv_mmsg_connected(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        for (i, packet) in packets.iter().take(recv).enumerate() {
+            assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
+            assert_eq!(packet.meta().socket_addr(), saddr);
+            assert_eq!(packet.data(..), Some(&[i as u8; PACKET_DATA_SIZE][..]));
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_borrowed() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        sender.send_to(&[1; 10], addr).unwrap();
+        sender.send_to(&[2; PACKET_DATA_SIZE], addr).unwrap();
+
+        let mut scratch = [0; PACKET_DATA_SIZE * PACKETS_PER_BATCH];
+        let received = recv_mmsg_borrowed(&reader, &mut scratch).unwrap();
+        assert_eq!(
+            received,
+            vec![(&[1; 10][..], saddr), (&[2; PACKET_DATA_SIZE][..], saddr),],
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_set_recv_buffer_size() {
+        let reader = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        // Well below the default net.core.rmem_max, so the kernel grants all of it
+        const REQUESTED_SIZE: usize = 64 * 1024;
+        let granted = set_recv_buffer_size(&reader, REQUESTED_SIZE).unwrap();
+        assert!(granted >= REQUESTED_SIZE, "granted: {granted}");
+
+        // Larger requests may be capped, but never shrink the buffer below a smaller request
+        let granted_recommended =
+            set_recv_buffer_size(&reader, recommended_recv_buffer_for_batch()).unwrap();
+        assert!(granted_recommended >= granted);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_with_flags() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+
+        // Nothing is queued, so a non-blocking receive returns at once
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let start = Instant::now();
+        let recv = recv_mmsg_with_flags(&reader, &mut packets[..], libc::MSG_DONTWAIT).unwrap();
+        assert_eq!(recv, 0);
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        sender.send_to(&[0; 10], addr).unwrap();
+        sender.send_to(&[1; 10], addr).unwrap();
+        let flags = libc::MSG_WAITFORONE | libc::MSG_CMSG_CLOEXEC;
+        let recv = recv_mmsg_with_flags(&reader, &mut packets[..], flags).unwrap();
+        assert_eq!(recv, 2);
+        for (i, packet) in packets.iter().enumerate().take(recv) {
+            assert_eq!(packet.meta().socket_addr(), saddr);
+            assert_eq!(packet.data(..), Some(&[i as u8; 10][..]));
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_nonblocking_empty() {
+        let (reader, ..) = test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        reader.set_nonblocking(true).unwrap();
+
+        // Callers poll on WouldBlock, so an empty socket must not look like an empty receive
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let err = recv_mmsg(&reader, &mut packets[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_with_waitforone_fallback() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let unsupported = AtomicBool::new(false);
+        let mut calls = Vec::new();
+        // Simulate a runtime that rejects MSG_WAITFORONE
+        let mut recv = |packets: &mut [Packet], flags| {
+            calls.push(flags);
+            if flags & libc::MSG_WAITFORONE != 0 {
+                return Err(io::Error::from_raw_os_error(libc::EINVAL));
+            }
+            recv_mmsg_with_flags(&reader, packets, flags)
+        };
+
+        // Without the flag the whole batch is waited for, so fill it
+        for i in 0..TEST_NUM_MSGS {
+            sender.send_to(&[i as u8; 10], addr).unwrap();
+        }
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv_count =
+            recv_with_waitforone_fallback(&unsupported, |flags| recv(&mut packets, flags)).unwrap();
+        assert_eq!(recv_count, TEST_NUM_MSGS);
+        for (i, packet) in packets.iter().enumerate() {
+            assert_eq!(packet.meta().socket_addr(), saddr);
+            assert_eq!(packet.data(..), Some(&[i as u8; 10][..]));
+        }
+        assert!(unsupported.load(Ordering::Relaxed));
+
+        // Once found to be unsupported, the flag is not tried again
+        sender.send_to(&[0; 10], addr).unwrap();
+        let mut packets = vec![Packet::default(); 1];
+        let recv_count =
+            recv_with_waitforone_fallback(&unsupported, |flags| recv(&mut packets, flags)).unwrap();
+        assert_eq!(recv_count, 1);
+        assert_eq!(calls, vec![libc::MSG_WAITFORONE, 0, 0]);
+
+        // An EINVAL that is not down to the flag is returned as is, and not cached
+        let unsupported = AtomicBool::new(false);
+        let err = recv_with_waitforone_fallback(&unsupported, |_flags| {
+            Err(io::Error::from_raw_os_error(libc::EINVAL))
+        })
+        .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        assert!(!unsupported.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_peek_socket() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        sender.send_to(&[1; 10], addr).unwrap();
+        sender.send_to(&[2; PACKET_DATA_SIZE], addr).unwrap();
+
+        // Peeking leaves the first packet queued, so peeking again gets it again
+        for _ in 0..2 {
+            let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+            let recv = recv_mmsg_peek_socket(&reader, &mut packets[..]).unwrap();
+            assert_eq!(recv, 1);
+            assert_eq!(packets[0].meta().size, 10);
+            assert_eq!(packets[0].meta().socket_addr(), saddr);
+            assert_eq!(packets[0].data(..), Some(&[1; 10][..]));
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, 2);
+        assert_eq!(packets[0].meta().size, 10);
+        assert_eq!(packets[0].meta().socket_addr(), saddr);
+        assert_eq!(packets[0].data(..), Some(&[1; 10][..]));
+        assert_eq!(packets[1].data(..), Some(&[2; PACKET_DATA_SIZE][..]));
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_sharded() {
+        const NUM_SHARDS: u8 = 4;
+        let reader = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = reader.local_addr().unwrap();
+        let senders: Vec<_> = (0..2)
+            .map(|_| UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap())
+            .collect();
+
+        for _ in 0..TEST_NUM_MSGS / 2 {
+            for sender in &senders {
+                sender.send_to(&[0; 10], addr).unwrap();
+            }
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let (recv, shards) = recv_mmsg_sharded(&reader, &mut packets[..], NUM_SHARDS).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        assert_eq!(shards.len(), recv);
+
+        let mut sender_shards = HashMap::new();
+        for (packet, shard) in packets.iter().zip(&shards) {
+            assert!(*shard < NUM_SHARDS);
+            let shard_for_sender = sender_shards
+                .entry(packet.meta().socket_addr())
+                .or_insert(*shard);
+            assert_eq!(*shard_for_sender, *shard);
+        }
+        assert_eq!(sender_shards.len(), senders.len());
+        for sender in &senders {
+            let sender_addr = sender.local_addr().unwrap();
+            assert_eq!(
+                sender_shards[&sender_addr],
+                shard_for_addr(&sender_addr, NUM_SHARDS),
+            );
+        }
+
+        assert_eq!(
+            recv_mmsg_sharded(&reader, &mut packets[..], 0)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput,
+        );
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_capped() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        for i in 0..10u8 {
+            sender.send_to(&[i; 10], addr).unwrap();
+        }
+
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg_capped(&reader, &mut packets[..], 4).unwrap();
+        assert_eq!(recv, 4);
+        for (i, packet) in packets.iter().enumerate().take(recv) {
+            assert_eq!(packet.meta().socket_addr(), saddr);
+            assert_eq!(packet.data(..), Some(&[i as u8; 10][..]));
+        }
+        assert!(packets[recv..]
+            .iter()
+            .all(|packet| packet.meta() == &Meta::default()));
+
+        // The rest were left queued for the next call
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, 6);
+        for (i, packet) in packets.iter().enumerate().take(recv) {
+            assert_eq!(packet.data(..), Some(&[i as u8 + 4; 10][..]));
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_timed() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        for i in 0..3u8 {
+            sender.send_to(&[i; 10], addr).unwrap();
+        }
+
+        // The packets are already queued, so the receive does not block
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let (recv, recv_duration) = recv_mmsg_timed(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, 3);
+        assert!(recv_duration < Duration::from_millis(500));
+        for (i, packet) in packets.iter().enumerate().take(recv) {
+            assert_eq!(packet.meta().socket_addr(), saddr);
+            assert_eq!(packet.data(..), Some(&[i as u8; 10][..]));
+        }
+    }
+
+    #[test]
+    pub fn test_recv_one() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        sender.send_to(&[7; 42], addr).unwrap();
+
+        let packet = recv_one(&reader).unwrap().unwrap();
+        assert_eq!(packet.meta().size, 42);
+        assert_eq!(packet.meta().socket_addr(), saddr);
+        assert_eq!(packet.data(..), Some(&[7; 42][..]));
+
+        // Nothing else is queued, so the receive times out
+        reader
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        assert!(recv_one(&reader).unwrap().is_none());
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_sequenced() {
+        let (reader, addr, sender, _saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let seq = AtomicU64::new(0);
+
+        let mut prev_recv_seqs: Option<Range<u64>> = None;
+        for sent in [TEST_NUM_MSGS, TEST_NUM_MSGS / 2] {
+            for _ in 0..sent {
+                let data = [0; PACKET_DATA_SIZE];
+                sender.send_to(&data[..], addr).unwrap();
+            }
+            let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+            let recv_seqs = recv_mmsg_sequenced(&reader, &mut packets[..], &seq).unwrap();
+            assert_eq!(recv_seqs.clone().count(), sent);
+            // Sequence numbers continue from the previous batch
+            let expected_start = prev_recv_seqs.map_or(0, |prev_recv_seqs| prev_recv_seqs.end);
+            assert_eq!(recv_seqs.start, expected_start);
+            prev_recv_seqs = Some(recv_seqs);
+        }
+        assert_eq!(seq.load(Ordering::Relaxed), (TEST_NUM_MSGS * 3 / 2) as u64);
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_batch() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let send = |num_msgs| {
+            for _ in 0..num_msgs {
+                let data = [0; PACKET_DATA_SIZE];
+                sender.send_to(&data[..], addr).unwrap();
+            }
+        };
+
+        let mut batch = PacketBatch::from(PinnedPacketBatch::default());
+        send(TEST_NUM_MSGS);
+        let recv = recv_mmsg_batch(&reader, &mut batch).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        assert_eq!(batch.len(), recv);
+        for packet in batch.iter() {
+            assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
+            assert_eq!(packet.meta().socket_addr(), saddr);
+        }
+
+        // Received packets are appended, up to PACKETS_PER_BATCH
+        send(PACKETS_PER_BATCH);
+        let recv = recv_mmsg_batch(&reader, &mut batch).unwrap();
+        assert_eq!(recv, PACKETS_PER_BATCH - TEST_NUM_MSGS);
+        assert_eq!(batch.len(), PACKETS_PER_BATCH);
+
+        // A full batch receives nothing
+        assert_eq!(recv_mmsg_batch(&reader, &mut batch).unwrap(), 0);
+        assert_eq!(batch.len(), PACKETS_PER_BATCH);
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_uninit() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let send = |sender: &UdpSocket| {
+            for i in 0..TEST_NUM_MSGS {
+                let data = [i as u8; PACKET_DATA_SIZE];
+                sender.send_to(&data[..=i], addr).unwrap();
+            }
+        };
+
+        send(&sender);
+        let mut uninit_packets = Vec::with_capacity(TEST_NUM_MSGS);
+        let recv = recv_mmsg_uninit(&reader, uninit_packets.spare_capacity_mut()).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+        // SAFETY: recv_mmsg_uninit() initialized the first `recv` packets
+        unsafe { uninit_packets.set_len(recv) };
+
+        send(&sender);
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+
+        for (i, (uninit_packet, packet)) in uninit_packets.iter().zip(&packets).enumerate() {
+            assert_eq!(uninit_packet.meta().size, i + 1);
+            assert_eq!(uninit_packet.meta().socket_addr(), saddr);
+            assert_eq!(uninit_packet.meta(), packet.meta());
+            assert_eq!(uninit_packet.data(..), packet.data(..));
+        }
+    }
+
+    #[test]
+    pub fn test_recv_mmsg_pooled() {
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let pool = PacketBatchPool::new(TEST_NUM_MSGS);
+        assert_eq!(pool.num_available(), 0);
+
+        for i in 0..3 {
+            let sent = TEST_NUM_MSGS - i;
+            for _ in 0..sent {
+                let data = [i as u8; PACKET_DATA_SIZE];
+                sender.send_to(&data[..], addr).unwrap();
+            }
+
+            let batch = recv_mmsg_pooled(&reader, &pool).unwrap();
+            // The one buffer is checked out, and reused on every iteration
+            assert_eq!(pool.num_available(), 0);
+            assert_eq!(batch.len(), sent);
+            for packet in batch.iter() {
+                assert_eq!(packet.meta().size, PACKET_DATA_SIZE);
+                assert_eq!(packet.meta().socket_addr(), saddr);
+                assert_eq!(packet.data(0), Some(&(i as u8)));
+            }
+            drop(batch);
+            assert_eq!(pool.num_available(), 1);
+        }
+
+        // Recycled buffers come back whole, with their meta cleared
+        let batch = pool.checkout();
+        assert_eq!(batch.packets.len(), TEST_NUM_MSGS);
+        assert!(batch
+            .packets
+            .iter()
+            .all(|pkt| pkt.meta() == &Meta::default()));
+        drop(batch);
+
+        // Failed receives recycle the buffer too
+        reader.set_nonblocking(true).unwrap();
+        assert!(recv_mmsg_pooled(&reader, &pool).is_err());
+        assert_eq!(pool.num_available(), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    pub fn test_recv_mmsg_errqueue() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let port_range = localhost_port_range_for_tests();
+        let sender = bind_in_range_with_config(ip, port_range, SocketConfig::default())
+            .unwrap()
+            .1;
+        // Bind, and then drop, a socket to find a local port that nothing is listening on
+        let unbound_addr = bind_in_range_with_config(ip, port_range, SocketConfig::default())
+            .unwrap()
+            .1
+            .local_addr()
+            .unwrap();
+
+        // Nothing is queued before enabling errors
+        let mut errors = Vec::new();
+        assert_eq!(recv_mmsg_errqueue(&sender, &mut errors).unwrap(), 0);
+
+        set_recv_err(&sender).unwrap();
+        sender.send_to(&[0; 10], unbound_addr).unwrap();
+
+        // The ICMP error arrives asynchronously
+        let start = Instant::now();
+        while errors.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            if recv_mmsg_errqueue(&sender, &mut errors).unwrap() == 0 {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        assert_eq!(errors.len(), 1);
+        let (addr, err) = &errors[0];
+        assert_eq!(addr.ip(), ip);
+        assert_eq!(err.raw_os_error(), Some(libc::ECONNREFUSED));
+
+        // The error queue has been drained
+        assert_eq!(recv_mmsg_errqueue(&sender, &mut errors).unwrap(), 0);
+        assert_eq!(errors.len(), 1);
+    }
+
+    /// Ensure the probed recvmmsg(7) support is stable, and that the recv_from() fallback receives
+    /// the same packets as recv_mmsg() without changing the socket's blocking mode
+    #[test]
+    pub fn test_recvmmsg_supported_fallback() {
+        let supported = recvmmsg_supported();
+        assert_eq!(recvmmsg_supported(), supported);
+
+        let (reader, addr, sender, saddr) =
+            test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let send_all = || {
+            for i in 0..TEST_NUM_MSGS {
+                sender.send_to(&vec![i as u8; i + 1], addr).unwrap();
+            }
+        };
+
+        send_all();
+        let mut packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+        assert_eq!(recv, TEST_NUM_MSGS);
+
+        send_all();
+        let mut fallback_packets = vec![Packet::default(); TEST_NUM_MSGS];
+        let fallback_recv =
+            recv_mmsg_up_to(&reader, &mut fallback_packets[..], PACKETS_PER_BATCH).unwrap();
+        assert_eq!(fallback_recv, recv);
+        // The fallback only reads without blocking, the socket stays blocking
+        // SAFETY: `reader` is an open socket for the duration of the call
+        let flags = unsafe {
+            libc::fcntl(
+                std::os::unix::io::AsRawFd::as_raw_fd(&reader),
+                libc::F_GETFL,
+            )
+        };
+        assert_ne!(flags, -1);
+        assert_eq!(flags & libc::O_NONBLOCK, 0);
+
+        for (packet, fallback_packet) in packets.iter().zip(&fallback_packets) {
+            assert_eq!(packet.meta(), fallback_packet.meta());
+            assert_eq!(packet.meta().socket_addr(), saddr);
+            assert_eq!(packet.data(..), fallback_packet.data(..));
+        }
+    }
+
+    /// Ensure the recvmmsg(7) path and the recv_from() fallback path report identical source
+    /// addresses, i.e. both the IP and the port survive the byte-order conversions
+    #[test]
+    pub fn test_recv_mmsg_socket_addr_matches_fallback() {
+        let test_socket_addr = |(reader, addr, sender, saddr): TestConfig| {
+            // The sender is bound to a known, non-zero, ephemeral port
+            assert_ne!(saddr.port(), 0);
+            let data = [0; PACKET_DATA_SIZE];
+
+            sender.send_to(&data[..], addr).unwrap();
+            let mut packets = vec![Packet::default(); 1];
+            let recv = recv_mmsg(&reader, &mut packets[..]).unwrap();
+            assert_eq!(recv, 1);
+            let recv_mmsg_addr = packets[0].meta().socket_addr();
+
+            sender.send_to(&data[..], addr).unwrap();
+            let mut packets = vec![Packet::default(); 1];
+            let recv = recv_mmsg_up_to(&reader, &mut packets[..], 1).unwrap();
+            assert_eq!(recv, 1);
+            let fallback_addr = packets[0].meta().socket_addr();
+
+            assert_eq!(recv_mmsg_addr, saddr);
+            assert_eq!(fallback_addr, saddr);
+            assert_eq!(recv_mmsg_addr.ip(), fallback_addr.ip());
+            assert_eq!(recv_mmsg_addr.port(), fallback_addr.port());
+        };
+
+        test_socket_addr(test_setup_reader_sender(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap());
+
+        match test_setup_reader_sender(IpAddr::V6(Ipv6Addr::LOCALHOST)) {
+            Ok(config) => test_socket_addr(config),
+            Err(e) => warn!("Failed to configure IPv6: {:?}", e),
+        }
     }
 }
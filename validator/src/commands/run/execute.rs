@@ -930,6 +930,7 @@ pub fn execute(
         maximum_full_snapshot_archives_to_retain,
         maximum_incremental_snapshot_archives_to_retain,
         packager_thread_niceness_adj: snapshot_packager_niceness_adj,
+        ..SnapshotConfig::default()
     };
 
     info!(